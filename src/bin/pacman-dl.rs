@@ -1,18 +1,25 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::future::Future;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
-use pacman_repo_tools::db::{read_db_dir, DatabasePackage};
+use pacman_repo_tools::config::{host_architecture, PacmanConfig, SigLevel, SignatureRequirement, DEFAULT_GPG_DIR};
+use pacman_repo_tools::db::{add_to_db_archive, read_db_archive, write_db_archive, DatabasePackage};
 use pacman_repo_tools::msg::{use_color, Paint};
-use pacman_repo_tools::parse::rpartition;
-use pacman_repo_tools::{error, msg, plain, plain_no_eol, warning};
+use pacman_repo_tools::package::{Dependency, VersionRange};
+use pacman_repo_tools::parse::partition;
+use pacman_repo_tools::signature::{Keyring, SignatureError};
+use pacman_repo_tools::{error, msg, plain, warning};
 
 /// Download packages from a number of pacman repositories.
 ///
 /// The order of repositories is significant in case multiple repositories have a package with the same name.
 /// In that case, repositories mentioned earlier will be used.
-/// Repositories mentioned with `--db-url` are always consulted before those read from a file.
+/// Repositories mentioned with `--db-url` are always consulted before those read from a `--db-file`,
+/// and those are consulted before the repositories from a `--config` file.
 #[derive(StructOpt)]
 #[structopt(name = env!("CARGO_BIN_NAME"))]
 #[structopt(setting = AppSettings::ColoredHelp)]
@@ -29,31 +36,77 @@ struct Options {
 	#[structopt(value_name = "PATH")]
 	pkg_file: Vec<PathBuf>,
 
-	/// A repository to download packages from (specify the URL for the database archive).
+	/// A repository to download packages from.
+	///
+	/// Specify either the URL of the database archive, or a repository name and server URL as `NAME=SERVER`.
+	/// In the server URL, `$repo` is replaced by the repository name and `$arch` by the architecture.
+	/// The database is downloaded from `$server/$repo.db` and packages from `$server/$filename`.
+	///
+	/// Specify the same repository multiple times to add mirrors.
+	/// Mirrors are tried in order, until one of them succeeds.
 	#[structopt(long)]
-	#[structopt(value_name = "URL.db")]
+	#[structopt(value_name = "URL.db|NAME=SERVER")]
 	db_url: Vec<String>,
 
-	/// Read repository database URLs from a file, one database URL per line.
+	/// Read repositories from a file, one repository per line in the same format as `--db-url`.
 	#[structopt(long, short)]
 	#[structopt(value_name = "PATH")]
 	db_file: Vec<PathBuf>,
 
+	/// The signature level for repositories given with `--db-url` and `--db-file`.
+	///
+	/// This uses the same syntax as `SigLevel` in pacman.conf, for example `Required TrustedOnly`.
+	/// Only package signatures are verified, database signatures are ignored.
+	/// Repositories from a `--config` file use the `SigLevel` from the file.
+	#[structopt(long)]
+	#[structopt(value_name = "LEVEL")]
+	#[structopt(default_value = "Never")]
+	sig_level: SigLevel,
+
+	/// The GnuPG home directory with the keyring used to verify package signatures.
+	///
+	/// Defaults to the `GPGDir` from the `--config` file, or /etc/pacman.d/gnupg.
+	#[structopt(long)]
+	#[structopt(value_name = "DIRECTORY")]
+	keyring: Option<PathBuf>,
+
+	/// The architecture to substitute for `$arch` in server URLs given with `--db-url` and `--db-file`.
+	///
+	/// Defaults to the architecture of the host.
+	#[structopt(long)]
+	#[structopt(value_name = "ARCH")]
+	arch: Option<String>,
+
+	/// Read repositories from a pacman.conf file.
+	///
+	/// Each server of a repository is added as a mirror, with the database URL `$server/$repo.db`.
+	#[structopt(long)]
+	#[structopt(value_name = "PATH")]
+	config: Option<PathBuf>,
+
 	/// Save downloaded packages to this directory.
 	#[structopt(long, short = "o")]
 	#[structopt(value_name = "DIRECTORY")]
 	#[structopt(default_value = "packages")]
 	pkg_dir: PathBuf,
 
-	/// Extract repository databases to this directory.
+	/// Move downloaded packages that do not match the database to this directory.
+	///
+	/// The packages are stored in a subdirectory for each mirror, so the bad downloads can be inspected.
+	/// Defaults to the `quarantine` directory inside the `--pkg-dir`.
+	#[structopt(long)]
+	#[structopt(value_name = "DIRECTORY")]
+	quarantine_dir: Option<PathBuf>,
+
+	/// Save repository databases to this directory.
 	#[structopt(long)]
 	#[structopt(value_name = "DIRECTORY")]
 	#[structopt(default_value = "db")]
 	db_dir: PathBuf,
 
-	/// Add the downloaded packages to a database.
+	/// Add the downloaded packages to a database (the file name must end in .db.tar.gz, .db.tar.xz, .db.tar.zst or .db.tar).
 	#[structopt(long)]
-	#[structopt(value_name = "NAME")]
+	#[structopt(value_name = "PATH")]
 	add_to_db: Option<PathBuf>,
 
 	/// Delete the database before adding packages to it.
@@ -64,6 +117,22 @@ struct Options {
 	/// Do not automatically download dependencies.
 	#[structopt(long)]
 	no_deps: bool,
+
+	/// The maximum number of concurrent downloads.
+	#[structopt(long)]
+	#[structopt(value_name = "N")]
+	#[structopt(default_value = "1")]
+	parallel: NonZeroUsize,
+
+	/// The maximum number of concurrent downloads from a single host.
+	#[structopt(long)]
+	#[structopt(value_name = "N")]
+	#[structopt(default_value = "3")]
+	parallel_per_host: NonZeroUsize,
+
+	/// Continue with the remaining downloads if a download fails, instead of stopping immediately.
+	#[structopt(long)]
+	keep_going: bool,
 }
 
 fn main() {
@@ -89,45 +158,70 @@ fn main() {
 async fn do_main(options: Options) -> Result<(), ()> {
 	let targets = read_files_to_vec(options.pkg, &options.pkg_file)?;
 	let databases = read_files_to_vec(options.db_url, &options.db_file)?;
+	let arch = options.arch.as_deref().unwrap_or(host_architecture());
+	let mut repositories = Repository::parse_specs(&databases, arch, options.sig_level)?;
+	let config = match &options.config {
+		Some(path) => {
+			let config = PacmanConfig::read(path).map_err(|e| error!("{}.", e))?;
+			for repository in config_repositories(&config, path)? {
+				repository.merge_into(&mut repositories);
+			}
+			Some(config)
+		},
+		None => None,
+	};
+	let keyring_dir = options
+		.keyring
+		.or_else(|| config.map(|config| config.gpg_dir))
+		.unwrap_or_else(|| DEFAULT_GPG_DIR.into());
 
 	if targets.is_empty() {
 		error!("Need atleast one package to download.");
 		return Err(());
 	}
 
-	if databases.is_empty() {
+	if repositories.is_empty() {
 		error!("Need atleast one repository database.");
 		return Err(());
 	}
 
-	let repositories = Repository::parse_urls(&databases)?;
+	let quarantine_dir = match options.quarantine_dir {
+		Some(dir) => dir,
+		None => options.pkg_dir.join("quarantine"),
+	};
 
 	let http_client = reqwest::Client::new();
+	let download_options = DownloadOptions {
+		parallel: options.parallel.get(),
+		keep_going: options.keep_going,
+		host_limiter: HostLimiter::new(options.parallel_per_host.get()),
+		quarantine_dir,
+		keyring: Keyring::new(keyring_dir),
+	};
 
 	msg!("Syncing repository databases");
-	let packages = sync_dbs(&http_client, &options.db_dir, &repositories).await?;
+	let packages = sync_dbs(&http_client, &download_options, &options.db_dir, &repositories).await?;
 	let packages = index_packages_by_name(&packages);
 
 	let selected_packages = if options.no_deps {
-		targets.iter().map(String::as_str).collect()
+		select_targets(&packages, &targets)?
 	} else {
 		let resolver = DependencyResolver::new(&packages);
 		resolver.resolve(&targets)?
 	};
 
 	msg!("Downloading packages");
-	let downloaded = download_packages(&http_client, &options.pkg_dir, &selected_packages, &packages).await?;
+	let downloaded = download_packages(&http_client, &download_options, &options.pkg_dir, &selected_packages, &packages).await?;
 
 	if let Some(db_path) = options.add_to_db {
 		msg!("Adding packages to {}", Paint::blue(db_path.display()).bold());
 		if options.recreate_db {
 			// If we create a fresh database, add all selected packages.
-			remove_file(&db_path)?;
 			let selected: Vec<_> = selected_packages.iter().map(|name| *packages.get(name).unwrap()).collect();
-			add_to_database(&db_path, &options.pkg_dir, &selected).await?;
+			add_to_database(&db_path, &options.pkg_dir, &selected, true)?;
 		} else {
 			// Otherwise, only add downloaded packages.
-			add_to_database(&db_path, &options.pkg_dir, &downloaded).await?;
+			add_to_database(&db_path, &options.pkg_dir, &downloaded, false)?;
 		}
 	}
 
@@ -143,7 +237,7 @@ fn read_files_to_vec(initial: Vec<String>, paths: &[impl AsRef<Path>]) -> Result
 
 	for path in paths {
 		let path = path.as_ref();
-		let buffer = std::fs::read(&path).map_err(|e| error!("Failed to read {}: {}.", path.display(), e))?;
+		let buffer = std::fs::read(path).map_err(|e| error!("Failed to read {}: {}.", path.display(), e))?;
 		let buffer = String::from_utf8(buffer).map_err(|e| error!("Invalid UTF-8 in {}: {}.", path.display(), e))?;
 
 		result.extend(buffer.lines().filter_map(|line| {
@@ -159,66 +253,193 @@ fn read_files_to_vec(initial: Vec<String>, paths: &[impl AsRef<Path>]) -> Result
 	Ok(result)
 }
 
+/// Get the repositories from a pacman.conf file.
+///
+/// The path of the file is only used for error messages.
+fn config_repositories(config: &PacmanConfig, path: &Path) -> Result<Vec<Repository>, ()> {
+	let mut repositories = Vec::with_capacity(config.repositories.len());
+	for repository in &config.repositories {
+		if repository.servers.is_empty() {
+			error!("Repository {} in {} has no servers.", repository.name, path.display());
+			return Err(());
+		}
+		let mirrors = repository
+			.servers
+			.iter()
+			.map(|server| Mirror::from_server(server, &repository.name))
+			.collect::<Result<_, _>>()?;
+		repositories.push(Repository {
+			name: repository.name.clone(),
+			mirrors,
+			sig_level: repository.sig_level,
+		});
+	}
+	Ok(repositories)
+}
+
 /// Metadata about a repository.
+#[derive(Debug)]
 struct Repository {
 	name: String,
-	db_url: reqwest::Url,
+
+	/// The mirrors of the repository, in order of preference.
+	///
+	/// There is always at least one mirror.
+	mirrors: Vec<Mirror>,
+
+	/// The signature level used to verify packages from the repository.
+	sig_level: SigLevel,
+}
+
+/// A server that hosts a repository.
+#[derive(Debug)]
+struct Mirror {
+	/// The URL of the directory that holds the database archive and the packages.
+	///
+	/// Any query string is kept for all file URLs, so that presigned URLs keep working.
+	server: reqwest::Url,
+
+	/// The file name of the database archive.
+	database: String,
 }
 
 impl Repository {
-	/// Parse a list of repository URLs.
+	/// Parse a list of repositories.
 	///
-	/// If different URLs refer to repositories with the same name,
-	/// an error is returned.
-	fn parse_urls(urls: &[impl AsRef<str>]) -> Result<Vec<Repository>, ()> {
-		let mut names = BTreeSet::new();
-		let mut repositories = Vec::with_capacity(urls.len());
-		for url in urls {
-			let repository: Repository = url.as_ref().parse()?;
-			if !names.insert(repository.name.clone()) {
-				error!("Duplicate repository name: {}.", repository.name);
-				return Err(());
-			}
-			repositories.push(repository);
+	/// Each entry is either a `name=server` pair or the URL of a database archive.
+	/// In a server URL, `$repo` is replaced by the repository name and `$arch` by the given architecture.
+	/// The database URL of a server is `$server/$repo.db`.
+	///
+	/// Entries that refer to repositories with the same name are added as mirrors of the same repository.
+	/// The repositories and mirrors keep the order in which they are given.
+	fn parse_specs(specs: &[impl AsRef<str>], arch: &str, sig_level: SigLevel) -> Result<Vec<Repository>, ()> {
+		let mut repositories: Vec<Repository> = Vec::with_capacity(specs.len());
+		for spec in specs {
+			Self::parse_spec(spec.as_ref(), arch, sig_level)?.merge_into(&mut repositories);
 		}
 
 		Ok(repositories)
 	}
-}
 
-impl std::str::FromStr for Repository {
-	type Err = ();
+	/// Parse a single repository entry, see [`Self::parse_specs`].
+	fn parse_spec(spec: &str, arch: &str, sig_level: SigLevel) -> Result<Self, ()> {
+		// A URL always has a scheme before the first `=`, so a name can not contain a `:` or `/`.
+		match partition(spec, '=') {
+			Some((name, template)) if !name.contains([':', '/']) => {
+				check_repository_name(name, spec)?;
+				let server = template.replace("$repo", name).replace("$arch", arch);
+				Ok(Self {
+					name: name.into(),
+					mirrors: vec![Mirror::from_server(&server, name)?],
+					sig_level,
+				})
+			},
+			_ => {
+				let db_url: reqwest::Url = spec.parse().map_err(|e| error!("Invalid URL: {}: {}.", spec, e))?;
+				let (name, mirror) = Mirror::from_database_url(db_url)?;
+				check_repository_name(&name, spec)?;
+				Ok(Self {
+					name,
+					mirrors: vec![mirror],
+					sig_level,
+				})
+			},
+		}
+	}
+
+	/// Add the repository to a list, or add its mirrors to an existing repository with the same name.
+	///
+	/// The existing repository keeps its own signature level.
+	fn merge_into(self, repositories: &mut Vec<Repository>) {
+		match repositories.iter_mut().find(|x| x.name == self.name) {
+			Some(existing) => existing.mirrors.extend(self.mirrors),
+			None => repositories.push(self),
+		}
+	}
+}
 
-	fn from_str(input: &str) -> Result<Self, Self::Err> {
-		let db_url: reqwest::Url = input.parse().map_err(|e| error!("Invalid URL: {}: {}.", input, e))?;
-		let name = rpartition(db_url.path(), '/').map(|(_, name)| name).unwrap_or_else(|| db_url.path());
-		if name.is_empty() {
-			error!("Can not determine repository name from URL: {}.", input);
+impl Mirror {
+	/// Create a mirror from a server URL, using `$repo.db` as database file name.
+	fn from_server(server: &str, repository: &str) -> Result<Self, ()> {
+		let server: reqwest::Url = server.parse().map_err(|e| error!("Invalid URL: {}: {}.", server, e))?;
+		if server.cannot_be_a_base() {
+			error!("Invalid server URL: {}.", server);
 			return Err(());
 		}
-		Ok(Self { name: name.into(), db_url })
+		Ok(Self {
+			server,
+			database: format!("{}.db", repository),
+		})
+	}
+
+	/// Create a mirror from the URL of a database archive.
+	///
+	/// The repository name is the file name, without the `.db` or `.db.tar.*` extension.
+	fn from_database_url(mut db_url: reqwest::Url) -> Result<(String, Self), ()> {
+		let database = match db_url.path_segments().and_then(|mut x| x.next_back()) {
+			Some(x) if !x.is_empty() => x.to_owned(),
+			_ => {
+				error!("Can not determine repository name from URL: {}.", db_url);
+				return Err(());
+			},
+		};
+		db_url.path_segments_mut().unwrap().pop();
+
+		let name = match database.rfind(".db") {
+			Some(i) if database[i + 3..].is_empty() || database[i + 3..].starts_with(".tar") => &database[..i],
+			_ => database.as_str(),
+		};
+		let name = name.to_owned();
+		Ok((name, Self { server: db_url, database }))
+	}
+
+	/// Get the URL of the database archive.
+	fn database_url(&self) -> reqwest::Url {
+		self.file_url(&self.database)
+	}
+
+	/// Get the URL of a package file.
+	fn package_url(&self, package: &DatabasePackage) -> reqwest::Url {
+		self.file_url(&package.filename)
+	}
+
+	/// Get the URL of a file in the server directory.
+	fn file_url(&self, file_name: &str) -> reqwest::Url {
+		let mut url = self.server.clone();
+		// Mirrors are never created with cannot-be-a-base URLs.
+		url.path_segments_mut().unwrap().pop_if_empty().push(file_name);
+		url
+	}
+}
+
+/// Check that a repository name can be used as directory name.
+fn check_repository_name(name: &str, spec: &str) -> Result<(), ()> {
+	if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+		error!("Invalid repository name in {}: {:?}.", spec, name);
+		return Err(());
 	}
+	Ok(())
 }
 
-/// Download and extract the given database files specified by the URLs to the given directory.
+/// Download the given database files specified by the URLs to the given directory and parse them.
 async fn sync_dbs<'a>(
 	http_client: &reqwest::Client,
+	options: &DownloadOptions,
 	directory: impl AsRef<Path>,
 	repositories: &'a [Repository],
 ) -> Result<Vec<(&'a Repository, Vec<DatabasePackage>)>, ()> {
 	let directory = directory.as_ref();
+	let total = repositories.len();
 
-	let mut repo_packages = Vec::new();
-
-	for (i, repo) in repositories.iter().enumerate() {
+	let jobs = repositories.iter().enumerate().map(|(i, repo)| async move {
 		let db_dir = directory.join(&repo.name);
-		download_database(http_client, &db_dir, &repo.db_url, i, repositories.len()).await?;
+		let db_path = download_database(http_client, options, &db_dir, repo, i, total).await?;
+		// Parsing a big database takes a while, so do it on the blocking thread pool to keep the other downloads going.
+		let packages = blocking(move || read_db_archive(&db_path)).await.map_err(|e| error!("{}.", e))?;
+		Ok((repo, packages))
+	});
 
-		let packages = read_db_dir(&db_dir).map_err(|e| error!("{}.", e))?;
-		repo_packages.push((repo, packages));
-	}
-
-	Ok(repo_packages)
+	run_downloads(options, jobs).await
 }
 
 /// Index packages from different repositories by name.
@@ -268,7 +489,7 @@ struct DependencyResolver<'a, 'b> {
 	packages: &'b BTreeMap<&'a str, (&'a Repository, &'a DatabasePackage)>,
 	providers: BTreeMap<&'a str, BTreeSet<&'a str>>,
 	selected_packages: BTreeSet<&'a str>,
-	provided_targets: BTreeSet<&'a str>,
+	provided_targets: BTreeMap<&'a str, Vec<&'a DatabasePackage>>,
 }
 
 impl<'a, 'b> DependencyResolver<'a, 'b> {
@@ -276,50 +497,60 @@ impl<'a, 'b> DependencyResolver<'a, 'b> {
 	pub fn new(packages: &'b BTreeMap<&'a str, (&'a Repository, &'a DatabasePackage)>) -> Self {
 		Self {
 			packages,
-			providers: index_providers(&packages),
+			providers: index_providers(packages),
 			selected_packages: BTreeSet::new(),
-			provided_targets: BTreeSet::new(),
+			provided_targets: BTreeMap::new(),
 		}
 	}
 
 	/// Resolve the targets into a set of packages to download.
 	///
 	/// This will recursively resolve all dependencies and virtual targets.
+	/// Targets may have a version constraint, just like dependencies (for example `mkinitcpio>=0.7`).
 	///
-	/// Dependencies and virtual targets that are already provided by a selected package are skipped.
+	/// Dependencies and virtual targets that are already satisfied by a selected package are skipped.
 	/// Howwever, all real packages given in `targets` will be selected.
+	///
+	/// It is an error to give the same target multiple times with version constraints that can not be satisfied together.
 	pub fn resolve(mut self, targets: &[impl AsRef<str>]) -> Result<BTreeSet<&'a str>, ()> {
-		let mut queue = BTreeSet::new();
+		let mut queue = VecDeque::new();
+
+		let targets = targets
+			.iter()
+			.map(|target| {
+				let target = target.as_ref();
+				target.parse::<Dependency>().map_err(|e| error!("Invalid target: {}: {}.", target, e))
+			})
+			.collect::<Result<Vec<_>, ()>>()?;
+		check_target_constraints(&targets)?;
 
 		for target in targets {
-			let target = target.as_ref();
 			// First add all explicitly listed real packages.
-			if let Some((_repo, package)) = self.packages.get(target) {
-				self.add_package(package);
-				for depend in &package.depends {
-					queue.insert(depend.name.as_str());
-				}
-			// Add virtual targets to the queue to be resolved later.
-			// They may already be provided by an explicitly listed package.
-			} else {
-				queue.insert(target);
+			match self.packages.get(target.name.as_str()) {
+				Some((_repo, package)) if target.is_satisfied_by(package) => {
+					self.add_package(package);
+					queue.extend(package.depends.iter().cloned());
+				},
+				// Add virtual targets to the queue to be resolved later.
+				// They may already be provided by an explicitly listed package.
+				_ => queue.push_back(target),
 			}
 		}
 
 		// Resolve targets in the queue until it is empty.
-		while let Some(target) = pop_first(&mut queue) {
-			// Ignore already-provided targets.
+		while let Some(target) = queue.pop_front() {
+			// Ignore already-satisfied targets.
 			// All explicitly listed packages have already been added,
 			// so these are either virtual targets or dependencies.
-			if self.provided_targets.contains(target) {
+			if self.is_satisfied(&target) {
 				continue;
 			}
 
-			let package = self.resolve_target(target)?;
+			let package = self.resolve_target(&target)?;
 			self.add_package(package);
 			for depend in &package.depends {
-				if !self.provided_targets.contains(depend.name.as_str()) {
-					queue.insert(&depend.name);
+				if !self.is_satisfied(depend) {
+					queue.push_back(depend.clone());
 				}
 			}
 		}
@@ -330,112 +561,365 @@ impl<'a, 'b> DependencyResolver<'a, 'b> {
 	/// Add a package to the selection.
 	fn add_package(&mut self, package: &'a DatabasePackage) {
 		self.selected_packages.insert(&package.name);
-		self.provided_targets.insert(&package.name);
-		let provides = package.provides.iter().map(|x| x.name.as_str());
-		self.provided_targets.extend(provides);
+		self.provided_targets.entry(&package.name).or_default().push(package);
+		for provides in &package.provides {
+			self.provided_targets.entry(&provides.name).or_default().push(package);
+		}
+	}
+
+	/// Check if a dependency is satisfied by one of the selected packages.
+	fn is_satisfied(&self, target: &Dependency) -> bool {
+		self.provided_targets
+			.get(target.name.as_str())
+			.map(|packages| packages.iter().any(|package| target.is_satisfied_by(package)))
+			.unwrap_or(false)
 	}
 
 	/// Choose a package for a target.
 	///
-	/// If the target is a concrete package, choose that.
-	/// Otherwise, choose some implementation defined provider, if it exists.
-	fn resolve_target(&self, target: &str) -> Result<&'a DatabasePackage, ()> {
-		if let Some((_repo, package)) = self.packages.get(target) {
-			Ok(package)
-		} else {
-			let provider = self
-				.providers
-				.get(target)
-				.and_then(|x| x.iter().next())
-				.ok_or_else(|| error!("No provider found for target: {}.", target))?;
-			self.packages
-				.get(provider)
-				.map(|&(_repo, package)| package)
-				.ok_or_else(|| error!("No such package: {}.", provider))
+	/// If the target is a concrete package that satisfies the version constraint, choose that.
+	/// Otherwise, choose some implementation defined provider that satisfies the constraint, if it exists.
+	fn resolve_target(&self, target: &Dependency) -> Result<&'a DatabasePackage, ()> {
+		if let Some((_repo, package)) = self.packages.get(target.name.as_str()) {
+			if target.is_satisfied_by(package) {
+				return Ok(package);
+			}
+		}
+
+		self.providers
+			.get(target.name.as_str())
+			.into_iter()
+			.flatten()
+			.filter_map(|provider| self.packages.get(provider).map(|&(_repo, package)| package))
+			.find(|package| target.is_satisfied_by(package))
+			.ok_or_else(|| error!("No package found that satisfies: {}.", target))
+	}
+}
+
+/// Select the targets as packages to download, without resolving dependencies.
+///
+/// Targets may have a version constraint (for example `mkinitcpio>=0.7`),
+/// but they must name real packages: virtual targets can not be selected without the dependency resolver.
+///
+/// All unknown and unsatisfied targets are reported before returning an error.
+fn select_targets<'a>(
+	packages: &BTreeMap<&'a str, (&'a Repository, &'a DatabasePackage)>,
+	targets: &[impl AsRef<str>],
+) -> Result<BTreeSet<&'a str>, ()> {
+	let mut selected = BTreeSet::new();
+	let mut failed = false;
+	for target in targets {
+		let target = target.as_ref();
+		let target = match target.parse::<Dependency>() {
+			Ok(x) => x,
+			Err(e) => {
+				error!("Invalid target: {}: {}.", target, e);
+				failed = true;
+				continue;
+			},
+		};
+		match packages.get(target.name.as_str()) {
+			Some((_repo, package)) if target.is_satisfied_by(package) => {
+				selected.insert(package.name.as_str());
+			},
+			Some((_repo, package)) => {
+				error!(
+					"Package {} version {} does not satisfy target: {}.",
+					package.name, package.version, target
+				);
+				failed = true;
+			},
+			None => {
+				error!("Unknown package: {}.", target.name);
+				failed = true;
+			},
+		}
+	}
+
+	if failed {
+		Err(())
+	} else {
+		Ok(selected)
+	}
+}
+
+/// Check that the version constraints of targets with the same name can be satisfied together.
+fn check_target_constraints(targets: &[Dependency]) -> Result<(), ()> {
+	let mut ranges = BTreeMap::<&str, VersionRange>::new();
+	for target in targets {
+		let range = ranges.entry(target.name.as_str()).or_insert_with(VersionRange::any);
+		if let Some(constraint) = &target.version {
+			*range = range.intersection(&constraint.into());
+		}
+	}
+
+	for (name, range) in &ranges {
+		if range.is_empty() {
+			let constraints: Vec<_> = targets.iter().filter(|x| x.name == *name).map(|x| x.to_string()).collect();
+			error!("Conflicting version constraints for target {}: {}.", name, constraints.join(", "));
+			return Err(());
+		}
+	}
+	Ok(())
+}
+
+/// Settings for concurrent downloads.
+struct DownloadOptions {
+	/// The maximum number of concurrent downloads.
+	parallel: usize,
+
+	/// Continue with the remaining downloads if a download fails.
+	keep_going: bool,
+
+	/// Limits the number of concurrent downloads per host.
+	host_limiter: HostLimiter,
+
+	/// The directory for downloaded packages that do not match the database.
+	quarantine_dir: PathBuf,
+
+	/// The keyring used to verify package signatures.
+	keyring: Keyring,
+}
+
+/// Limits the number of concurrent downloads per host.
+struct HostLimiter {
+	per_host: usize,
+	hosts: Mutex<BTreeMap<String, Arc<tokio::sync::Semaphore>>>,
+}
+
+impl HostLimiter {
+	/// Create a new limiter that allows `per_host` concurrent downloads from each host.
+	fn new(per_host: usize) -> Self {
+		Self {
+			per_host,
+			hosts: Mutex::new(BTreeMap::new()),
+		}
+	}
+
+	/// Wait until a download from the host of the URL is allowed.
+	///
+	/// The download is allowed for as long as the returned permit is kept alive.
+	async fn acquire(&self, url: &reqwest::Url) -> tokio::sync::OwnedSemaphorePermit {
+		let host = url.host_str().unwrap_or_default().to_owned();
+		let semaphore = self
+			.hosts
+			.lock()
+			.unwrap()
+			.entry(host)
+			.or_insert_with(|| Arc::new(tokio::sync::Semaphore::new(self.per_host)))
+			.clone();
+		// The semaphore is never closed, so acquiring a permit can not fail.
+		semaphore.acquire_owned().await.unwrap()
+	}
+}
+
+/// Run a list of download jobs concurrently.
+///
+/// The jobs are expected to report their own errors.
+/// If a job fails, the remaining jobs are cancelled, unless `keep_going` is set.
+/// With `keep_going`, all jobs are run to completion and the number of failed jobs is reported at the end.
+///
+/// The results are returned in the same order as the jobs.
+async fn run_downloads<T>(options: &DownloadOptions, jobs: impl IntoIterator<Item = impl Future<Output = Result<T, ()>>>) -> Result<Vec<T>, ()> {
+	use futures::stream::StreamExt;
+
+	let jobs = jobs.into_iter().enumerate().map(|(i, job)| async move { (i, job.await) });
+	let mut results = futures::stream::iter(jobs).buffer_unordered(options.parallel);
+
+	let mut output = Vec::new();
+	let mut failed = 0;
+	while let Some((i, result)) = results.next().await {
+		match result {
+			Ok(x) => output.push((i, x)),
+			Err(()) if options.keep_going => failed += 1,
+			Err(()) => return Err(()),
 		}
 	}
+
+	if failed > 0 {
+		error!("{} download(s) failed.", failed);
+		return Err(());
+	}
+
+	output.sort_by_key(|&(i, _)| i);
+	Ok(output.into_iter().map(|(_, x)| x).collect())
 }
 
-/// Pop the first entry from a BTreeSet.
-fn pop_first<T: Copy + Ord>(set: &mut BTreeSet<T>) -> Option<T> {
-	let value = *set.iter().next()?;
-	set.take(&value)
+/// Print the status of a finished download.
+///
+/// The whole line is printed at once, so that concurrent downloads do not mix their output.
+/// If the `mirror` is given, the line reports which mirror served the file.
+fn print_status(index: usize, total: usize, name: impl std::fmt::Display, status: Paint<&str>, mirror: Option<&Mirror>) {
+	let mirror = mirror.map(|mirror| format!(" (from {})", mirror_name(mirror))).unwrap_or_default();
+	plain!(
+		"Downloading [{}/{}] {}... {}{}",
+		Paint::blue(index + 1).bold(),
+		Paint::blue(total).bold(),
+		Paint::cyan(name),
+		status,
+		mirror
+	);
 }
 
-/// Download and extract a database file.
-async fn download_database(http_client: &reqwest::Client, directory: &Path, url: &reqwest::Url, index: usize, total: usize) -> Result<(), ()> {
-	plain_no_eol!("Downloading [{}/{}] {}...", Paint::blue(index + 1).bold(), Paint::blue(total).bold(), Paint::cyan(url));
+/// Get the name of a mirror to show to the user.
+fn mirror_name(mirror: &Mirror) -> String {
+	let url = &mirror.server;
+	match (url.host_str(), url.port()) {
+		(Some(host), Some(port)) => format!("{}:{}", host, port),
+		(Some(host), None) => host.to_owned(),
+		(None, _) => url.to_string(),
+	}
+}
+
+/// Get the name of the quarantine subdirectory for a mirror.
+///
+/// This is the name of the mirror followed by a hash of the server URL (without query string),
+/// so that mirrors on the same host with different paths do not share a directory.
+fn quarantine_name(mirror: &Mirror) -> String {
+	use sha2::Digest;
+	let mut server = mirror.server.clone();
+	server.set_query(None);
+	let hash = format!("{:x}", sha2::Sha256::digest(server.as_str().as_bytes()));
+	format!("{}-{}", mirror_name(mirror), &hash[..12])
+}
+
+/// Download a database file, if it changed since the last download.
+///
+/// The mirrors of the repository are tried in order, until one of them succeeds.
+/// The cache validators of the last download are only sent to the mirror that served it.
+///
+/// Returns the path of the database archive.
+async fn download_database(
+	http_client: &reqwest::Client,
+	options: &DownloadOptions,
+	directory: &Path,
+	repository: &Repository,
+	index: usize,
+	total: usize,
+) -> Result<PathBuf, ()> {
+	let db_path = directory.join("database");
 	let last_modified_path = directory.join("last-modified");
 	let etag_path = directory.join("etag");
-	let last_modified = std::fs::read_to_string(&last_modified_path).ok();
-	let etag = std::fs::read_to_string(&etag_path).ok();
+	let source_path = directory.join("source");
+
+	// Only send cache validators if we still have the database archive itself.
+	let (source, last_modified, etag) = if db_path.is_file() {
+		(
+			tokio::fs::read_to_string(&source_path).await.ok(),
+			tokio::fs::read_to_string(&last_modified_path).await.ok(),
+			tokio::fs::read_to_string(&etag_path).await.ok(),
+		)
+	} else {
+		(None, None, None)
+	};
 
-	let download = maybe_download(http_client, &url, last_modified.as_deref(), etag.as_deref())
-		.await
-		.map_err(|e| {
-			println!(" {}", Paint::red("failed"));
-			error!("{}.", e);
-		})?;
+	make_dirs(directory)?;
+	let mut mirrors = repository.mirrors.iter().peekable();
+	let (mirror, db_url, download) = loop {
+		let mirror = mirrors.next().unwrap();
+		let db_url = mirror.database_url();
+
+		// The validators are only meaningful to the mirror that sent them, other mirrors have their own ETags and timestamps.
+		let (last_modified, etag) = if source.as_deref() == Some(db_url.as_str()) {
+			(last_modified.as_deref(), etag.as_deref())
+		} else {
+			(None, None)
+		};
+
+		let permit = options.host_limiter.acquire(&db_url).await;
+		let result = maybe_download(http_client, &db_url, &db_path, last_modified, etag).await;
+		drop(permit);
+		match result {
+			Ok(download) => break (mirror, db_url, download),
+			Err(e) if e.is_mirror_error() && mirrors.peek().is_some() => {
+				warning!(
+					"Failed to download {} from {}: {}. Trying the next mirror.",
+					repository.name,
+					mirror_name(mirror),
+					e
+				);
+			},
+			Err(e) => {
+				print_status(index, total, &repository.name, Paint::red("failed"), Some(mirror));
+				error!("Failed to download {} from {}: {}.", repository.name, mirror_name(mirror), e);
+				return Err(());
+			},
+		}
+	};
 
 	if let Some(download) = download {
-		println!(" {}", Paint::green("done"));
-		let _: Result<_, _> = std::fs::remove_file(&last_modified_path);
-		let _: Result<_, _> = std::fs::remove_file(&etag_path);
-		extract_archive(&directory, &download.data).await?;
+		// Remove the cache validators of the old database before replacing it.
+		remove_file(&source_path).await?;
+		remove_file(&last_modified_path).await?;
+		remove_file(&etag_path).await?;
+		download.file.persist().await.map_err(|e| {
+			print_status(index, total, &repository.name, Paint::red("failed"), Some(mirror));
+			error!("{}.", e);
+		})?;
+		print_status(index, total, &repository.name, Paint::green("done"), Some(mirror));
 		if let Some(last_modified) = download.last_modified {
-			let _: Result<_, _> = std::fs::write(&last_modified_path, last_modified);
+			let _: Result<_, _> = tokio::fs::write(&last_modified_path, last_modified).await;
 		}
 		if let Some(etag) = download.etag {
-			let _: Result<_, _> = std::fs::write(&etag_path, etag);
+			let _: Result<_, _> = tokio::fs::write(&etag_path, etag).await;
 		}
+		let _: Result<_, _> = tokio::fs::write(&source_path, db_url.as_str()).await;
 	} else {
-		println!(" {}", Paint::yellow("up to date"));
+		print_status(index, total, &repository.name, Paint::yellow("up to date"), Some(mirror));
 	}
-	Ok(())
+	Ok(db_path)
 }
 
 /// Download all packages.
 async fn download_packages<'a>(
 	http_client: &reqwest::Client,
+	options: &DownloadOptions,
 	directory: &impl AsRef<Path>,
 	selected: &BTreeSet<&str>,
 	packages: &BTreeMap<&str, (&'a Repository, &'a DatabasePackage)>,
 ) -> Result<Vec<(&'a Repository, &'a DatabasePackage)>, ()> {
 	let directory = directory.as_ref();
-	let mut downloaded = Vec::with_capacity(selected.len());
-	for (i, pkg_name) in selected.iter().enumerate() {
-		let (repository, package) = packages
+	make_dirs(directory)?;
+
+	let jobs = selected.iter().enumerate().map(|(i, pkg_name)| {
+		let &(repository, package) = packages
 			.get(pkg_name)
 			.unwrap_or_else(|| panic!("selected package list contains unknown package: {}", pkg_name));
-		if download_package(http_client, directory, repository, package, i, selected.len()).await? {
-			downloaded.push((*repository, *package));
+		async move {
+			let downloaded = download_package(http_client, options, directory, repository, package, i, selected.len()).await?;
+			Ok(if downloaded { Some((repository, package)) } else { None })
 		}
-	}
-	Ok(downloaded)
+	});
+
+	let downloaded = run_downloads(options, jobs).await?;
+	Ok(downloaded.into_iter().flatten().collect())
 }
 
 /// Download a single package, if required.
+///
+/// The mirrors of the repository are tried in order, until one of them succeeds.
 async fn download_package(
 	http_client: &reqwest::Client,
+	options: &DownloadOptions,
 	directory: impl AsRef<Path>,
 	repository: &Repository,
 	package: &DatabasePackage,
 	index: usize,
 	total: usize,
 ) -> Result<bool, ()> {
-	use std::io::Write;
 	let directory = directory.as_ref();
-	make_dirs(directory)?;
 
-	let pkg_url = package_url(repository, package);
 	let pkg_path = directory.join(&package.filename);
 	let skip = if let Some(metadata) = stat(&pkg_path)? {
 		if metadata.len() != package.compressed_size {
 			warning!("File size of {} does not match, re-downloading package.", package.filename);
 			false
-		} else if !file_sha256(&pkg_path)?.eq_ignore_ascii_case(&package.sha256sum) {
+		} else if !file_sha256(&pkg_path).await?.eq_ignore_ascii_case(&package.sha256sum) {
 			warning!("SHA256 checksum of {} does not match, re-downloading package.", package.filename);
 			false
+		} else if let Err(e) = verify_local_signature(options, &repository.sig_level, package, &pkg_path).await {
+			warning!("Failed to verify the signature of {}: {}, re-downloading package.", package.filename, e);
+			false
 		} else {
 			true
 		}
@@ -443,44 +927,171 @@ async fn download_package(
 		false
 	};
 
-	plain_no_eol!("Downloading [{}/{}] {}...", Paint::blue(index + 1).bold(), Paint::blue(total).bold(), Paint::cyan(&package.name));
 	if skip {
-		println!(" {}", Paint::yellow("up to date"));
+		print_status(index, total, &package.name, Paint::yellow("up to date"), None);
 		return Ok(false);
 	}
-	let mut file = std::fs::File::create(&pkg_path).map_err(|e| {
-		println!(" {}", Paint::red("failed"));
-		error!("Failed to open {} for writing: {}.", pkg_path.display(), e);
-	})?;
-	let data = download(http_client, &pkg_url).await.map_err(|e| {
-		println!(" {}", Paint::red("failed"));
-		error!("{}.", e);
-	})?;
-	file.write_all(&data).map_err(|e| {
-		println!(" {}", Paint::red("failed"));
-		error!("Failed to write to {}: {}.", pkg_path.display(), e);
-	})?;
-	println!(" {}", Paint::green("done"));
-	Ok(true)
+
+	let mut mirrors = repository.mirrors.iter().peekable();
+	while let Some(mirror) = mirrors.next() {
+		match download_package_from(http_client, options, mirror, &repository.sig_level, package, &pkg_path).await {
+			Ok(()) => {
+				print_status(index, total, &package.name, Paint::green("done"), Some(mirror));
+				return Ok(true);
+			},
+			Err(e) if e.is_mirror_error() && mirrors.peek().is_some() => {
+				warning!(
+					"Failed to download {} from {}: {}. Trying the next mirror.",
+					package.filename,
+					mirror_name(mirror),
+					e
+				);
+			},
+			Err(e) => {
+				print_status(index, total, &package.name, Paint::red("failed"), Some(mirror));
+				error!("Failed to download {} from {}: {}.", package.filename, mirror_name(mirror), e);
+				return Err(());
+			},
+		}
+	}
+
+	unreachable!("repository {} has no mirrors", repository.name);
 }
 
-/// Get the URL of a package file.
-fn package_url(repository: &Repository, package: &DatabasePackage) -> reqwest::Url {
-	let db_path = repository.db_url.path();
-	let parent = rpartition(db_path, '/').map(|(parent, _db_name)| parent).unwrap_or("");
+/// Download a package from a specific mirror and verify the checksum and signature.
+///
+/// The package is only moved to the final path if the size and checksum match the database,
+/// and if the signature is valid according to the signature level.
+/// A detached signature is saved next to the package.
+async fn download_package_from(
+	http_client: &reqwest::Client,
+	options: &DownloadOptions,
+	mirror: &Mirror,
+	sig_level: &SigLevel,
+	package: &DatabasePackage,
+	pkg_path: &Path,
+) -> Result<(), DownloadError> {
+	let signature_error = |error, from_mirror, quarantined| DownloadError::Signature {
+		filename: package.filename.clone(),
+		error,
+		from_mirror,
+		quarantined,
+	};
+
+	// Get the detached signature first, so that a failure does not leave a complete download behind.
+	let embedded_signature = package.pgp_signature().map_err(|e| signature_error(e, false, None))?;
+	let detached_signature = match (&embedded_signature, sig_level.package) {
+		(None, SignatureRequirement::Optional | SignatureRequirement::Required) => download_signature(http_client, options, mirror, package).await?,
+		_ => None,
+	};
+	let signature = embedded_signature.as_deref().or(detached_signature.as_deref());
+	let from_mirror = embedded_signature.is_none();
+
+	let pkg_url = mirror.package_url(package);
+	let permit = options.host_limiter.acquire(&pkg_url).await;
+	let file = download(http_client, &pkg_url, pkg_path).await?;
+	drop(permit);
+
+	if file.size() != package.compressed_size || !file.sha256().eq_ignore_ascii_case(&package.sha256sum) {
+		let (size, sha256) = (file.size(), file.sha256());
+		return Err(DownloadError::ChecksumMismatch {
+			filename: package.filename.clone(),
+			expected_size: package.compressed_size,
+			expected_sha256: package.sha256sum.clone(),
+			size,
+			sha256,
+			quarantined: quarantine(options, mirror, package, file).await,
+		});
+	}
+
+	if let Err(e) = verify_package(options, &file.part_path, signature, sig_level).await {
+		return Err(signature_error(e, from_mirror, quarantine(options, mirror, package, file).await));
+	}
 
-	let mut pkg_url = repository.db_url.clone();
-	pkg_url.set_path(&format!("{}/{}", parent, package.filename));
-	pkg_url
+	file.persist().await?;
+	if let Some(signature) = detached_signature {
+		let signature_path = signature_path(pkg_path);
+		tokio::fs::write(&signature_path, signature)
+			.await
+			.map_err(|e| DownloadError::Write(signature_path, e))?;
+	}
+	Ok(())
+}
+
+/// Download the detached signature of a package from a mirror.
+///
+/// Returns `None` if the mirror does not have a signature for the package.
+async fn download_signature(
+	http_client: &reqwest::Client,
+	options: &DownloadOptions,
+	mirror: &Mirror,
+	package: &DatabasePackage,
+) -> Result<Option<Vec<u8>>, DownloadError> {
+	let url = mirror.file_url(&format!("{}.sig", package.filename));
+	let permit = options.host_limiter.acquire(&url).await;
+	let response = http_client.get(url).send().await.map_err(DownloadError::Http)?;
+	if response.status() == reqwest::StatusCode::NOT_FOUND {
+		return Ok(None);
+	}
+	let response = response.error_for_status().map_err(DownloadError::Http)?;
+	let signature = response.bytes().await.map_err(DownloadError::Http)?;
+	drop(permit);
+	Ok(Some(signature.to_vec()))
+}
+
+/// Verify the signature of a package that was downloaded before.
+///
+/// The signature is taken from the database, or from the detached signature saved next to the package.
+async fn verify_local_signature(
+	options: &DownloadOptions,
+	sig_level: &SigLevel,
+	package: &DatabasePackage,
+	pkg_path: &Path,
+) -> Result<(), SignatureError> {
+	if sig_level.package == SignatureRequirement::Never {
+		return Ok(());
+	}
+	let signature = match package.pgp_signature()? {
+		Some(signature) => Some(signature),
+		None => tokio::fs::read(signature_path(pkg_path)).await.ok(),
+	};
+	verify_package(options, pkg_path, signature.as_deref(), sig_level).await
+}
+
+/// Verify the signature of a package with the keyring of the download options.
+///
+/// This runs `gpg` on the blocking thread pool, so that other downloads continue in the meantime.
+async fn verify_package(options: &DownloadOptions, path: &Path, signature: Option<&[u8]>, sig_level: &SigLevel) -> Result<(), SignatureError> {
+	let keyring = options.keyring.clone();
+	let path = path.to_owned();
+	let signature = signature.map(<[u8]>::to_vec);
+	let sig_level = *sig_level;
+	blocking(move || keyring.verify_package(&path, signature.as_deref(), &sig_level)).await
+}
+
+/// Get the path of the detached signature of a package.
+fn signature_path(pkg_path: &Path) -> PathBuf {
+	let mut path = pkg_path.as_os_str().to_owned();
+	path.push(".sig");
+	path.into()
+}
+
+/// Move a download that failed verification to the quarantine directory of the mirror that served it.
+///
+/// Returns the path of the quarantined file, or `None` if it could not be moved.
+async fn quarantine(options: &DownloadOptions, mirror: &Mirror, package: &DatabasePackage, file: PartFile) -> Option<PathBuf> {
+	file.quarantine(&options.quarantine_dir.join(quarantine_name(mirror)))
+		.await
+		.map_err(|e| warning!("Failed to quarantine {}: {}.", package.filename, e))
+		.ok()
 }
 
 /// Add packages to a database.
-async fn add_to_database(
-	db_path: &Path,
-	pkg_dir: &Path,
-	packages: &[(&Repository, &DatabasePackage)],
-) -> Result<(), ()> {
-	if packages.is_empty() {
+///
+/// If `recreate` is true, the database will contain only the given packages.
+/// Otherwise, the packages are added to the existing database, replacing older entries for the same package.
+fn add_to_database(db_path: &Path, pkg_dir: &Path, packages: &[(&Repository, &DatabasePackage)], recreate: bool) -> Result<(), ()> {
+	if packages.is_empty() && !recreate {
 		plain!("No packages to add.");
 		return Ok(());
 	}
@@ -489,54 +1100,403 @@ async fn add_to_database(
 		make_dirs(parent)?;
 	}
 
-	for (i, (_repo, package)) in packages.iter().enumerate() {
-		if Paint::is_enabled() && i != 0 {
-			print!("\x1b[F"); // Go up one line.
-			plain_no_eol!("Processing package {}/{}.", i + 1, packages.len());
-			print!("\x1b[K"); // Clear to end of line.
-			println!();
-		} else {
-			plain!("Processing package {}/{}.", i + 1, packages.len());
-		}
-
-		let status = tokio::process::Command::new("repo-add")
-			.arg("-q")
-			.arg(&db_path)
-			.arg(pkg_dir.join(&package.filename))
-			.stdin(std::process::Stdio::null())
-			.spawn()
-			.map_err(|e| error!("Failed to run repo-add: {}", e))?
-			.wait()
-			.await
-			.map_err(|e| error!("Failed to wait for repo-add to finish: {}.", e))?;
-		if !status.success() {
-			error!("repo-add exited with {}.", status);
+	// Refuse to add database entries for packages that are not actually in the package directory.
+	for (_repo, package) in packages {
+		let pkg_path = pkg_dir.join(&package.filename);
+		if stat(&pkg_path)?.is_none() {
+			error!("Package file {} does not exist.", pkg_path.display());
 			return Err(());
 		}
 	}
 
-	Ok(())
+	plain!("Writing {} package(s).", packages.len());
+	let packages = packages.iter().map(|&(_repo, package)| package);
+	let result = if recreate {
+		write_db_archive(db_path, packages)
+	} else {
+		add_to_db_archive(db_path, packages)
+	};
+	result.map_err(|e| error!("{}.", e))
 }
 
 struct Download {
-	data: Vec<u8>,
+	file: PartFile,
 	last_modified: Option<String>,
 	etag: Option<String>,
 }
 
-/// Download a file over HTTP(S).
-async fn download(client: &reqwest::Client, url: &reqwest::Url) -> Result<Vec<u8>, reqwest::Error> {
-	let response = client.get(url.clone()).send().await?.error_for_status()?;
-	Ok(response.bytes().await?.to_vec())
+/// Error that can occur while downloading a file.
+enum DownloadError {
+	Http(reqwest::Error),
+	Read(PathBuf, std::io::Error),
+	Write(PathBuf, std::io::Error),
+	Rename(PathBuf, PathBuf, std::io::Error),
+	ChecksumMismatch {
+		filename: String,
+		expected_size: u64,
+		expected_sha256: String,
+		size: u64,
+		sha256: String,
+		quarantined: Option<PathBuf>,
+	},
+	Signature {
+		filename: String,
+		error: SignatureError,
+		/// The signature was served by the mirror instead of taken from the database.
+		from_mirror: bool,
+		quarantined: Option<PathBuf>,
+	},
+}
+
+impl DownloadError {
+	/// Check if the error is caused by the mirror, so that another mirror should be tried.
+	///
+	/// These are connection errors, server errors, missing files, corrupt downloads and bad signatures served by the mirror.
+	///
+	/// If the signature comes from the database, the package already matched the checksum from the database,
+	/// so every other mirror would serve the same package with the same bad signature.
+	fn is_mirror_error(&self) -> bool {
+		match self {
+			Self::Http(e) => match e.status() {
+				Some(status) => status.is_server_error() || status == reqwest::StatusCode::NOT_FOUND,
+				None => true,
+			},
+			Self::ChecksumMismatch { .. } => true,
+			Self::Signature { from_mirror, .. } => *from_mirror,
+			Self::Read(..) | Self::Write(..) | Self::Rename(..) => false,
+		}
+	}
 }
 
-/// Download a file over HTTP(S).
+/// A file that is being downloaded.
+///
+/// The data is written to a `.part` file next to the final path, while computing the SHA256 checksum.
+/// Once the download is complete, [`PartFile::persist`] atomically renames the file to the final path.
+///
+/// The file is written through [`tokio::fs`], and hashing existing data runs on the blocking thread pool,
+/// so that other downloads continue while the disk is busy.
+///
+/// If a download is interrupted, the `.part` file is kept so that the download can be resumed later.
+/// To make that safe, the URL and the validator of the response (the `ETag` or `Last-Modified` header)
+/// are stored in a `.part.validator` file next to the partial download.
+/// A partial download is only resumed from the same URL,
+/// so a download that does not match the database is always blamed on the mirror that served all of it.
+struct PartFile {
+	path: PathBuf,
+	part_path: PathBuf,
+	validator_path: PathBuf,
+	file: tokio::fs::File,
+	size: u64,
+	sha256: sha2::Sha256,
+}
+
+impl PartFile {
+	/// Create an empty `.part` file for the given final path.
+	async fn create(path: &Path) -> Result<Self, DownloadError> {
+		use sha2::Digest;
+		let (part_path, validator_path) = Self::part_paths(path);
+		remove_file_if_exists(&validator_path)
+			.await
+			.map_err(|e| DownloadError::Write(validator_path.clone(), e))?;
+		let file = tokio::fs::OpenOptions::new()
+			.read(true)
+			.write(true)
+			.create(true)
+			.truncate(true)
+			.open(&part_path)
+			.await
+			.map_err(|e| DownloadError::Write(part_path.clone(), e))?;
+		Ok(Self {
+			path: path.into(),
+			part_path,
+			validator_path,
+			file,
+			size: 0,
+			sha256: sha2::Sha256::new(),
+		})
+	}
+
+	/// Open an existing `.part` file for the given final path, to resume the download from the given URL.
+	///
+	/// Returns the file and the validator of the partial download.
+	/// If there is no partial download with a validator, or if it was downloaded from a different URL, this returns `None`.
+	async fn open_partial(path: &Path, url: &reqwest::Url) -> Result<Option<(Self, String)>, DownloadError> {
+		use sha2::Digest;
+		let (part_path, validator_path) = Self::part_paths(path);
+		let contents = match tokio::fs::read_to_string(&validator_path).await {
+			Ok(x) => x,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(DownloadError::Read(validator_path, e)),
+		};
+		let validator = match contents.split_once('\n') {
+			Some((source, validator)) if source == url.as_str() => validator.to_owned(),
+			_ => return Ok(None),
+		};
+		let file = match tokio::fs::OpenOptions::new().read(true).write(true).open(&part_path).await {
+			Ok(x) => x,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(DownloadError::Read(part_path, e)),
+		};
+
+		// Hash the data we already have, which also moves the file cursor to the end.
+		let mut file = file.into_std().await;
+		let (file, size, sha256) = blocking(move || {
+			let mut sha256 = sha2::Sha256::new();
+			let size = std::io::copy(&mut file, &mut sha256)?;
+			Ok((file, size, sha256))
+		})
+		.await
+		.map_err(|e| DownloadError::Read(part_path.clone(), e))?;
+		let part_file = Self {
+			path: path.into(),
+			part_path,
+			validator_path,
+			file: tokio::fs::File::from_std(file),
+			size,
+			sha256,
+		};
+		Ok(Some((part_file, validator)))
+	}
+
+	/// Get the paths of the `.part` file and the validator file for a final path.
+	fn part_paths(path: &Path) -> (PathBuf, PathBuf) {
+		let mut part_path = path.as_os_str().to_owned();
+		part_path.push(".part");
+		let mut validator_path = part_path.clone();
+		validator_path.push(".validator");
+		(part_path.into(), validator_path.into())
+	}
+
+	/// Discard the data written so far, to start the download from the beginning.
+	async fn restart(&mut self) -> Result<(), DownloadError> {
+		use sha2::Digest;
+		use tokio::io::AsyncSeekExt;
+		self.file.set_len(0).await.map_err(|e| DownloadError::Write(self.part_path.clone(), e))?;
+		self.file
+			.seek(std::io::SeekFrom::Start(0))
+			.await
+			.map_err(|e| DownloadError::Write(self.part_path.clone(), e))?;
+		self.size = 0;
+		self.sha256 = sha2::Sha256::new();
+		Ok(())
+	}
+
+	/// Store the URL and validator of the response that is being written, or remove them if there is no validator.
+	async fn set_validator(&self, url: &reqwest::Url, validator: Option<&str>) -> Result<(), DownloadError> {
+		let result = match validator {
+			Some(validator) => tokio::fs::write(&self.validator_path, format!("{}\n{}", url, validator)).await,
+			None => remove_file_if_exists(&self.validator_path).await,
+		};
+		result.map_err(|e| DownloadError::Write(self.validator_path.clone(), e))
+	}
+
+	/// Write the body of a response to the file as it arrives.
+	async fn write_response(&mut self, mut response: reqwest::Response) -> Result<(), DownloadError> {
+		use sha2::Digest;
+		use tokio::io::AsyncWriteExt;
+		while let Some(chunk) = response.chunk().await.map_err(DownloadError::Http)? {
+			self.file
+				.write_all(&chunk)
+				.await
+				.map_err(|e| DownloadError::Write(self.part_path.clone(), e))?;
+			self.sha256.update(&chunk);
+			self.size += chunk.len() as u64;
+		}
+		self.file.flush().await.map_err(|e| DownloadError::Write(self.part_path.clone(), e))
+	}
+
+	/// Get the number of bytes written so far.
+	fn size(&self) -> u64 {
+		self.size
+	}
+
+	/// Get the SHA256 checksum of the data written so far, as hex string.
+	fn sha256(&self) -> String {
+		use sha2::Digest;
+		format!("{:x}", self.sha256.clone().finalize())
+	}
+
+	/// Flush the data to disk and move the file to the final path.
+	async fn persist(self) -> Result<(), DownloadError> {
+		let Self {
+			path,
+			part_path,
+			validator_path,
+			file,
+			..
+		} = self;
+		file.sync_all().await.map_err(|e| DownloadError::Write(part_path.clone(), e))?;
+		drop(file);
+		tokio::fs::rename(&part_path, &path)
+			.await
+			.map_err(|e| DownloadError::Rename(part_path, path, e))?;
+		let _: Result<_, _> = remove_file_if_exists(&validator_path).await;
+		Ok(())
+	}
+
+	/// Remove the downloaded data, so that it will not be used or resumed.
+	async fn discard(self) {
+		let Self {
+			part_path,
+			validator_path,
+			file,
+			..
+		} = self;
+		drop(file);
+		let _: Result<_, _> = remove_file_if_exists(&part_path).await;
+		let _: Result<_, _> = remove_file_if_exists(&validator_path).await;
+	}
+
+	/// Move the downloaded data into a quarantine directory, so that it will not be used or resumed.
+	///
+	/// Earlier quarantined files are never overwritten: if the file name is taken, a number is appended to it.
+	///
+	/// Returns the path of the quarantined file.
+	async fn quarantine(self, directory: &Path) -> Result<PathBuf, DownloadError> {
+		let Self {
+			path,
+			part_path,
+			validator_path,
+			file,
+			..
+		} = self;
+		drop(file);
+		let _: Result<_, _> = remove_file_if_exists(&validator_path).await;
+		tokio::fs::create_dir_all(directory)
+			.await
+			.map_err(|e| DownloadError::Write(directory.into(), e))?;
+		// The final path always has a file name, since it is derived from the package file name.
+		let file_name = path.file_name().unwrap();
+		let mut quarantine_path = directory.join(file_name);
+		for i in 1.. {
+			if tokio::fs::symlink_metadata(&quarantine_path).await.is_err() {
+				break;
+			}
+			let mut numbered = file_name.to_owned();
+			numbered.push(format!(".{}", i));
+			quarantine_path = directory.join(numbered);
+		}
+		tokio::fs::rename(&part_path, &quarantine_path)
+			.await
+			.map_err(|e| DownloadError::Rename(part_path, quarantine_path.clone(), e))?;
+		Ok(quarantine_path)
+	}
+}
+
+impl std::fmt::Display for DownloadError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Http(e) => e.fmt(f),
+			Self::Read(path, e) => write!(f, "Failed to read {}: {}", path.display(), e),
+			Self::Write(path, e) => write!(f, "Failed to write to {}: {}", path.display(), e),
+			Self::Rename(source, target, e) => write!(f, "Failed to rename {} to {}: {}", source.display(), target.display(), e),
+			Self::ChecksumMismatch {
+				filename,
+				expected_size,
+				expected_sha256,
+				size,
+				sha256,
+				quarantined,
+			} => {
+				write!(
+					f,
+					"Downloaded file {} does not match the database: expected {} bytes with SHA256 checksum {}, got {} bytes with SHA256 checksum {}",
+					filename, expected_size, expected_sha256, size, sha256,
+				)?;
+				if let Some(path) = quarantined {
+					write!(f, " (moved to {})", path.display())?;
+				}
+				Ok(())
+			},
+			Self::Signature {
+				filename,
+				error,
+				quarantined,
+				..
+			} => {
+				write!(f, "Failed to verify the signature of {}: {}", filename, error)?;
+				if let Some(path) = quarantined {
+					write!(f, " (moved to {})", path.display())?;
+				}
+				Ok(())
+			},
+		}
+	}
+}
+
+/// Download a file over HTTP(S) to a `.part` file next to the given path.
+///
+/// If a previous download of the file was interrupted, it is resumed with a `Range` request.
+/// The `If-Range` header makes sure the server only sends the remaining part if the file did not change.
+/// Otherwise, or if the server does not support range requests, the whole file is downloaded again.
+async fn download(client: &reqwest::Client, url: &reqwest::Url, path: &Path) -> Result<PartFile, DownloadError> {
+	use reqwest::header::{IF_RANGE, RANGE};
+	use reqwest::StatusCode;
+
+	let (mut file, validator) = match PartFile::open_partial(path, url).await? {
+		Some((file, validator)) => (file, Some(validator)),
+		None => (PartFile::create(path).await?, None),
+	};
+
+	let mut request = client.get(url.clone());
+	if let Some(validator) = &validator {
+		request = request.header(RANGE, format!("bytes={}-", file.size())).header(IF_RANGE, validator);
+	}
+	let response = request.send().await.map_err(DownloadError::Http)?;
+
+	let response = match response.status() {
+		StatusCode::PARTIAL_CONTENT if validator.is_some() && content_range_start(response.headers()) == Some(file.size()) => response,
+		StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE if validator.is_some() => {
+			// The server can not give us the remaining part, so start over with a plain request.
+			file.restart().await?;
+			client
+				.get(url.clone())
+				.send()
+				.await
+				.and_then(|x| x.error_for_status())
+				.map_err(DownloadError::Http)?
+		},
+		_ => {
+			// The server sent the whole file, either because it ignored the range or because the file changed.
+			let response = response.error_for_status().map_err(DownloadError::Http)?;
+			file.restart().await?;
+			response
+		},
+	};
+
+	file.set_validator(url, response_validator(response.headers()).as_deref()).await?;
+	file.write_response(response).await?;
+	Ok(file)
+}
+
+/// Get the validator of a response, to resume a download of the same file later.
+///
+/// This is the `ETag` header if the server sent a strong ETag, or the `Last-Modified` header otherwise.
+fn response_validator(headers: &reqwest::header::HeaderMap) -> Option<String> {
+	match get_string_header(headers, "ETag") {
+		Some(etag) if !etag.starts_with("W/") => Some(etag),
+		_ => get_string_header(headers, "Last-Modified"),
+	}
+}
+
+/// Get the start of the range from a `Content-Range` header.
+fn content_range_start(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+	let content_range = get_string_header(headers, "Content-Range")?;
+	let range = content_range.strip_prefix("bytes ")?;
+	let (start, _rest) = range.split_once('-')?;
+	start.trim().parse().ok()
+}
+
+/// Download a file over HTTP(S) to a `.part` file next to the given path, if it was modified.
+///
+/// Returns `None` if the server reports that the file was not modified.
 async fn maybe_download(
 	client: &reqwest::Client,
 	url: &reqwest::Url,
+	path: &Path,
 	last_modified: Option<&str>,
 	etag: Option<&str>,
-) -> Result<Option<Download>, reqwest::Error> {
+) -> Result<Option<Download>, DownloadError> {
 	let mut request = client.get(url.clone());
 	if let Some(last_modified) = last_modified {
 		request = request.header("If-Modified-Since", last_modified);
@@ -545,15 +1505,20 @@ async fn maybe_download(
 		request = request.header("If-None-Match", etag);
 	}
 
-	let response = request.send().await?.error_for_status()?;
+	let response = request.send().await.and_then(|x| x.error_for_status()).map_err(DownloadError::Http)?;
 	if response.status() == reqwest::StatusCode::NOT_MODIFIED {
 		return Ok(None);
 	}
 
 	let last_modified = get_string_header(response.headers(), "Last-Modified");
 	let etag = get_string_header(response.headers(), "ETag");
-	let data = response.bytes().await?.to_vec();
-	Ok(Some(Download { data, last_modified, etag }))
+	let mut file = PartFile::create(path).await?;
+	if let Err(e) = file.write_response(response).await {
+		// Database downloads are never resumed, so do not leave the partial file behind.
+		file.discard().await;
+		return Err(e);
+	}
+	Ok(Some(Download { file, last_modified, etag }))
 }
 
 /// Get the value of a header as string.
@@ -569,16 +1534,19 @@ fn make_dirs(path: impl AsRef<Path>) -> Result<(), ()> {
 	std::fs::create_dir_all(path).map_err(|e| error!("Failed to create directory {}: {}.", path.display(), e))
 }
 
-/// Recursively remove a directory and it's content.
-fn remove_dir_all(path: impl AsRef<Path>) -> Result<(), ()> {
+/// Remove a file if it exists.
+async fn remove_file(path: impl AsRef<Path>) -> Result<(), ()> {
 	let path = path.as_ref();
-	match std::fs::remove_dir_all(path) {
-		Ok(()) => Ok(()),
-		Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
-		Err(e) => {
-			error!("Failed to remove directory {} or it's content: {}.", path.display(), e);
-			Err(())
-		},
+	remove_file_if_exists(path)
+		.await
+		.map_err(|e| error!("Failed to remove {}: {}.", path.display(), e))
+}
+
+/// Remove a file, without reporting an error if it does not exist.
+async fn remove_file_if_exists(path: impl AsRef<Path>) -> std::io::Result<()> {
+	match tokio::fs::remove_file(path).await {
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+		x => x,
 	}
 }
 
@@ -602,12 +1570,19 @@ fn stat(path: impl AsRef<Path>) -> Result<Option<std::fs::Metadata>, ()> {
 
 /// Compute the sha256 checksum of the contents of a file.
 ///
+/// The file is read on the blocking thread pool, so that other downloads continue in the meantime.
+///
 /// Returns the sha256 digest as hex string.
-fn file_sha256(path: impl AsRef<Path>) -> Result<String, ()> {
+async fn file_sha256(path: impl AsRef<Path>) -> Result<String, ()> {
 	use sha2::Digest;
-	let path = path.as_ref();
-	let data = std::fs::read(path).map_err(|e| error!("Failed to read {}: {}.", path.display(), e))?;
-	let digest = sha2::Sha256::digest(&data);
+	let path = path.as_ref().to_owned();
+	let digest = blocking(move || {
+		let mut file = std::fs::File::open(&path).map_err(|e| error!("Failed to open {}: {}.", path.display(), e))?;
+		let mut hasher = sha2::Sha256::new();
+		std::io::copy(&mut file, &mut hasher).map_err(|e| error!("Failed to read {}: {}.", path.display(), e))?;
+		Ok(hasher.finalize())
+	})
+	.await?;
 	let mut hex = String::with_capacity(256 / 8 * 2);
 	for byte in digest {
 		hex += &format!("{:02x}", byte);
@@ -615,52 +1590,1011 @@ fn file_sha256(path: impl AsRef<Path>) -> Result<String, ()> {
 	Ok(hex)
 }
 
-/// Extract an archive in a directory using bsdtar.
-async fn extract_archive(directory: &Path, data: &[u8]) -> Result<(), ()> {
-	use tokio::io::AsyncWriteExt;
+/// Run a blocking function on the blocking thread pool of the runtime.
+///
+/// Use this for hashing whole files and other work that would otherwise stall all downloads.
+async fn blocking<T: Send + 'static>(function: impl FnOnce() -> T + Send + 'static) -> T {
+	match tokio::task::spawn_blocking(function).await {
+		Ok(x) => x,
+		Err(e) => std::panic::resume_unwind(e.into_panic()),
+	}
+}
 
-	// Delete and re-create directory for extracting the archive.
-	remove_dir_all(directory)?;
-	make_dirs(directory)?;
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::{assert, let_assert};
+	use std::io::{Read, Write};
+	use std::sync::atomic::{AtomicUsize, Ordering};
 
-	// Spawn bsdtar process.
-	let mut process = tokio::process::Command::new("bsdtar")
-		.args(&["xf", "-"])
-		.current_dir(directory)
-		.stdin(std::process::Stdio::piped())
-		.spawn()
-		.map_err(|e| error!("Failed to run bsdtar: {}.", e))?;
-
-	// Write archive to standard input of bsdtar.
-	let mut stdin = process.stdin.take().ok_or_else(|| error!("Failed to get stdin for bsdtar."))?;
-	stdin
-		.write_all(data)
-		.await
-		.map_err(|e| error!("Failed to write to bsdtar stdin: {}.", e))?;
-	drop(stdin);
+	/// How the stand-in server should respond to requests.
+	#[derive(Clone, Copy)]
+	struct ServerBehaviour {
+		/// The ETag of the served file.
+		etag: &'static str,
 
-	// Wait for bsdtar to finish.
-	let exit_status = process.wait().await.map_err(|e| error!("Failed to wait for bsdtar to exit: {}.", e))?;
+		/// Honor `Range` requests.
+		support_ranges: bool,
 
-	// Check the exit status.
-	if exit_status.success() {
-		Ok(())
-	} else {
-		error!("bsdtar exitted with {}.", exit_status);
-		Err(())
+		/// Close the connection of the first response after this many bytes of the body.
+		interrupt_after: Option<usize>,
+
+		/// Respond with this status and an empty body instead of serving the file.
+		status: Option<&'static str>,
+
+		/// Serve this detached signature for paths ending in `.sig`, or respond with 404 if there is none.
+		signature: Option<&'static [u8]>,
 	}
-}
 
-/// Remove a file.
-///
-/// Unlike [`std::fs::remove_file`], this function does not return an error if the file does not exist.
-fn remove_file(path: impl AsRef<Path>) -> Result<(), ()> {
-	let path = path.as_ref();
-	if let Err(e) = std::fs::remove_file(path) {
-		if e.kind() != std::io::ErrorKind::NotFound {
-			error!("Failed to delete {}: {}.", path.display(), e);
-			return Err(());
+	impl Default for ServerBehaviour {
+		fn default() -> Self {
+			Self {
+				etag: "\"v1\"",
+				support_ranges: true,
+				interrupt_after: None,
+				status: None,
+				signature: None,
+			}
 		}
 	}
-	Ok(())
+
+	/// A minimal HTTP server that stands in for a package mirror.
+	///
+	/// It serves the same body for every path except signatures,
+	/// and records the `Range` and `If-None-Match` headers of each request.
+	struct TestServer {
+		url: reqwest::Url,
+		ranges: Arc<Mutex<Vec<Option<String>>>>,
+		if_none_match: Arc<Mutex<Vec<Option<String>>>>,
+	}
+
+	impl TestServer {
+		fn start(body: &'static [u8], behaviour: ServerBehaviour) -> Self {
+			let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+			let url = format!("http://{}/repo/package.pkg.tar.zst", listener.local_addr().unwrap())
+				.parse()
+				.unwrap();
+			let ranges = Arc::new(Mutex::new(Vec::new()));
+			let if_none_match = Arc::new(Mutex::new(Vec::new()));
+			let requests = AtomicUsize::new(0);
+
+			let recorded = (ranges.clone(), if_none_match.clone());
+			std::thread::spawn(move || {
+				for stream in listener.incoming() {
+					let first = requests.fetch_add(1, Ordering::Relaxed) == 0;
+					let _: Result<_, _> = Self::respond(stream.unwrap(), body, behaviour, first, &recorded.0, &recorded.1);
+				}
+			});
+
+			Self { url, ranges, if_none_match }
+		}
+
+		fn respond(
+			mut stream: std::net::TcpStream,
+			body: &[u8],
+			behaviour: ServerBehaviour,
+			first: bool,
+			ranges: &Mutex<Vec<Option<String>>>,
+			if_none_match: &Mutex<Vec<Option<String>>>,
+		) -> std::io::Result<()> {
+			let mut request = Vec::new();
+			let mut buffer = [0; 1024];
+			while !request.ends_with(b"\r\n\r\n") {
+				let read = stream.read(&mut buffer)?;
+				if read == 0 {
+					return Ok(());
+				}
+				request.extend_from_slice(&buffer[..read]);
+			}
+
+			let request = String::from_utf8_lossy(&request).into_owned();
+			let header = |name: &str| {
+				request.lines().find_map(|line| {
+					let (key, value) = line.split_once(':')?;
+					key.eq_ignore_ascii_case(name).then(|| value.trim().to_owned())
+				})
+			};
+			let range = header("Range");
+			ranges.lock().unwrap().push(range.clone());
+			if_none_match.lock().unwrap().push(header("If-None-Match"));
+
+			let is_signature = request.split_whitespace().nth(1).map_or(false, |path| path.ends_with(".sig"));
+			let (status, body) = match (is_signature, behaviour.signature) {
+				(true, Some(signature)) => (behaviour.status, signature),
+				(true, None) => (Some("404 Not Found"), body),
+				(false, _) => (behaviour.status, body),
+			};
+
+			let range_start = range
+				.filter(|_| behaviour.support_ranges && header("If-Range").as_deref() == Some(behaviour.etag))
+				.and_then(|range| range.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok());
+
+			let (status, content_range, body) = match (status, range_start) {
+				(Some(status), _) => (status, None, &body[..0]),
+				(None, None) => ("200 OK", None, body),
+				(None, Some(start)) if start >= body.len() => ("416 Range Not Satisfiable", Some(format!("bytes */{}", body.len())), &body[..0]),
+				(None, Some(start)) => (
+					"206 Partial Content",
+					Some(format!("bytes {}-{}/{}", start, body.len() - 1, body.len())),
+					&body[start..],
+				),
+			};
+
+			write!(
+				stream,
+				"HTTP/1.1 {}\r\nContent-Length: {}\r\nETag: {}\r\nConnection: close\r\n",
+				status,
+				body.len(),
+				behaviour.etag
+			)?;
+			if let Some(content_range) = content_range {
+				write!(stream, "Content-Range: {}\r\n", content_range)?;
+			}
+			write!(stream, "\r\n")?;
+
+			match behaviour.interrupt_after {
+				Some(n) if first => stream.write_all(&body[..n]),
+				_ => stream.write_all(body),
+			}
+		}
+
+		fn ranges(&self) -> Vec<Option<String>> {
+			self.ranges.lock().unwrap().clone()
+		}
+
+		fn if_none_match(&self) -> Vec<Option<String>> {
+			self.if_none_match.lock().unwrap().clone()
+		}
+
+		fn mirror(&self) -> Mirror {
+			let (_name, mirror) = Mirror::from_database_url(self.url.join("core.db").unwrap()).unwrap();
+			mirror
+		}
+
+		fn quarantine_path(&self, dir: &Path) -> PathBuf {
+			dir.join("quarantine").join(quarantine_name(&self.mirror())).join("package.pkg.tar.zst")
+		}
+	}
+
+	const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+	fn run<F: Future>(future: F) -> F::Output {
+		tokio::runtime::Builder::new_current_thread()
+			.enable_all()
+			.build()
+			.unwrap()
+			.block_on(future)
+	}
+
+	/// Download a file and move it into place.
+	fn download_and_persist(url: &reqwest::Url, path: &Path) -> Result<(), DownloadError> {
+		run(async {
+			let client = reqwest::Client::new();
+			download(&client, url, path).await?.persist().await
+		})
+	}
+
+	fn sha256_hex(data: &[u8]) -> String {
+		use sha2::Digest;
+		format!("{:x}", sha2::Sha256::digest(data))
+	}
+
+	fn write_partial(path: &Path, url: &reqwest::Url, data: &[u8], validator: &str) {
+		let (part_path, validator_path) = PartFile::part_paths(path);
+		std::fs::write(part_path, data).unwrap();
+		std::fs::write(validator_path, format!("{}\n{}", url, validator)).unwrap();
+	}
+
+	#[test]
+	fn resume_interrupted_download() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let path = dir.join("package.pkg.tar.zst");
+		let (part_path, validator_path) = PartFile::part_paths(&path);
+		let server = TestServer::start(BODY, ServerBehaviour {
+			interrupt_after: Some(20),
+			..Default::default()
+		});
+
+		// The first download is interrupted, but the partial file is kept.
+		let_assert!(Err(DownloadError::Http(_)) = download_and_persist(&server.url, &path));
+		assert!(std::fs::read(&part_path).unwrap() == &BODY[..20]);
+		assert!(std::fs::read_to_string(&validator_path).unwrap() == format!("{}\n\"v1\"", server.url));
+
+		// The second download picks up where the first one stopped.
+		let_assert!(Ok(()) = download_and_persist(&server.url, &path));
+		assert!(server.ranges() == [None, Some("bytes=20-".into())]);
+		assert!(std::fs::read(&path).unwrap() == BODY);
+		assert!(!part_path.exists());
+		assert!(!validator_path.exists());
+	}
+
+	#[test]
+	fn resume_checksum_covers_whole_file() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let path = dir.join("package.pkg.tar.zst");
+		let server = TestServer::start(BODY, ServerBehaviour::default());
+		write_partial(&path, &server.url, &BODY[..10], "\"v1\"");
+
+		let file = run(async { download(&reqwest::Client::new(), &server.url, &path).await });
+		let_assert!(Ok(file) = file);
+		assert!(server.ranges() == [Some("bytes=10-".into())]);
+		assert!(file.size() == BODY.len() as u64);
+		assert!(file.sha256() == sha256_hex(BODY));
+	}
+
+	#[test]
+	fn restart_when_server_ignores_ranges() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let path = dir.join("package.pkg.tar.zst");
+		let server = TestServer::start(BODY, ServerBehaviour {
+			support_ranges: false,
+			..Default::default()
+		});
+		write_partial(&path, &server.url, &BODY[..10], "\"v1\"");
+
+		let_assert!(Ok(()) = download_and_persist(&server.url, &path));
+		assert!(server.ranges() == [Some("bytes=10-".into())]);
+		assert!(std::fs::read(&path).unwrap() == BODY);
+	}
+
+	#[test]
+	fn restart_when_validator_changed() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let path = dir.join("package.pkg.tar.zst");
+		let server = TestServer::start(BODY, ServerBehaviour {
+			etag: "\"v2\"",
+			..Default::default()
+		});
+		write_partial(&path, &server.url, b"old data", "\"v1\"");
+
+		let_assert!(Ok(()) = download_and_persist(&server.url, &path));
+		assert!(server.ranges() == [Some("bytes=8-".into())]);
+		assert!(std::fs::read(&path).unwrap() == BODY);
+	}
+
+	#[test]
+	fn restart_when_range_not_satisfiable() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let path = dir.join("package.pkg.tar.zst");
+		let mut too_long = BODY.to_vec();
+		too_long.extend_from_slice(b"trailing garbage");
+		let server = TestServer::start(BODY, ServerBehaviour::default());
+		write_partial(&path, &server.url, &too_long, "\"v1\"");
+
+		let_assert!(Ok(()) = download_and_persist(&server.url, &path));
+		assert!(server.ranges() == [Some(format!("bytes={}-", too_long.len())), None]);
+		assert!(std::fs::read(&path).unwrap() == BODY);
+	}
+
+	#[test]
+	fn no_resume_from_other_url() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let path = dir.join("package.pkg.tar.zst");
+		let other = TestServer::start(BODY, ServerBehaviour::default());
+		let server = TestServer::start(BODY, ServerBehaviour::default());
+		write_partial(&path, &other.url, b"bad data from another mirror", "\"v1\"");
+
+		let_assert!(Ok(()) = download_and_persist(&server.url, &path));
+		assert!(server.ranges() == [None]);
+		assert!(other.ranges().is_empty());
+		assert!(std::fs::read(&path).unwrap() == BODY);
+
+		// Validator files without a URL are not trusted either.
+		let (_part_path, validator_path) = PartFile::part_paths(&path);
+		std::fs::remove_file(&path).unwrap();
+		write_partial(&path, &server.url, &BODY[..10], "\"v1\"");
+		std::fs::write(&validator_path, "\"v1\"").unwrap();
+		let_assert!(Ok(()) = download_and_persist(&server.url, &path));
+		assert!(server.ranges() == [None, None]);
+	}
+
+	#[test]
+	fn no_resume_without_validator() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let path = dir.join("package.pkg.tar.zst");
+		let (part_path, _validator_path) = PartFile::part_paths(&path);
+		std::fs::write(&part_path, &BODY[..10]).unwrap();
+		let server = TestServer::start(BODY, ServerBehaviour::default());
+
+		let_assert!(Ok(()) = download_and_persist(&server.url, &path));
+		assert!(server.ranges() == [None]);
+		assert!(std::fs::read(&path).unwrap() == BODY);
+	}
+
+	/// The package entry for the [`BODY`] served by a [`TestServer`].
+	///
+	/// The test helpers of the library are not available to the binary, so this loads the fixture itself.
+	fn test_package() -> DatabasePackage {
+		let mut package: DatabasePackage = pacman_repo_tools::db::from_bytes(include_bytes!("../../tests/database-package/desc")).unwrap();
+		package.filename = "package.pkg.tar.zst".into();
+		package.compressed_size = BODY.len() as u64;
+		package.sha256sum = sha256_hex(BODY);
+		package
+	}
+
+	/// Download a file from a test server as job for [`run_downloads`], with a permit from the host limiter.
+	///
+	/// The number of jobs that hold a permit at the same time is tracked in `active`, and the maximum in `max_active`.
+	async fn limited_download(
+		options: &DownloadOptions,
+		url: &reqwest::Url,
+		path: PathBuf,
+		active: &AtomicUsize,
+		max_active: &AtomicUsize,
+	) -> Result<(), ()> {
+		let _permit = options.host_limiter.acquire(url).await;
+		max_active.fetch_max(active.fetch_add(1, Ordering::Relaxed) + 1, Ordering::Relaxed);
+		let result = download(&reqwest::Client::new(), url, &path).await;
+		active.fetch_sub(1, Ordering::Relaxed);
+		result.map_err(drop)?.persist().await.map_err(drop)
+	}
+
+	#[test]
+	fn host_limiter_limits_per_host() {
+		use futures::FutureExt;
+
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let server = TestServer::start(BODY, ServerBehaviour::default());
+		let options = DownloadOptions {
+			parallel: 4,
+			host_limiter: HostLimiter::new(2),
+			..download_options(dir)
+		};
+
+		let (active, max_active) = (AtomicUsize::new(0), AtomicUsize::new(0));
+		let jobs = (0..6).map(|i| limited_download(&options, &server.url, dir.join(i.to_string()), &active, &max_active));
+		let_assert!(Ok(results) = run(run_downloads(&options, jobs)));
+		assert!(results.len() == 6);
+		assert!(server.ranges().len() == 6);
+		assert!(max_active.into_inner() == 2);
+
+		// Other hosts have their own limit.
+		let other_host = "http://localhost/core.db".parse().unwrap();
+		let permits = run(async {
+			(
+				options.host_limiter.acquire(&server.url).await,
+				options.host_limiter.acquire(&server.url).await,
+			)
+		});
+		assert!(options.host_limiter.acquire(&server.url).now_or_never().is_none());
+		assert!(options.host_limiter.acquire(&other_host).now_or_never().is_some());
+		drop(permits);
+		assert!(options.host_limiter.acquire(&server.url).now_or_never().is_some());
+	}
+
+	#[test]
+	fn run_downloads_stops_on_first_failure() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let good = TestServer::start(BODY, ServerBehaviour::default());
+		let broken = TestServer::start(BODY, ServerBehaviour {
+			status: Some("500 Internal Server Error"),
+			..Default::default()
+		});
+		let never = TestServer::start(BODY, ServerBehaviour::default());
+		let options = download_options(dir);
+
+		let (active, max_active) = (AtomicUsize::new(0), AtomicUsize::new(0));
+		let servers = [&good, &broken, &never];
+		let jobs = servers
+			.iter()
+			.enumerate()
+			.map(|(i, server)| limited_download(&options, &server.url, dir.join(i.to_string()), &active, &max_active));
+		let_assert!(Err(()) = run(run_downloads(&options, jobs)));
+		assert!(good.ranges().len() == 1);
+		assert!(broken.ranges().len() == 1);
+		assert!(never.ranges().is_empty());
+	}
+
+	#[test]
+	fn run_downloads_keep_going() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let good = TestServer::start(BODY, ServerBehaviour::default());
+		let broken = TestServer::start(BODY, ServerBehaviour {
+			status: Some("500 Internal Server Error"),
+			..Default::default()
+		});
+		let options = DownloadOptions {
+			keep_going: true,
+			..download_options(dir)
+		};
+
+		let (active, max_active) = (AtomicUsize::new(0), AtomicUsize::new(0));
+		let failed = AtomicUsize::new(0);
+		let servers = [&broken, &good, &broken, &good];
+		let jobs = servers.iter().enumerate().map(|(i, server)| {
+			let job = limited_download(&options, &server.url, dir.join(i.to_string()), &active, &max_active);
+			async {
+				let result = job.await;
+				if result.is_err() {
+					failed.fetch_add(1, Ordering::Relaxed);
+				}
+				result
+			}
+		});
+
+		// All jobs run to completion, but the result is still a failure.
+		let_assert!(Err(()) = run(run_downloads(&options, jobs)));
+		assert!(failed.into_inner() == 2);
+		assert!(broken.ranges().len() == 2);
+		assert!(good.ranges().len() == 2);
+		assert!(std::fs::read(dir.join("1")).unwrap() == BODY);
+		assert!(std::fs::read(dir.join("3")).unwrap() == BODY);
+	}
+
+	#[test]
+	fn run_downloads_keeps_job_order() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let server = TestServer::start(BODY, ServerBehaviour::default());
+		let options = DownloadOptions {
+			parallel: 3,
+			host_limiter: HostLimiter::new(3),
+			..download_options(dir)
+		};
+
+		// Each job waits for the next one to finish, so they finish in reverse order.
+		let (senders, receivers): (Vec<_>, Vec<_>) = (0..2).map(|_| tokio::sync::oneshot::channel::<()>()).unzip();
+		let senders = std::iter::once(None).chain(senders.into_iter().map(Some));
+		let receivers = receivers.into_iter().map(Some).chain(std::iter::once(None));
+
+		let (active, max_active) = (AtomicUsize::new(0), AtomicUsize::new(0));
+		let finished = Mutex::new(Vec::new());
+		let jobs = senders.into_iter().zip(receivers).enumerate().map(|(i, (sender, receiver))| {
+			let job = limited_download(&options, &server.url, dir.join(i.to_string()), &active, &max_active);
+			let finished = &finished;
+			async move {
+				job.await?;
+				if let Some(receiver) = receiver {
+					receiver.await.unwrap();
+				}
+				finished.lock().unwrap().push(i);
+				if let Some(sender) = sender {
+					sender.send(()).unwrap();
+				}
+				Ok(i)
+			}
+		});
+
+		let_assert!(Ok(results) = run(run_downloads(&options, jobs)));
+		assert!(finished.into_inner().unwrap() == [2, 1, 0]);
+		assert!(results == [0, 1, 2]);
+	}
+
+	#[test]
+	fn select_targets_without_dependencies() {
+		let repository = Repository {
+			name: "core".into(),
+			mirrors: Vec::new(),
+			sig_level: SigLevel::default(),
+		};
+		let mut linux = test_package();
+		linux.name = "linux".into();
+		linux.provides = vec!["WIREGUARD-MODULE".parse().unwrap()];
+		let databases = [(&repository, vec![linux, test_package()])];
+		let packages = index_packages_by_name(&databases);
+
+		let_assert!(Ok(selected) = select_targets(&packages, &["linux-aarch64>=5.8", "linux"]));
+		assert!(selected.into_iter().collect::<Vec<_>>() == ["linux", "linux-aarch64"]);
+
+		assert!(let Err(()) = select_targets(&packages, &["linux<5"]));
+		assert!(let Err(()) = select_targets(&packages, &["linux", "linux-firmware"]));
+		assert!(let Err(()) = select_targets(&packages, &["-linux"]));
+		// Virtual targets need the dependency resolver.
+		assert!(let Err(()) = select_targets(&packages, &["WIREGUARD-MODULE"]));
+	}
+
+	fn download_options(dir: &Path) -> DownloadOptions {
+		DownloadOptions {
+			parallel: 1,
+			keep_going: false,
+			host_limiter: HostLimiter::new(1),
+			quarantine_dir: dir.join("quarantine"),
+			keyring: Keyring::new(dir.join("keyring")),
+		}
+	}
+
+	fn download_test_package(dir: &Path, mirrors: &[&TestServer]) -> Result<bool, ()> {
+		let repository = Repository {
+			name: "core".into(),
+			mirrors: mirrors.iter().map(|x| x.mirror()).collect(),
+			sig_level: SigLevel::default().with_overrides("Never").unwrap(),
+		};
+		let options = download_options(dir);
+		let package = test_package();
+		run(async { download_package(&reqwest::Client::new(), &options, dir, &repository, &package, 0, 1).await })
+	}
+
+	fn download_test_database(dir: &Path, mirrors: &[&TestServer]) -> Result<PathBuf, ()> {
+		let repository = Repository {
+			name: "core".into(),
+			mirrors: mirrors.iter().map(|x| x.mirror()).collect(),
+			sig_level: SigLevel::default().with_overrides("Never").unwrap(),
+		};
+		let options = download_options(dir);
+		run(async { download_database(&reqwest::Client::new(), &options, dir, &repository, 0, 1).await })
+	}
+
+	#[test]
+	fn database_validators_only_sent_to_source() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let one = TestServer::start(BODY, ServerBehaviour::default());
+		let two = TestServer::start(BODY, ServerBehaviour {
+			etag: "\"v2\"",
+			..Default::default()
+		});
+
+		let_assert!(Ok(db_path) = download_test_database(dir, &[&one]));
+		assert!(std::fs::read(&db_path).unwrap() == BODY);
+		assert!(one.if_none_match() == [None]);
+
+		// The ETag of the first mirror is not sent to the second mirror.
+		let_assert!(Ok(_) = download_test_database(dir, &[&two, &one]));
+		assert!(two.if_none_match() == [None]);
+		let_assert!(Ok(_) = download_test_database(dir, &[&two, &one]));
+		assert!(two.if_none_match() == [None, Some("\"v2\"".into())]);
+		assert!(one.if_none_match() == [None]);
+	}
+
+	#[test]
+	fn discard_partial_database() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let interrupted = || {
+			TestServer::start(BODY, ServerBehaviour {
+				interrupt_after: Some(20),
+				..Default::default()
+			})
+		};
+		let good = TestServer::start(BODY, ServerBehaviour::default());
+
+		let_assert!(Ok(db_path) = download_test_database(dir, &[&interrupted(), &good]));
+		assert!(std::fs::read(&db_path).unwrap() == BODY);
+		assert!(!dir.join("database.part").exists());
+
+		let_assert!(Err(()) = download_test_database(dir, &[&interrupted()]));
+		assert!(std::fs::read(&db_path).unwrap() == BODY);
+		assert!(!dir.join("database.part").exists());
+	}
+
+	#[test]
+	fn parse_database_urls() {
+		let_assert!(
+			Ok(repositories) = Repository::parse_specs(
+				&[
+					"https://one.example.com/core/os/x86_64/core.db",
+					"https://one.example.com/extra/os/x86_64/extra.db.tar.zst",
+					"https://two.example.com/archlinux/core/os/x86_64/core.db",
+				],
+				"x86_64",
+				SigLevel::default()
+			)
+		);
+		assert!(repositories.len() == 2);
+		assert!(repositories[0].name == "core");
+		assert!(repositories[0].mirrors.len() == 2);
+		assert!(repositories[0].mirrors[1].database_url().as_str() == "https://two.example.com/archlinux/core/os/x86_64/core.db");
+		assert!(
+			repositories[0].mirrors[1].package_url(&test_package()).as_str()
+				== "https://two.example.com/archlinux/core/os/x86_64/package.pkg.tar.zst"
+		);
+		assert!(repositories[1].name == "extra");
+		assert!(repositories[1].mirrors.len() == 1);
+		assert!(repositories[1].mirrors[0].database_url().as_str() == "https://one.example.com/extra/os/x86_64/extra.db.tar.zst");
+		assert!(repositories[1].mirrors[0].package_url(&test_package()).as_str() == "https://one.example.com/extra/os/x86_64/package.pkg.tar.zst");
+	}
+
+	#[test]
+	fn parse_server_templates() {
+		let_assert!(
+			Ok(repositories) = Repository::parse_specs(
+				&[
+					"core=https://one.example.com/$repo/os/$arch",
+					"custom=https://bucket.example.com/repo/?signature=abc%2Bdef",
+					"https://two.example.com/core/os/aarch64/core.db",
+					"extra=https://one.example.com/$repo/os/$arch/",
+				],
+				"aarch64",
+				SigLevel::default()
+			)
+		);
+		let names: Vec<_> = repositories.iter().map(|x| x.name.as_str()).collect();
+		assert!(names == ["core", "custom", "extra"]);
+
+		let core = &repositories[0];
+		assert!(core.mirrors.len() == 2);
+		assert!(core.mirrors[0].database_url().as_str() == "https://one.example.com/core/os/aarch64/core.db");
+		assert!(core.mirrors[0].package_url(&test_package()).as_str() == "https://one.example.com/core/os/aarch64/package.pkg.tar.zst");
+		assert!(core.mirrors[1].database_url().as_str() == "https://two.example.com/core/os/aarch64/core.db");
+
+		let custom = &repositories[1].mirrors[0];
+		assert!(custom.database_url().as_str() == "https://bucket.example.com/repo/custom.db?signature=abc%2Bdef");
+		assert!(custom.package_url(&test_package()).as_str() == "https://bucket.example.com/repo/package.pkg.tar.zst?signature=abc%2Bdef");
+		assert!(repositories[2].mirrors[0].database_url().as_str() == "https://one.example.com/extra/os/aarch64/extra.db");
+
+		assert!(let Err(()) = Repository::parse_specs(&["=https://example.com/"], "x86_64", SigLevel::default()));
+		assert!(let Err(()) = Repository::parse_specs(&["..=https://example.com/"], "x86_64", SigLevel::default()));
+		assert!(let Err(()) = Repository::parse_specs(&["core="], "x86_64", SigLevel::default()));
+		assert!(let Err(()) = Repository::parse_specs(&["core=mailto:root@example.com"], "x86_64", SigLevel::default()));
+		assert!(let Err(()) = Repository::parse_specs(&["https://example.com/"], "x86_64", SigLevel::default()));
+	}
+
+	#[test]
+	fn repositories_from_config() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let mirrorlist = dir.join("mirrorlist");
+		std::fs::write(
+			&mirrorlist,
+			"Server = https://one.example.com/$repo/os/$arch\nServer = https://two.example.com/$repo/os/$arch\n",
+		)
+		.unwrap();
+		let config = dir.join("pacman.conf");
+		std::fs::write(
+			&config,
+			format!(
+				"[options]\nArchitecture = x86_64\n[core]\nInclude = {0}\n[extra]\nInclude = {0}\n",
+				mirrorlist.display()
+			),
+		)
+		.unwrap();
+
+		let_assert!(Ok(mut repositories) = Repository::parse_specs(&["extra=https://local.example.com/$repo"], "x86_64", SigLevel::default()));
+		let_assert!(Ok(parsed) = PacmanConfig::read(&config));
+		let_assert!(Ok(from_config) = config_repositories(&parsed, &config));
+		for repository in from_config {
+			repository.merge_into(&mut repositories);
+		}
+		assert!(repositories.len() == 2);
+		assert!(repositories[0].name == "extra");
+		assert!(repositories[0].mirrors.len() == 3);
+		assert!(repositories[0].mirrors[0].database_url().as_str() == "https://local.example.com/extra/extra.db");
+		assert!(repositories[0].mirrors[2].database_url().as_str() == "https://two.example.com/extra/os/x86_64/extra.db");
+		assert!(repositories[1].name == "core");
+		assert!(repositories[1].mirrors[0].database_url().as_str() == "https://one.example.com/core/os/x86_64/core.db");
+
+		std::fs::write(&config, "[core]\n").unwrap();
+		let_assert!(Ok(parsed) = PacmanConfig::read(&config));
+		assert!(let Err(()) = config_repositories(&parsed, &config));
+	}
+
+	#[test]
+	fn failover_on_server_errors() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let broken = TestServer::start(BODY, ServerBehaviour {
+			status: Some("503 Service Unavailable"),
+			..Default::default()
+		});
+		let missing = TestServer::start(BODY, ServerBehaviour {
+			status: Some("404 Not Found"),
+			..Default::default()
+		});
+		let good = TestServer::start(BODY, ServerBehaviour::default());
+
+		let_assert!(Ok(true) = download_test_package(dir, &[&broken, &missing, &good]));
+		assert!(broken.ranges().len() == 1);
+		assert!(missing.ranges().len() == 1);
+		assert!(good.ranges().len() == 1);
+		assert!(std::fs::read(dir.join("package.pkg.tar.zst")).unwrap() == BODY);
+	}
+
+	#[test]
+	fn failover_on_checksum_mismatch() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let corrupt = TestServer::start(b"not the package you are looking for", ServerBehaviour::default());
+		let good = TestServer::start(BODY, ServerBehaviour::default());
+
+		let_assert!(Ok(true) = download_test_package(dir, &[&corrupt, &good]));
+		assert!(corrupt.ranges().len() == 1);
+		assert!(good.ranges() == [None]);
+		assert!(std::fs::read(dir.join("package.pkg.tar.zst")).unwrap() == BODY);
+		assert!(std::fs::read(corrupt.quarantine_path(dir)).unwrap() == b"not the package you are looking for");
+		assert!(!good.quarantine_path(dir).exists());
+	}
+
+	#[test]
+	fn no_failover_on_other_client_errors() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let forbidden = TestServer::start(BODY, ServerBehaviour {
+			status: Some("403 Forbidden"),
+			..Default::default()
+		});
+		let good = TestServer::start(BODY, ServerBehaviour::default());
+
+		let_assert!(Err(()) = download_test_package(dir, &[&forbidden, &good]));
+		assert!(good.ranges().is_empty());
+	}
+
+	#[test]
+	fn all_mirrors_failing() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let broken = TestServer::start(BODY, ServerBehaviour {
+			status: Some("500 Internal Server Error"),
+			..Default::default()
+		});
+		let corrupt = TestServer::start(b"corrupt", ServerBehaviour::default());
+
+		let_assert!(Err(()) = download_test_package(dir, &[&broken, &corrupt]));
+		assert!(!dir.join("package.pkg.tar.zst").exists());
+		assert!(!dir.join("package.pkg.tar.zst.part").exists());
+		assert!(std::fs::read(corrupt.quarantine_path(dir)).unwrap() == b"corrupt");
+	}
+
+	/// Get the names of the files in a directory, without subdirectories.
+	fn file_names(dir: &Path) -> BTreeSet<String> {
+		std::fs::read_dir(dir)
+			.unwrap()
+			.map(|entry| entry.unwrap())
+			.filter(|entry| entry.file_type().unwrap().is_file())
+			.map(|entry| entry.file_name().to_string_lossy().into_owned())
+			.collect()
+	}
+
+	#[test]
+	fn failed_download_only_leaves_part_file() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let pkg_path = dir.join("package.pkg.tar.zst");
+		let (part_path, _validator_path) = PartFile::part_paths(&pkg_path);
+		let interrupted = TestServer::start(BODY, ServerBehaviour {
+			interrupt_after: Some(20),
+			..Default::default()
+		});
+
+		// An interrupted download is kept as .part file to resume later, but never at the final path.
+		let_assert!(Err(()) = download_test_package(dir, &[&interrupted]));
+		assert!(!pkg_path.exists());
+		assert!(std::fs::read(&part_path).unwrap() == &BODY[..20]);
+		assert!(
+			file_names(dir)
+				== ["package.pkg.tar.zst.part", "package.pkg.tar.zst.part.validator"]
+					.iter()
+					.map(|x| x.to_string())
+					.collect()
+		);
+
+		// A complete download that does not match the database is moved from the .part file to quarantine.
+		let corrupt = TestServer::start(b"corrupt", ServerBehaviour::default());
+		std::fs::remove_file(&part_path).unwrap();
+		let_assert!(Err(()) = download_test_package(dir, &[&corrupt]));
+		assert!(!pkg_path.exists());
+		assert!(!part_path.exists());
+		assert!(file_names(dir).is_empty());
+		assert!(std::fs::read(corrupt.quarantine_path(dir)).unwrap() == b"corrupt");
+	}
+
+	#[test]
+	fn quarantine_keeps_earlier_files() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let corrupt = TestServer::start(b"corrupt", ServerBehaviour::default());
+
+		let_assert!(Err(()) = download_test_package(dir, &[&corrupt]));
+		let_assert!(Err(()) = download_test_package(dir, &[&corrupt]));
+		let first = corrupt.quarantine_path(dir);
+		let mut second = first.clone().into_os_string();
+		second.push(".1");
+		assert!(std::fs::read(&first).unwrap() == b"corrupt");
+		assert!(std::fs::read(&second).unwrap() == b"corrupt");
+	}
+
+	#[test]
+	fn quarantine_name_includes_path() {
+		let mirror = |url: &str| Mirror::from_database_url(url.parse().unwrap()).unwrap().1;
+		let one = mirror("https://example.com/one/core.db");
+		let two = mirror("https://example.com/two/core.db");
+		assert!(quarantine_name(&one).starts_with("example.com-"));
+		assert!(quarantine_name(&one) != quarantine_name(&two));
+		assert!(quarantine_name(&one) == quarantine_name(&mirror("https://example.com/one/core.db?token=abc")));
+	}
+
+	#[test]
+	fn checksum_mismatch_error() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let mut corrupt_body = BODY.to_vec();
+		corrupt_body[10] ^= 0xFF;
+		let corrupt = TestServer::start(Box::leak(corrupt_body.into_boxed_slice()), ServerBehaviour::default());
+		let options = download_options(dir);
+
+		let package = test_package();
+		let pkg_path = dir.join(&package.filename);
+		let result = run(download_package_from(
+			&reqwest::Client::new(),
+			&options,
+			&corrupt.mirror(),
+			&sig_level("Never"),
+			&package,
+			&pkg_path,
+		));
+		let_assert!(Err(e) = result);
+		let_assert!(
+			DownloadError::ChecksumMismatch {
+				size,
+				quarantined: Some(quarantined),
+				..
+			} = &e
+		);
+		assert!(*size == BODY.len() as u64);
+		assert!(quarantined == &corrupt.quarantine_path(dir));
+		assert!(e.to_string().ends_with(&format!("(moved to {})", quarantined.display())));
+		assert!(!pkg_path.exists());
+		assert!(!dir.join("package.pkg.tar.zst.part").exists());
+	}
+
+	/// A GnuPG home directory with an ultimately trusted signing key.
+	///
+	/// It uses the same directory as the keyring from [`download_options`].
+	struct TestSigner {
+		homedir: PathBuf,
+	}
+
+	impl TestSigner {
+		fn new(dir: &Path) -> Self {
+			use std::os::unix::fs::PermissionsExt;
+			let homedir = dir.join("keyring");
+			std::fs::create_dir_all(&homedir).unwrap();
+			std::fs::set_permissions(&homedir, std::fs::Permissions::from_mode(0o700)).unwrap();
+			let signer = Self { homedir };
+			signer.gpg(&["--quick-gen-key", "Packager <packager@example.com>", "ed25519", "sign", "never"], b"");
+			signer
+		}
+
+		fn gpg(&self, args: &[&str], input: &[u8]) -> Vec<u8> {
+			let mut child = std::process::Command::new("gpg")
+				.arg("--homedir")
+				.arg(&self.homedir)
+				.args(["--batch", "--no-tty", "--passphrase", ""])
+				.args(args)
+				.stdin(std::process::Stdio::piped())
+				.stdout(std::process::Stdio::piped())
+				.stderr(std::process::Stdio::null())
+				.spawn()
+				.unwrap();
+			child.stdin.take().unwrap().write_all(input).unwrap();
+			let output = child.wait_with_output().unwrap();
+			assert!(output.status.success(), "gpg {:?} failed", args);
+			output.stdout
+		}
+
+		fn sign(&self, data: &[u8]) -> &'static [u8] {
+			Box::leak(self.gpg(&["--detach-sign", "--output", "-"], data).into_boxed_slice())
+		}
+	}
+
+	impl Drop for TestSigner {
+		fn drop(&mut self) {
+			let _ = std::process::Command::new("gpgconf")
+				.arg("--homedir")
+				.arg(&self.homedir)
+				.args(["--kill", "gpg-agent"])
+				.status();
+		}
+	}
+
+	fn sig_level(input: &str) -> SigLevel {
+		SigLevel::default().with_overrides(input).unwrap()
+	}
+
+	fn download_signed_package(
+		options: &DownloadOptions,
+		server: &TestServer,
+		sig_level: &SigLevel,
+		package: &DatabasePackage,
+		pkg_path: &Path,
+	) -> Result<(), DownloadError> {
+		run(download_package_from(
+			&reqwest::Client::new(),
+			options,
+			&server.mirror(),
+			sig_level,
+			package,
+			pkg_path,
+		))
+	}
+
+	#[test]
+	fn verify_package_signatures() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let signer = TestSigner::new(dir);
+		let options = download_options(dir);
+		let required = sig_level("Required TrustedOnly");
+
+		// A detached signature is downloaded from the mirror and saved next to the package.
+		let signature = signer.sign(BODY);
+		let server = TestServer::start(BODY, ServerBehaviour {
+			signature: Some(signature),
+			..Default::default()
+		});
+		let mut package = test_package();
+		package.pgpsig = None;
+		let pkg_path = dir.join(&package.filename);
+		let_assert!(Ok(()) = download_signed_package(&options, &server, &required, &package, &pkg_path));
+		assert!(std::fs::read(&pkg_path).unwrap() == BODY);
+		assert!(std::fs::read(dir.join("package.pkg.tar.zst.sig")).unwrap() == signature);
+		assert!(let Ok(()) = run(verify_local_signature(&options, &required, &package, &pkg_path)));
+
+		// A signature from the database is used without asking the mirror.
+		std::fs::remove_file(&pkg_path).unwrap();
+		std::fs::remove_file(dir.join("package.pkg.tar.zst.sig")).unwrap();
+		let server = TestServer::start(BODY, ServerBehaviour::default());
+		package.pgpsig = Some(base64::Engine::encode(&base64::engine::general_purpose::STANDARD, signer.sign(BODY)));
+		let_assert!(Ok(()) = download_signed_package(&options, &server, &required, &package, &pkg_path));
+		assert!(server.ranges() == [None]);
+		assert!(!dir.join("package.pkg.tar.zst.sig").exists());
+		assert!(let Ok(()) = run(verify_local_signature(&options, &required, &package, &pkg_path)));
+
+		// A local package with a bad signature is not accepted.
+		package.pgpsig = Some(base64::Engine::encode(
+			&base64::engine::general_purpose::STANDARD,
+			signer.sign(b"other data"),
+		));
+		assert!(let Err(SignatureError::Invalid(_)) = run(verify_local_signature(&options, &required, &package, &pkg_path)));
+		assert!(let Ok(()) = run(verify_local_signature(&options, &sig_level("Never"), &package, &pkg_path)));
+	}
+
+	#[test]
+	fn reject_bad_signatures() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let signer = TestSigner::new(dir);
+		let options = download_options(dir);
+		let mut package = test_package();
+		package.pgpsig = None;
+		let pkg_path = dir.join(&package.filename);
+
+		// A signature for different data is rejected and the package is quarantined.
+		let server = TestServer::start(BODY, ServerBehaviour {
+			signature: Some(signer.sign(b"other data")),
+			..Default::default()
+		});
+		let_assert!(Err(e) = download_signed_package(&options, &server, &sig_level("Optional"), &package, &pkg_path));
+		let_assert!(
+			DownloadError::Signature {
+				error: SignatureError::Invalid(_),
+				quarantined: Some(quarantined),
+				..
+			} = &e
+		);
+		assert!(quarantined == &server.quarantine_path(dir));
+		assert!(e.to_string().starts_with("Failed to verify the signature of package.pkg.tar.zst: "));
+		assert!(e.is_mirror_error());
+		assert!(!pkg_path.exists());
+		assert!(!dir.join("package.pkg.tar.zst.sig").exists());
+
+		// A missing signature is only accepted if signatures are optional.
+		let server = TestServer::start(BODY, ServerBehaviour::default());
+		let_assert!(Err(e) = download_signed_package(&options, &server, &sig_level("Required"), &package, &pkg_path));
+		assert!(let DownloadError::Signature { error: SignatureError::Missing, .. } = &e);
+		assert!(e.is_mirror_error());
+		assert!(!pkg_path.exists());
+		let_assert!(Ok(()) = download_signed_package(&options, &server, &sig_level("Optional"), &package, &pkg_path));
+		assert!(pkg_path.exists());
+		assert!(!dir.join("package.pkg.tar.zst.sig").exists());
+
+		// A bad signature from the database is not the fault of the mirror.
+		std::fs::remove_file(&pkg_path).unwrap();
+		package.pgpsig = Some(base64::Engine::encode(
+			&base64::engine::general_purpose::STANDARD,
+			signer.sign(b"other data"),
+		));
+		let_assert!(Err(e) = download_signed_package(&options, &server, &sig_level("Required"), &package, &pkg_path));
+		assert!(let DownloadError::Signature { error: SignatureError::Invalid(_), from_mirror: false, .. } = &e);
+		assert!(!e.is_mirror_error());
+	}
 }
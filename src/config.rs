@@ -0,0 +1,656 @@
+//! Parsing of `pacman.conf` files.
+//!
+//! Only the settings needed to locate repositories are interpreted:
+//! the `Architecture`, `SigLevel` and `GPGDir` options, and the `Server` and `SigLevel` settings of repositories.
+//! All other directives are accepted and ignored, like pacman does for directives it does not recognize.
+
+use std::path::{Path, PathBuf};
+
+use crate::parse::partition;
+
+/// The maximum nesting depth of `Include` directives, the same limit pacman uses.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// The default GnuPG home directory of pacman.
+pub const DEFAULT_GPG_DIR: &str = "/etc/pacman.d/gnupg/";
+
+/// The repositories and relevant options from a `pacman.conf` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PacmanConfig {
+	/// The architectures from the `Architecture` option, with `auto` replaced by the host architecture.
+	///
+	/// The first architecture is used to substitute `$arch` in server URLs.
+	pub architectures: Vec<String>,
+
+	/// The default signature level for repositories.
+	pub sig_level: SigLevel,
+
+	/// The GnuPG home directory with the keyring used to verify signatures.
+	pub gpg_dir: PathBuf,
+
+	/// The repositories in the order they appear in the configuration.
+	pub repositories: Vec<RepositoryConfig>,
+}
+
+/// A repository section from a `pacman.conf` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RepositoryConfig {
+	/// The name of the repository.
+	pub name: String,
+
+	/// The servers of the repository in order of preference, with `$repo` and `$arch` substituted.
+	pub servers: Vec<String>,
+
+	/// The signature level of the repository, combined with the global signature level.
+	pub sig_level: SigLevel,
+}
+
+/// The signature verification requirements for packages and databases.
+///
+/// The [`Default`] is the same as the default of pacman: `Required DatabaseOptional`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SigLevel {
+	/// Whether package signatures are checked.
+	pub package: SignatureRequirement,
+
+	/// Which keys are accepted for package signatures.
+	pub package_trust: SignatureTrust,
+
+	/// Whether database signatures are checked.
+	pub database: SignatureRequirement,
+
+	/// Which keys are accepted for database signatures.
+	pub database_trust: SignatureTrust,
+}
+
+/// Whether signatures are checked.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SignatureRequirement {
+	/// Signatures are not checked at all.
+	Never,
+
+	/// Signatures are checked if they are present.
+	Optional,
+
+	/// Signatures must be present and valid.
+	Required,
+}
+
+/// Which keys are accepted for signatures.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SignatureTrust {
+	/// Only signatures from fully trusted keys are accepted.
+	TrustedOnly,
+
+	/// Signatures from any known key are accepted.
+	TrustAll,
+}
+
+/// Error when parsing a [`SigLevel`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SigLevelFromStrError {
+	InvalidOption(String),
+}
+
+/// Error when reading a `pacman.conf` file.
+#[derive(Debug)]
+pub enum ReadConfigError {
+	Read(PathBuf, std::io::Error),
+	Parse(ConfigParseError),
+}
+
+/// Error in the contents of a `pacman.conf` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfigParseError {
+	/// The file that contains the error.
+	pub path: PathBuf,
+
+	/// The line number of the error, starting at 1.
+	pub line: usize,
+
+	/// The error message.
+	pub message: String,
+}
+
+impl ConfigParseError {
+	fn new(path: &Path, line: usize, message: impl std::fmt::Display) -> Self {
+		Self {
+			path: path.into(),
+			line,
+			message: message.to_string(),
+		}
+	}
+}
+
+impl PacmanConfig {
+	/// Read a `pacman.conf` file, including all files it includes.
+	pub fn read(path: impl AsRef<Path>) -> Result<Self, ReadConfigError> {
+		let path = path.as_ref();
+		let data = read_config_file(path)?;
+		Self::parse(&data, path)
+	}
+
+	/// Parse the contents of a `pacman.conf` file.
+	///
+	/// The path is used in error messages.
+	/// Included files are read from the file system.
+	pub fn parse(data: &str, path: impl AsRef<Path>) -> Result<Self, ReadConfigError> {
+		let mut parser = Parser::default();
+		parser.parse(data, path.as_ref(), 0)?;
+		Ok(parser.finish())
+	}
+}
+
+impl RepositoryConfig {
+	/// Get the URLs of the database archive of the repository, one for each server.
+	pub fn database_urls(&self) -> impl Iterator<Item = String> + '_ {
+		self.servers
+			.iter()
+			.map(move |server| format!("{}/{}.db", server.trim_end_matches('/'), self.name))
+	}
+}
+
+impl SigLevel {
+	/// Apply the options of a `SigLevel` directive on top of this signature level.
+	///
+	/// Options that only apply to packages or databases can be prefixed with `Package` or `Database`.
+	/// Settings that are not mentioned keep their current value.
+	pub fn with_overrides(mut self, input: &str) -> Result<Self, SigLevelFromStrError> {
+		for option in input.split_whitespace() {
+			let (package, database, setting) = if let Some(setting) = option.strip_prefix("Package") {
+				(true, false, setting)
+			} else if let Some(setting) = option.strip_prefix("Database") {
+				(false, true, setting)
+			} else {
+				(true, true, option)
+			};
+
+			match setting {
+				"Never" | "Optional" | "Required" => {
+					let requirement = match setting {
+						"Never" => SignatureRequirement::Never,
+						"Optional" => SignatureRequirement::Optional,
+						_ => SignatureRequirement::Required,
+					};
+					if package {
+						self.package = requirement;
+					}
+					if database {
+						self.database = requirement;
+					}
+				},
+				"TrustedOnly" | "TrustAll" => {
+					let trust = match setting {
+						"TrustedOnly" => SignatureTrust::TrustedOnly,
+						_ => SignatureTrust::TrustAll,
+					};
+					if package {
+						self.package_trust = trust;
+					}
+					if database {
+						self.database_trust = trust;
+					}
+				},
+				_ => return Err(SigLevelFromStrError::InvalidOption(option.into())),
+			}
+		}
+		Ok(self)
+	}
+}
+
+impl Default for SigLevel {
+	fn default() -> Self {
+		Self {
+			package: SignatureRequirement::Required,
+			package_trust: SignatureTrust::TrustedOnly,
+			database: SignatureRequirement::Optional,
+			database_trust: SignatureTrust::TrustedOnly,
+		}
+	}
+}
+
+/// Get the architecture of the host in the form pacman uses, for `Architecture = auto`.
+pub fn host_architecture() -> &'static str {
+	match std::env::consts::ARCH {
+		"x86" => "i686",
+		arch => arch,
+	}
+}
+
+/// The section of the configuration file that is being parsed.
+#[derive(Copy, Clone)]
+enum Section {
+	None,
+	Options,
+	Repository(usize),
+}
+
+/// A repository section, before variables are substituted and signature levels are combined.
+struct RawRepository {
+	name: String,
+	servers: Vec<String>,
+	sig_levels: Vec<String>,
+}
+
+/// State of the parser, shared between the main file and included files.
+struct Parser {
+	section: Section,
+	architectures: Vec<String>,
+	sig_level: SigLevel,
+	gpg_dir: Option<PathBuf>,
+	repositories: Vec<RawRepository>,
+}
+
+impl Default for Parser {
+	fn default() -> Self {
+		Self {
+			section: Section::None,
+			architectures: Vec::new(),
+			sig_level: SigLevel::default(),
+			gpg_dir: None,
+			repositories: Vec::new(),
+		}
+	}
+}
+
+impl Parser {
+	fn parse(&mut self, data: &str, path: &Path, depth: usize) -> Result<(), ReadConfigError> {
+		for (i, line) in data.lines().enumerate() {
+			let line_number = i + 1;
+			let error = |message: &dyn std::fmt::Display| ConfigParseError::new(path, line_number, message);
+
+			let line = match partition(line, '#') {
+				Some((line, _comment)) => line.trim(),
+				None => line.trim(),
+			};
+			if line.is_empty() {
+				continue;
+			}
+
+			if let Some(name) = line.strip_prefix('[') {
+				let name = name
+					.strip_suffix(']')
+					.ok_or_else(|| error(&"section header is missing a closing bracket"))?;
+				self.start_section(name.trim()).map_err(|e| error(&e))?;
+				continue;
+			}
+
+			let (key, value) = match partition(line, '=') {
+				Some((key, value)) => (key.trim(), Some(value.trim())),
+				None => (line, None),
+			};
+			let value = || match value {
+				Some(value) if !value.is_empty() => Ok(value),
+				_ => Err(error(&format_args!("directive {} needs a value", key))),
+			};
+
+			match (self.section, key) {
+				(Section::None, _) => return Err(error(&format_args!("directive {} is not in a section", key)).into()),
+				(_, "Include") => {
+					if depth >= MAX_INCLUDE_DEPTH {
+						return Err(error(&"too many levels of nested includes").into());
+					}
+					for include in expand_include(value()?).map_err(|e| error(&e))? {
+						let data = read_config_file(&include)?;
+						self.parse(&data, &include, depth + 1)?;
+					}
+				},
+				(Section::Options, "Architecture") => self.architectures.extend(value()?.split_whitespace().map(String::from)),
+				(Section::Options, "SigLevel") => {
+					self.sig_level = self.sig_level.with_overrides(value()?).map_err(|e| error(&e))?;
+				},
+				(Section::Options, "GPGDir") => self.gpg_dir = Some(value()?.into()),
+				(Section::Repository(index), "Server") => self.repositories[index].servers.push(value()?.into()),
+				(Section::Repository(index), "SigLevel") => {
+					let value = value()?;
+					SigLevel::default().with_overrides(value).map_err(|e| error(&e))?;
+					self.repositories[index].sig_levels.push(value.into());
+				},
+				(_, _) => (),
+			}
+		}
+		Ok(())
+	}
+
+	fn start_section(&mut self, name: &str) -> Result<(), String> {
+		match name {
+			"" => return Err("empty section name".into()),
+			"options" => self.section = Section::Options,
+			"local" => return Err("the repository name local is reserved".into()),
+			name => {
+				if self.repositories.iter().any(|x| x.name == name) {
+					return Err(format!("duplicate repository: {}", name));
+				}
+				self.section = Section::Repository(self.repositories.len());
+				self.repositories.push(RawRepository {
+					name: name.into(),
+					servers: Vec::new(),
+					sig_levels: Vec::new(),
+				});
+			},
+		}
+		Ok(())
+	}
+
+	/// Substitute variables and combine signature levels, now that all options are known.
+	fn finish(self) -> PacmanConfig {
+		let mut architectures: Vec<String> = self
+			.architectures
+			.into_iter()
+			.map(|arch| if arch == "auto" { host_architecture().into() } else { arch })
+			.collect();
+		if architectures.is_empty() {
+			architectures.push(host_architecture().into());
+		}
+
+		let global_sig_level = self.sig_level;
+		let repositories = self
+			.repositories
+			.into_iter()
+			.map(|repository| {
+				let servers = repository
+					.servers
+					.iter()
+					.map(|server| server.replace("$repo", &repository.name).replace("$arch", &architectures[0]))
+					.collect();
+				let sig_level = repository
+					.sig_levels
+					.iter()
+					.fold(global_sig_level, |sig_level, value| sig_level.with_overrides(value).unwrap());
+				RepositoryConfig {
+					name: repository.name,
+					servers,
+					sig_level,
+				}
+			})
+			.collect();
+
+		PacmanConfig {
+			architectures,
+			sig_level: global_sig_level,
+			gpg_dir: self.gpg_dir.unwrap_or_else(|| DEFAULT_GPG_DIR.into()),
+			repositories,
+		}
+	}
+}
+
+fn read_config_file(path: &Path) -> Result<String, ReadConfigError> {
+	std::fs::read_to_string(path).map_err(|e| ReadConfigError::Read(path.into(), e))
+}
+
+/// Expand the wildcards in the file name of an included path.
+///
+/// Like pacman, a pattern that matches no files is not an error.
+/// Only the last path component may contain wildcards.
+fn expand_include(pattern: &str) -> Result<Vec<PathBuf>, String> {
+	let pattern = Path::new(pattern);
+	let file_pattern = match pattern.file_name().and_then(|x| x.to_str()) {
+		Some(x) if x.contains(['*', '?']) => x,
+		_ => return Ok(vec![pattern.into()]),
+	};
+	let dir = match pattern.parent() {
+		Some(x) if !x.as_os_str().is_empty() => x,
+		_ => Path::new("."),
+	};
+	if dir.to_str().map_or(false, |x| x.contains(['*', '?'])) {
+		return Err(format!(
+			"wildcards are only supported in the file name of included files: {}",
+			pattern.display()
+		));
+	}
+
+	let entries = match std::fs::read_dir(dir) {
+		Ok(x) => x,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(format!("failed to read directory {}: {}", dir.display(), e)),
+	};
+	let mut paths = Vec::new();
+	for entry in entries {
+		let entry = entry.map_err(|e| format!("failed to read directory {}: {}", dir.display(), e))?;
+		let name = entry.file_name();
+		if let Some(name) = name.to_str() {
+			if !name.starts_with('.') && wildcard_match(file_pattern.as_bytes(), name.as_bytes()) {
+				paths.push(entry.path());
+			}
+		}
+	}
+	paths.sort();
+	Ok(paths)
+}
+
+/// Match a name against a pattern with `*` and `?` wildcards.
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+	match pattern.split_first() {
+		None => name.is_empty(),
+		Some((b'*', rest)) => (0..=name.len()).any(|i| wildcard_match(rest, &name[i..])),
+		Some((b'?', rest)) => !name.is_empty() && wildcard_match(rest, &name[1..]),
+		Some((c, rest)) => name.first() == Some(c) && wildcard_match(rest, &name[1..]),
+	}
+}
+
+impl std::str::FromStr for SigLevel {
+	type Err = SigLevelFromStrError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		Self::default().with_overrides(input)
+	}
+}
+
+impl std::fmt::Display for SignatureRequirement {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Never => write!(f, "Never"),
+			Self::Optional => write!(f, "Optional"),
+			Self::Required => write!(f, "Required"),
+		}
+	}
+}
+
+impl std::fmt::Display for SignatureTrust {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::TrustedOnly => write!(f, "TrustedOnly"),
+			Self::TrustAll => write!(f, "TrustAll"),
+		}
+	}
+}
+
+impl std::fmt::Display for SigLevel {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(
+			f,
+			"Package{} Package{} Database{} Database{}",
+			self.package, self.package_trust, self.database, self.database_trust
+		)
+	}
+}
+
+impl From<ConfigParseError> for ReadConfigError {
+	fn from(other: ConfigParseError) -> Self {
+		Self::Parse(other)
+	}
+}
+
+impl std::error::Error for SigLevelFromStrError {}
+impl std::error::Error for ConfigParseError {}
+impl std::error::Error for ReadConfigError {}
+
+impl std::fmt::Display for SigLevelFromStrError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::InvalidOption(option) => write!(f, "invalid SigLevel option: {}", option),
+		}
+	}
+}
+
+impl std::fmt::Display for ConfigParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+	}
+}
+
+impl std::fmt::Display for ReadConfigError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Read(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+			Self::Parse(e) => e.fmt(f),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::{assert, let_assert};
+
+	#[test]
+	fn parse_config() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let mirrorlist = dir.join("mirrorlist");
+		std::fs::write(
+			&mirrorlist,
+			"## Worldwide\n\
+			Server = https://geo.mirror.pkgbuild.com/$repo/os/$arch\n\
+			#Server = https://disabled.example.com/$repo/os/$arch\n\
+			Server = https://mirror.example.com/archlinux/$repo/os/$arch/ # trailing comment\n",
+		)
+		.unwrap();
+
+		let config = format!(
+			"[options]\n\
+			HoldPkg = pacman glibc\n\
+			Architecture = x86_64\n\
+			Color\n\
+			SigLevel = Required DatabaseOptional\n\
+			\n\
+			[core]\n\
+			Include = {0}\n\
+			\n\
+			[extra]\n\
+			Include = {0}\n\
+			\n\
+			[custom]\n\
+			SigLevel = Optional TrustAll\n\
+			Server = file:///srv/repo/$repo/$arch\n",
+			mirrorlist.display()
+		);
+		let_assert!(Ok(config) = PacmanConfig::parse(&config, dir.join("pacman.conf")));
+		assert!(config.architectures == ["x86_64"]);
+		assert!(config.sig_level == SigLevel::default());
+		assert!(config.gpg_dir == Path::new(DEFAULT_GPG_DIR));
+
+		let names: Vec<_> = config.repositories.iter().map(|x| x.name.as_str()).collect();
+		assert!(names == ["core", "extra", "custom"]);
+		assert!(
+			config.repositories[1].servers
+				== [
+					"https://geo.mirror.pkgbuild.com/extra/os/x86_64",
+					"https://mirror.example.com/archlinux/extra/os/x86_64/"
+				]
+		);
+		assert!(
+			config.repositories[1].database_urls().collect::<Vec<_>>()
+				== [
+					"https://geo.mirror.pkgbuild.com/extra/os/x86_64/extra.db",
+					"https://mirror.example.com/archlinux/extra/os/x86_64/extra.db"
+				]
+		);
+		assert!(config.repositories[2].servers == ["file:///srv/repo/custom/x86_64"]);
+		assert!(config.repositories[2].sig_level.to_string() == "PackageOptional PackageTrustAll DatabaseOptional DatabaseTrustAll");
+	}
+
+	#[test]
+	fn architecture_auto() {
+		let_assert!(
+			Ok(config) = PacmanConfig::parse(
+				"[options]\nArchitecture = auto\n[core]\nServer = https://example.com/$arch\n",
+				"pacman.conf"
+			)
+		);
+		assert!(config.architectures == [host_architecture()]);
+		assert!(config.repositories[0].servers == [format!("https://example.com/{}", host_architecture())]);
+
+		let_assert!(Ok(config) = PacmanConfig::parse("[core]\nServer = https://example.com/$arch\n", "pacman.conf"));
+		assert!(config.architectures == [host_architecture()]);
+
+		let_assert!(Ok(config) = PacmanConfig::parse("[options]\nArchitecture = x86_64_v3 x86_64\n", "pacman.conf"));
+		assert!(config.architectures == ["x86_64_v3", "x86_64"]);
+	}
+
+	#[test]
+	fn options_apply_to_earlier_repositories() {
+		let config =
+			"[core]\nServer = https://example.com/$arch/$repo\nSigLevel = PackageOptional\n[options]\nArchitecture = aarch64\nSigLevel = TrustAll\nGPGDir = /srv/keyring\n";
+		let_assert!(Ok(config) = PacmanConfig::parse(config, "pacman.conf"));
+		assert!(config.repositories[0].servers == ["https://example.com/aarch64/core"]);
+		assert!(config.repositories[0].sig_level.package == SignatureRequirement::Optional);
+		assert!(config.repositories[0].sig_level.package_trust == SignatureTrust::TrustAll);
+		assert!(config.repositories[0].sig_level.database == SignatureRequirement::Optional);
+		assert!(config.gpg_dir == Path::new("/srv/keyring"));
+	}
+
+	#[test]
+	fn parse_sig_level() {
+		assert!("".parse::<SigLevel>() == Ok(SigLevel::default()));
+		let_assert!(Ok(sig_level) = "Never".parse::<SigLevel>());
+		assert!(sig_level.package == SignatureRequirement::Never);
+		assert!(sig_level.database == SignatureRequirement::Never);
+		let_assert!(Ok(sig_level) = "PackageTrustAll DatabaseRequired".parse::<SigLevel>());
+		assert!(sig_level.package_trust == SignatureTrust::TrustAll);
+		assert!(sig_level.database_trust == SignatureTrust::TrustedOnly);
+		assert!(sig_level.database == SignatureRequirement::Required);
+		assert!("Sometimes".parse::<SigLevel>() == Err(SigLevelFromStrError::InvalidOption("Sometimes".into())));
+		assert!("PackageTrustNone".parse::<SigLevel>() == Err(SigLevelFromStrError::InvalidOption("PackageTrustNone".into())));
+	}
+
+	#[test]
+	fn include_wildcards() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		std::fs::create_dir(dir.join("repos.d")).unwrap();
+		std::fs::write(dir.join("repos.d/20-extra.conf"), "[extra]\nServer = https://example.com/$repo\n").unwrap();
+		std::fs::write(dir.join("repos.d/10-core.conf"), "[core]\nServer = https://example.com/$repo\n").unwrap();
+		std::fs::write(dir.join("repos.d/README"), "not a config file").unwrap();
+
+		let config = format!("[options]\nInclude = {}\n", dir.join("repos.d/*.conf").display());
+		let_assert!(Ok(config) = PacmanConfig::parse(&config, "pacman.conf"));
+		let names: Vec<_> = config.repositories.iter().map(|x| x.name.as_str()).collect();
+		assert!(names == ["core", "extra"]);
+
+		let config = format!("[options]\nInclude = {}\n", dir.join("missing.d/*.conf").display());
+		let_assert!(Ok(config) = PacmanConfig::parse(&config, "pacman.conf"));
+		assert!(config.repositories.is_empty());
+	}
+
+	#[test]
+	fn include_errors() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let missing = dir.join("missing");
+		let_assert!(Err(ReadConfigError::Read(path, _)) = PacmanConfig::parse(&format!("[core]\nInclude = {}\n", missing.display()), "pacman.conf"));
+		assert!(path == missing);
+
+		let recursive = dir.join("recursive.conf");
+		std::fs::write(&recursive, format!("[options]\nInclude = {}\n", recursive.display())).unwrap();
+		let_assert!(Err(ReadConfigError::Parse(e)) = PacmanConfig::read(&recursive));
+		assert!(e.message == "too many levels of nested includes");
+	}
+
+	#[test]
+	fn parse_errors() {
+		let parse = |data: &str| match PacmanConfig::parse(data, "pacman.conf") {
+			Ok(_) => panic!("expected an error"),
+			Err(ReadConfigError::Read(..)) => panic!("expected a parse error"),
+			Err(ReadConfigError::Parse(e)) => e.to_string(),
+		};
+		assert!(parse("Server = https://example.com\n") == "pacman.conf:1: directive Server is not in a section");
+		assert!(parse("[core\n") == "pacman.conf:1: section header is missing a closing bracket");
+		assert!(parse("[]\n") == "pacman.conf:1: empty section name");
+		assert!(parse("[local]\n") == "pacman.conf:1: the repository name local is reserved");
+		assert!(parse("[core]\n[extra]\n[core]\n") == "pacman.conf:3: duplicate repository: core");
+		assert!(parse("[core]\nServer\n") == "pacman.conf:2: directive Server needs a value");
+		assert!(parse("[core]\nServer =\n") == "pacman.conf:2: directive Server needs a value");
+		assert!(parse("[options]\n\nSigLevel = Always\n") == "pacman.conf:3: invalid SigLevel option: Always");
+	}
+}
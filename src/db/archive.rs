@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::deserializer::Deserializer;
+use super::{DatabasePackage, ParseError, ReadOptions};
+
+/// Compression formats supported for repository database archives.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Compression {
+	None,
+	Gzip,
+	Xz,
+	Zstd,
+}
+
+impl Compression {
+	/// Detect the compression format of an archive from the magic bytes at the start of the data.
+	///
+	/// Returns `None` if the data is not recognized as a compressed or uncompressed tar archive.
+	pub fn detect(data: &[u8]) -> Option<Self> {
+		if data.starts_with(&[0x1F, 0x8B]) {
+			Some(Self::Gzip)
+		} else if data.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+			Some(Self::Xz)
+		} else if data.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+			Some(Self::Zstd)
+		} else if data.get(257..262) == Some(b"ustar") {
+			Some(Self::None)
+		} else {
+			None
+		}
+	}
+
+	/// Wrap a reader in a decoder for the compression format.
+	pub(crate) fn decoder<'a, R: Read + 'a>(self, reader: R) -> std::io::Result<Box<dyn Read + 'a>> {
+		match self {
+			Self::None => Ok(Box::new(reader)),
+			Self::Gzip => Ok(Box::new(flate2::read::MultiGzDecoder::new(reader))),
+			Self::Xz => Ok(Box::new(xz2::read::XzDecoder::new_multi_decoder(reader))),
+			Self::Zstd => Ok(Box::new(zstd::stream::read::Decoder::new(reader)?)),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum ReadDbArchiveError {
+	Open(PathBuf, std::io::Error),
+	UnknownCompression,
+	Read(std::io::Error),
+	MissingDesc(String),
+	Parse(ParseError),
+}
+
+/// The raw files of a single package entry in a database archive.
+#[derive(Default)]
+struct RawEntry {
+	desc: Option<Vec<u8>>,
+	depends: Option<Vec<u8>>,
+	files: Option<Vec<u8>>,
+}
+
+/// Read package information from a repository database archive on disk.
+///
+/// This can read both `.db` and `.files` archives.
+/// For `.files` archives, the file list of each package is stored in [`DatabasePackage::files`].
+///
+/// The archive may be uncompressed, or compressed with gzip, xz or zstd.
+/// The compression format is detected from the file contents, not from the file name.
+pub fn read_db_archive(path: impl AsRef<Path>) -> Result<Vec<DatabasePackage>, ReadDbArchiveError> {
+	read_db_archive_with_options(path, &ReadOptions::default())
+}
+
+/// Read package information from a repository database archive on disk with custom options.
+pub fn read_db_archive_with_options(path: impl AsRef<Path>, options: &ReadOptions) -> Result<Vec<DatabasePackage>, ReadDbArchiveError> {
+	let path = path.as_ref();
+	let data = std::fs::read(path).map_err(|e| ReadDbArchiveError::Open(path.into(), e))?;
+	read_db_archive_bytes(&data, Some(&path.display().to_string()), options)
+}
+
+/// Read package information from an in-memory repository database archive.
+///
+/// The `source` is used to give more context in parse errors, and is typically the path or URL of the archive.
+pub fn read_db_archive_bytes(data: &[u8], source: Option<&str>, options: &ReadOptions) -> Result<Vec<DatabasePackage>, ReadDbArchiveError> {
+	let compression = Compression::detect(data).ok_or(ReadDbArchiveError::UnknownCompression)?;
+	let decoder = compression.decoder(data).map_err(ReadDbArchiveError::Read)?;
+	let mut archive = tar::Archive::new(decoder);
+
+	// Collect the raw files first: entries in the archive are not guaranteed to be grouped per package.
+	let mut entries = BTreeMap::<String, RawEntry>::new();
+	for entry in archive.entries().map_err(ReadDbArchiveError::Read)? {
+		let mut entry = entry.map_err(ReadDbArchiveError::Read)?;
+		if !entry.header().entry_type().is_file() {
+			continue;
+		}
+
+		let path = entry.path().map_err(ReadDbArchiveError::Read)?;
+		let path = path.to_string_lossy();
+		let (dir_name, file_name) = match crate::parse::rpartition(path.trim_start_matches("./"), '/') {
+			Some(x) => x,
+			None => continue,
+		};
+		let dir_name = dir_name.to_string();
+
+		let slot = match file_name {
+			"desc" => &mut entries.entry(dir_name).or_default().desc,
+			"depends" => &mut entries.entry(dir_name).or_default().depends,
+			"files" => &mut entries.entry(dir_name).or_default().files,
+			_ => continue,
+		};
+
+		let mut buffer = Vec::with_capacity(entry.header().size().unwrap_or(0) as usize);
+		entry.read_to_end(&mut buffer).map_err(ReadDbArchiveError::Read)?;
+		*slot = Some(buffer);
+	}
+
+	let mut packages = Vec::with_capacity(entries.len());
+	for (dir_name, entry) in entries {
+		let entry_source = |file_name: &str| match source {
+			Some(source) => format!("{}:{}/{}", source, dir_name, file_name),
+			None => format!("{}/{}", dir_name, file_name),
+		};
+
+		let desc = entry.desc.ok_or_else(|| ReadDbArchiveError::MissingDesc(dir_name.clone()))?;
+		let mut package = parse_bytes::<DatabasePackage>(&desc, entry_source("desc"))?;
+		if let Some(depends) = entry.depends {
+			package.add_depends(parse_bytes(&depends, entry_source("depends"))?);
+		}
+		if let Some(files) = entry.files {
+			package.files = Some(parse_bytes(&files, entry_source("files"))?);
+		}
+		match source {
+			Some(source) => options.check(&package, format_args!("{}:{}", source, dir_name))?,
+			None => options.check(&package, &dir_name)?,
+		}
+		packages.push(package);
+	}
+
+	Ok(packages)
+}
+
+/// Parse a value from bytes, using `source` in error messages.
+fn parse_bytes<T: serde::de::DeserializeOwned>(data: &[u8], source: String) -> Result<T, ParseError> {
+	let mut deserializer = Deserializer::from_bytes(data, Some(source));
+	T::deserialize(&mut deserializer)
+}
+
+impl From<ParseError> for ReadDbArchiveError {
+	fn from(other: ParseError) -> Self {
+		Self::Parse(other)
+	}
+}
+
+impl std::error::Error for ReadDbArchiveError {}
+
+impl std::fmt::Display for ReadDbArchiveError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Open(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+			Self::UnknownCompression => write!(f, "unrecognized archive format"),
+			Self::Read(e) => write!(f, "failed to read archive: {}", e),
+			Self::MissingDesc(name) => write!(f, "missing desc file for database entry {}", name),
+			Self::Parse(e) => e.fmt(f),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::{assert, let_assert};
+	use std::io::Write;
+
+	const PACKAGE_DESC: &[u8] = include_bytes!("../../tests/database-package/desc");
+	const PACKAGE_DEPENDS: &[u8] = include_bytes!("../../tests/database-package/depends");
+	const PACKAGE_FILES: &[u8] = include_bytes!("../../tests/database-package/files");
+
+	fn make_tar() -> Vec<u8> {
+		let mut builder = tar::Builder::new(Vec::new());
+		for (path, data) in &[
+			("linux-aarch64-5.8.9-2/desc", PACKAGE_DESC),
+			("linux-aarch64-5.8.9-2/depends", PACKAGE_DEPENDS),
+			("linux-aarch64-5.8.9-2/files", PACKAGE_FILES),
+		] {
+			let mut header = tar::Header::new_gnu();
+			header.set_size(data.len() as u64);
+			header.set_mode(0o644);
+			header.set_cksum();
+			builder.append_data(&mut header, path, *data).unwrap();
+		}
+		builder.into_inner().unwrap()
+	}
+
+	#[track_caller]
+	fn check_archive(data: &[u8], compression: Compression) {
+		assert!(Compression::detect(data) == Some(compression));
+		let_assert!(Ok(packages) = read_db_archive_bytes(data, None, &ReadOptions::default()));
+		assert!(packages.len() == 1);
+		assert!(packages[0].name == "linux-aarch64");
+		assert!(packages[0].depends.len() == 4);
+		assert!(packages[0].provides.len() == 2);
+		let_assert!(Some(files) = &packages[0].files);
+		assert!(files.files.len() == 13);
+	}
+
+	#[test]
+	fn read_uncompressed() {
+		check_archive(&make_tar(), Compression::None);
+	}
+
+	#[test]
+	fn read_gzip() {
+		let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+		encoder.write_all(&make_tar()).unwrap();
+		check_archive(&encoder.finish().unwrap(), Compression::Gzip);
+	}
+
+	#[test]
+	fn read_xz() {
+		let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+		encoder.write_all(&make_tar()).unwrap();
+		check_archive(&encoder.finish().unwrap(), Compression::Xz);
+	}
+
+	#[test]
+	fn read_zstd() {
+		let data = zstd::stream::encode_all(make_tar().as_slice(), 0).unwrap();
+		check_archive(&data, Compression::Zstd);
+	}
+
+	#[test]
+	fn unknown_compression() {
+		let_assert!(Err(ReadDbArchiveError::UnknownCompression) = read_db_archive_bytes(b"not an archive", None, &ReadOptions::default()));
+	}
+}
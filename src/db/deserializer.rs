@@ -1,5 +1,6 @@
 use serde::de;
 use serde::de::Error as _;
+use serde::de::IntoDeserializer;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
@@ -70,6 +71,25 @@ pub struct Error {
 }
 
 impl Error {
+	/// Create a new error with a message and optional source and line number.
+	pub(super) fn new(source: Option<String>, line: Option<u32>, message: impl ToString) -> Self {
+		Self {
+			source,
+			line,
+			message: message.to_string(),
+		}
+	}
+
+	/// The line number where the error occurred, if known.
+	pub fn line(&self) -> Option<u32> {
+		self.line
+	}
+
+	/// The error message, without source and line number.
+	pub fn message(&self) -> &str {
+		&self.message
+	}
+
 	fn with_source(mut self, source: Option<String>) -> Self {
 		self.source = source;
 		self
@@ -340,16 +360,18 @@ struct FieldDeserializer<'a, R> {
 impl<'de, R: BufRead> de::Deserializer<'de> for FieldDeserializer<'_, R> {
 	type Error = Error;
 
-	fn deserialize_any<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
-		Err(Error::custom(
-			"ALPM database field values are not self describing, so deserialize_any is not supported",
-		))
+	fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		// ALPM database field values are not self describing,
+		// so the best we can do is to treat every field as a list of strings.
+		if self.in_sequence {
+			self.deserialize_string(visitor)
+		} else {
+			visitor.visit_seq(self)
+		}
 	}
 
-	fn deserialize_ignored_any<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
-		Err(Error::custom(
-			"ALPM database field values are not self describing, so deserialize_ignored_any is not supported",
-		))
+	fn deserialize_ignored_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+		self.deserialize_any(visitor)
 	}
 
 	fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
@@ -469,11 +491,11 @@ impl<'de, R: BufRead> de::Deserializer<'de> for FieldDeserializer<'_, R> {
 	}
 
 	fn deserialize_enum<V: de::Visitor<'de>>(self, name: &str, variants: &[&str], visitor: V) -> Result<V::Value, Self::Error> {
-		// We only support value-less enums, so we simply check fo
+		// We only support value-less enums, so we simply check for a matching variant name.
 		let line: String = self.parent.read_value(name)?;
 		for &variant in variants {
 			if line == variant {
-				return visitor.visit_str(variant);
+				return self.parent.extend_error(visitor.visit_enum(variant.into_deserializer()));
 			}
 		}
 		Err(self.parent.error(format_args!("invalud enum variant for {}: {}", name, line)))
@@ -513,7 +535,7 @@ impl<'de, R: BufRead> de::SeqAccess<'de> for FieldDeserializer<'_, R> {
 			Ok(None)
 		} else {
 			let deserializer = FieldDeserializer {
-				parent: &mut self.parent,
+				parent: self.parent,
 				in_sequence: true,
 			};
 			Ok(Some(seed.deserialize(deserializer)?))
@@ -528,7 +550,8 @@ impl std::fmt::Display for Error {
 		match (&self.source, self.line) {
 			(Some(source), Some(line)) => write!(f, "{}:{}: {}", source, line, self.message),
 			(Some(source), None) => write!(f, "{}: {}", source, self.message),
-			_ => f.write_str(&self.message),
+			(None, Some(line)) => write!(f, "line {}: {}", line, self.message),
+			(None, None) => f.write_str(&self.message),
 		}
 	}
 }
@@ -581,4 +604,33 @@ mod test {
 			baz: true,
 		});
 	}
+
+	#[test]
+	#[rustfmt::skip]
+	fn flatten_unknown_fields() {
+		#[derive(Debug, Eq, PartialEq, Deserialize)]
+		#[serde(rename_all = "UPPERCASE")]
+		struct Test {
+			foo: String,
+			#[serde(flatten)]
+			extra: std::collections::BTreeMap<String, Vec<String>>,
+		}
+		let blob = [
+			"%FOO%",
+			"aap",
+			"",
+			"%BAR%",
+			"noot",
+			"mies",
+			"",
+			"%BAZ%",
+			"wim",
+		].join("\n");
+
+		let_assert!(Ok(parsed) = from_str::<Test>(&blob));
+		assert!(parsed.foo == "aap");
+		assert!(parsed.extra.len() == 2);
+		assert!(parsed.extra["BAR"] == ["noot", "mies"]);
+		assert!(parsed.extra["BAZ"] == ["wim"]);
+	}
 }
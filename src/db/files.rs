@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+use super::DatabasePackage;
+use crate::parse::partition;
+
+/// The contents of a `files` entry of a package in a `.files` database (or the local database).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct DatabasePackageFiles {
+	/// The files and directories in the package, relative to the root directory.
+	///
+	/// Directories have a trailing slash.
+	#[serde(default)]
+	pub files: Vec<String>,
+
+	/// The files that are backed up when they are modified.
+	#[serde(default)]
+	pub backup: Vec<BackupEntry>,
+}
+
+/// A backup entry, consisting of a path and the MD5 checksum of the original file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BackupEntry {
+	pub path: String,
+	pub md5sum: String,
+}
+
+/// Error when parsing a [`BackupEntry`] from a string.
+#[derive(Debug, Eq, PartialEq)]
+pub struct BackupEntryFromStrError;
+
+impl DatabasePackageFiles {
+	/// Check if the package contains a file or directory.
+	///
+	/// The path may be given with or without a leading slash.
+	/// Directories may be given with or without a trailing slash.
+	pub fn contains(&self, path: &str) -> bool {
+		let path = path.trim_start_matches('/');
+		let dir = path.trim_end_matches('/');
+		self.files.iter().any(|file| file == path || file.strip_suffix('/') == Some(dir))
+	}
+}
+
+impl DatabasePackage {
+	/// Check if the package owns a file or directory.
+	///
+	/// This always returns false if the file list of the package is not known.
+	pub fn owns_file(&self, path: &str) -> bool {
+		self.files.as_ref().map(|files| files.contains(path)).unwrap_or(false)
+	}
+}
+
+/// Find all packages that contain a file or directory.
+///
+/// Only packages that have a file list are considered.
+/// Note that directories are often owned by many packages.
+pub fn find_file_owners<'a>(packages: impl IntoIterator<Item = &'a DatabasePackage>, path: &str) -> Vec<&'a DatabasePackage> {
+	packages.into_iter().filter(|package| package.owns_file(path)).collect()
+}
+
+impl std::str::FromStr for BackupEntry {
+	type Err = BackupEntryFromStrError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let (path, md5sum) = partition(input, '\t').ok_or(BackupEntryFromStrError)?;
+		Ok(Self {
+			path: path.into(),
+			md5sum: md5sum.into(),
+		})
+	}
+}
+
+impl std::fmt::Display for BackupEntry {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}\t{}", self.path, self.md5sum)
+	}
+}
+
+impl std::error::Error for BackupEntryFromStrError {}
+
+impl std::fmt::Display for BackupEntryFromStrError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "missing tab between path and checksum in backup entry")
+	}
+}
+
+impl<'de> Deserialize<'de> for BackupEntry {
+	fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct Visitor;
+		impl<'de> serde::de::Visitor<'de> for Visitor {
+			type Value = BackupEntry;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "a backup entry with a path and checksum separated by a tab")
+			}
+
+			fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+				value.parse().map_err(E::custom)
+			}
+		}
+
+		deserializer.deserialize_str(Visitor)
+	}
+}
+
+impl Serialize for BackupEntry {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
@@ -0,0 +1,343 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::{from_file, take_xdata, to_string, DatabasePackageFiles, ExtraRef, ParseError, ReadDbDirError, SerializeError, XdataRef};
+use crate::package::{Dependency, ExtraData, OptionalDependency, Provides};
+use crate::version::PackageVersion;
+
+/// The location of the local database, relative to the root directory.
+pub const LOCAL_DB_PATH: &str = "var/lib/pacman/local";
+
+/// A package entry from the local database of installed packages.
+///
+/// The fields are in the same order as pacman writes them in the `desc` file.
+/// Like [`DatabasePackage`](super::DatabasePackage), the type does not implement [`Serialize`]:
+/// use [`LocalPackage::to_desc`] to serialize it to the ALPM database format.
+#[derive(Debug, Deserialize)]
+#[serde(remote = "Self")]
+#[serde(rename_all = "UPPERCASE")]
+pub struct LocalPackage {
+	pub name: String,
+
+	pub version: PackageVersion,
+
+	pub base: Option<String>,
+
+	#[serde(rename = "DESC")]
+	#[serde(default)]
+	pub description: String,
+
+	pub url: Option<String>,
+
+	pub arch: String,
+
+	#[serde(rename = "BUILDDATE")]
+	pub build_date: i64,
+
+	#[serde(rename = "INSTALLDATE")]
+	pub install_date: i64,
+
+	pub packager: String,
+
+	#[serde(rename = "SIZE")]
+	#[serde(default)]
+	pub installed_size: u64,
+
+	#[serde(default)]
+	pub reason: InstallReason,
+
+	#[serde(default)]
+	pub groups: Vec<String>,
+
+	#[serde(rename = "LICENSE")]
+	#[serde(default)]
+	pub licenses: Vec<String>,
+
+	#[serde(default)]
+	pub validation: Vec<Validation>,
+
+	#[serde(default)]
+	pub replaces: Vec<String>,
+
+	#[serde(default)]
+	pub depends: Vec<Dependency>,
+
+	#[serde(default)]
+	pub optdepends: Vec<OptionalDependency>,
+
+	#[serde(default)]
+	pub conflicts: Vec<Dependency>,
+
+	#[serde(default)]
+	pub provides: Vec<Provides>,
+
+	/// The valid `%XDATA%` entries.
+	///
+	/// Malformed entries are kept in [`LocalPackage::extra`] under the `XDATA` key.
+	#[serde(default, skip_deserializing)]
+	pub xdata: Vec<ExtraData>,
+
+	/// The file list of the package, if the `files` entry exists.
+	#[serde(skip)]
+	pub files: Option<DatabasePackageFiles>,
+
+	/// True if the local database has an `mtree` file for the package.
+	#[serde(skip)]
+	pub has_mtree: bool,
+
+	/// Unknown fields, by key (without the surrounding `%` signs).
+	#[serde(flatten)]
+	pub extra: BTreeMap<String, Vec<String>>,
+}
+
+/// The reason a package was installed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InstallReason {
+	/// The package was explicitly installed by the user.
+	Explicit,
+
+	/// The package was installed as dependency of another package.
+	Dependency,
+}
+
+/// The method that was used to validate a package before it was installed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Validation {
+	None,
+	Md5,
+	Sha256,
+	Pgp,
+}
+
+impl InstallReason {
+	/// Check if the package was explicitly installed.
+	pub fn is_explicit(&self) -> bool {
+		*self == Self::Explicit
+	}
+}
+
+impl Default for InstallReason {
+	fn default() -> Self {
+		Self::Explicit
+	}
+}
+
+impl<'de> Deserialize<'de> for LocalPackage {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let mut package = Self::deserialize(deserializer)?;
+		package.xdata = take_xdata(&mut package.extra);
+		Ok(package)
+	}
+}
+
+/// Borrowed version of the `desc` file of a [`LocalPackage`] for serialization.
+#[derive(Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct LocalPackageDescRef<'a> {
+	name: &'a str,
+	version: &'a PackageVersion,
+	base: Option<&'a str>,
+	#[serde(rename = "DESC")]
+	description: &'a str,
+	url: Option<&'a str>,
+	arch: &'a str,
+	#[serde(rename = "BUILDDATE")]
+	build_date: i64,
+	#[serde(rename = "INSTALLDATE")]
+	install_date: i64,
+	packager: &'a str,
+	#[serde(rename = "SIZE")]
+	installed_size: u64,
+	#[serde(skip_serializing_if = "InstallReason::is_explicit")]
+	reason: InstallReason,
+	groups: &'a [String],
+	#[serde(rename = "LICENSE")]
+	licenses: &'a [String],
+	validation: &'a [Validation],
+	replaces: &'a [String],
+	depends: &'a [Dependency],
+	optdepends: &'a [OptionalDependency],
+	conflicts: &'a [Dependency],
+	provides: &'a [Provides],
+	xdata: XdataRef<'a>,
+	#[serde(flatten)]
+	extra: ExtraRef<'a>,
+}
+
+impl<'a> From<&'a LocalPackage> for LocalPackageDescRef<'a> {
+	fn from(other: &'a LocalPackage) -> Self {
+		Self {
+			name: &other.name,
+			version: &other.version,
+			base: other.base.as_deref(),
+			description: &other.description,
+			url: other.url.as_deref(),
+			arch: &other.arch,
+			build_date: other.build_date,
+			install_date: other.install_date,
+			packager: &other.packager,
+			installed_size: other.installed_size,
+			reason: other.reason,
+			groups: &other.groups,
+			licenses: &other.licenses,
+			validation: &other.validation,
+			replaces: &other.replaces,
+			depends: &other.depends,
+			optdepends: &other.optdepends,
+			conflicts: &other.conflicts,
+			provides: &other.provides,
+			xdata: XdataRef::new(&other.xdata, &[&other.extra]),
+			extra: ExtraRef::new(&[&other.extra]),
+		}
+	}
+}
+
+impl LocalPackage {
+	/// Read a package entry from a directory in the local database.
+	pub fn from_directory(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+		let path = path.as_ref();
+		let mut package: Self = from_file(path.join("desc"))?;
+		let files_path = path.join("files");
+		if files_path.exists() {
+			package.files = Some(from_file(&files_path)?);
+		}
+		package.has_mtree = path.join("mtree").exists();
+		Ok(package)
+	}
+
+	/// Serialize the `desc` file of the package.
+	pub fn to_desc(&self) -> Result<String, SerializeError> {
+		to_string(&LocalPackageDescRef::from(self))
+	}
+}
+
+/// Read the local database of installed packages for a root directory.
+///
+/// The database is read from `$root/var/lib/pacman/local`.
+/// Use `/` as root directory to read the database of the running system.
+pub fn read_local_db(root: impl AsRef<Path>) -> Result<Vec<LocalPackage>, ReadDbDirError> {
+	read_local_db_dir(root.as_ref().join(LOCAL_DB_PATH))
+}
+
+/// Read the local database of installed packages from a database directory.
+pub fn read_local_db_dir(path: impl AsRef<Path>) -> Result<Vec<LocalPackage>, ReadDbDirError> {
+	let path = path.as_ref();
+	let readdir_error = |e| ReadDbDirError::ReadDir(PathBuf::from(path), e);
+
+	let dir = std::fs::read_dir(path).map_err(readdir_error)?;
+	let mut packages = Vec::with_capacity(dir.size_hint().0);
+	for entry in dir {
+		let entry = entry.map_err(readdir_error)?;
+		let stat = entry.metadata().map_err(readdir_error)?;
+		// Skip the ALPM_DB_VERSION file and other non-directories.
+		if !stat.file_type().is_dir() {
+			continue;
+		}
+		packages.push(LocalPackage::from_directory(entry.path())?);
+	}
+
+	Ok(packages)
+}
+
+impl<'de> Deserialize<'de> for InstallReason {
+	fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct Visitor;
+		impl<'de> serde::de::Visitor<'de> for Visitor {
+			type Value = InstallReason;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "an install reason (0 or 1)")
+			}
+
+			fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Self::Value, E> {
+				match value {
+					0 => Ok(InstallReason::Explicit),
+					1 => Ok(InstallReason::Dependency),
+					_ => Err(E::invalid_value(serde::de::Unexpected::Unsigned(value), &self)),
+				}
+			}
+		}
+
+		deserializer.deserialize_u8(Visitor)
+	}
+}
+
+impl Serialize for InstallReason {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		match self {
+			Self::Explicit => serializer.serialize_u8(0),
+			Self::Dependency => serializer.serialize_u8(1),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::db::{from_bytes, BackupEntry};
+	use assert2::{assert, let_assert};
+
+	const PACKAGE_DESC: &[u8] = include_bytes!("../../tests/local-package/desc");
+
+	#[test]
+	fn test_parse_local_package() {
+		let_assert!(Ok(parsed) = from_bytes::<LocalPackage>(PACKAGE_DESC));
+		assert!(parsed.name == "pacman");
+		assert!(parsed.version == PackageVersion::new(0, "6.0.1", "5"));
+		assert!(parsed.install_date == 1651012345);
+		assert!(parsed.installed_size == 4753874);
+		assert!(parsed.reason == InstallReason::Dependency);
+		assert!(parsed.validation == [Validation::Pgp]);
+		assert!(parsed.depends.len() == 7);
+		assert!(parsed.xdata == [ExtraData::new("pkgtype", "pkg")]);
+		assert!(parsed.extra.is_empty());
+
+		let_assert!(Ok(serialized) = parsed.to_desc());
+		assert!(serialized.as_bytes() == PACKAGE_DESC);
+	}
+
+	#[test]
+	fn test_mixed_xdata_round_trip() {
+		let desc = std::str::from_utf8(PACKAGE_DESC)
+			.unwrap()
+			.replace("pkgtype=pkg\n", "pkgtype=pkg\nbroken\n");
+		let_assert!(Ok(parsed) = from_bytes::<LocalPackage>(desc.as_bytes()));
+		assert!(parsed.xdata == [ExtraData::new("pkgtype", "pkg")]);
+		assert!(parsed.extra["XDATA"] == ["broken"]);
+
+		let_assert!(Ok(serialized) = parsed.to_desc());
+		assert!(serialized == desc);
+		let_assert!(Ok(reparsed) = from_bytes::<LocalPackage>(serialized.as_bytes()));
+		assert!(reparsed.xdata == [ExtraData::new("pkgtype", "pkg")]);
+		assert!(reparsed.extra["XDATA"] == ["broken"]);
+	}
+
+	#[test]
+	fn test_read_local_db() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let root = tempdir.path();
+		let db_dir = root.join(LOCAL_DB_PATH);
+		let pkg_dir = db_dir.join("pacman-6.0.1-5");
+		std::fs::create_dir_all(&pkg_dir).unwrap();
+		std::fs::write(db_dir.join("ALPM_DB_VERSION"), "9\n").unwrap();
+		std::fs::write(pkg_dir.join("desc"), PACKAGE_DESC).unwrap();
+		std::fs::write(pkg_dir.join("files"), include_bytes!("../../tests/local-package/files")).unwrap();
+		std::fs::write(pkg_dir.join("mtree"), b"").unwrap();
+
+		let_assert!(Ok(packages) = read_local_db(root));
+		assert!(packages.len() == 1);
+		assert!(packages[0].name == "pacman");
+		assert!(packages[0].has_mtree);
+		let_assert!(Some(files) = &packages[0].files);
+		assert!(files.contains("/usr/bin/pacman"));
+		assert!(
+			files.backup[1]
+				== BackupEntry {
+					path: "etc/pacman.conf".into(),
+					md5sum: "1c2f0d1bb0b7b1a9cf36d3d8d3e6e1f6".into(),
+				}
+		);
+	}
+}
@@ -1,21 +1,48 @@
 //! Types and parsers for repository database files.
 //!
-//! The types represent the contents of *.db.tar files.
-//! This module currently does not support reading (compressed) tar files directly.
-
-use serde::Deserialize;
+//! The types represent the contents of *.db.tar and *.files.tar files,
+//! and of the local database of installed packages (see [`read_local_db`]).
+//! Databases can be read from an extracted directory with [`read_db_dir`],
+//! or directly from a (compressed) archive with [`read_db_archive`].
+//! New archives can be written with [`write_db_archive`] and [`add_to_db_archive`].
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
-use crate::package::{Dependency, OptionalDependency, Provides};
-use crate::version::PackageVersion;
+use crate::package::{Dependency, ExtraData, OptionalDependency, Provides};
+use crate::version::{PackageVersion, ParseMode};
 
+mod archive;
 mod deserializer;
+mod files;
+mod local;
+mod serializer;
+mod writer;
 
+pub use archive::{read_db_archive, read_db_archive_bytes, read_db_archive_with_options, Compression, ReadDbArchiveError};
 pub use deserializer::{from_bytes, from_file, from_str, Error as ParseError};
-
+pub use files::{find_file_owners, BackupEntry, BackupEntryFromStrError, DatabasePackageFiles};
+pub use local::{read_local_db, read_local_db_dir, InstallReason, LocalPackage, Validation, LOCAL_DB_PATH};
+pub use serializer::{to_bytes, to_file, to_string, to_writer, Error as SerializeError};
+pub use writer::{add_to_db_archive, write_db_archive, write_db_archive_bytes, WriteDbArchiveError};
+
+/// A package entry from a repository database.
+///
+/// Use [`DatabasePackage::to_desc`] to serialize the package to the ALPM database format.
+/// All fields are written to a single `desc` file in the same order as `repo-add` does.
+/// Databases written by older versions of `repo-add` have a separate `depends` file,
+/// which is still read by [`DatabasePackage::from_directory`].
+///
+/// The type deliberately does not implement [`Serialize`], since the ALPM format writes versions and dependencies as plain strings.
+/// Use the `json` module (with the `json` feature) to serialize packages to structured JSON instead.
+///
+/// Fields that are not known by this library are kept in [`DatabasePackage::extra`],
+/// so that newer database formats can still be read.
+/// Use [`ReadOptions::strict`] to reject them instead.
 #[derive(Debug, Deserialize)]
+#[serde(remote = "Self")]
 #[serde(rename_all = "UPPERCASE")]
-#[serde(deny_unknown_fields)]
 pub struct DatabasePackage {
 	pub filename: String,
 
@@ -26,6 +53,7 @@ pub struct DatabasePackage {
 	pub version: PackageVersion,
 
 	#[serde(rename = "DESC")]
+	#[serde(default)]
 	pub description: String,
 
 	#[serde(default)]
@@ -59,6 +87,12 @@ pub struct DatabasePackage {
 	#[serde(default)]
 	pub replaces: Vec<String>,
 
+	/// The valid `%XDATA%` entries.
+	///
+	/// Malformed entries are kept in [`DatabasePackage::extra`] under the `XDATA` key.
+	#[serde(default, skip_deserializing)]
+	pub xdata: Vec<ExtraData>,
+
 	#[serde(default)]
 	pub depends: Vec<Dependency>,
 
@@ -76,37 +110,341 @@ pub struct DatabasePackage {
 
 	#[serde(default)]
 	pub checkdepends: Vec<Dependency>,
+
+	/// The file list of the package, if it was read from a `.files` database.
+	#[serde(skip)]
+	pub files: Option<DatabasePackageFiles>,
+
+	/// Unknown fields, by key (without the surrounding `%` signs).
+	#[serde(flatten)]
+	pub extra: BTreeMap<String, Vec<String>>,
+
+	/// Unknown fields from the legacy `depends` file, by key (without the surrounding `%` signs).
+	///
+	/// They are kept apart from [`DatabasePackage::extra`], and written after them to the `desc` file.
+	#[serde(skip)]
+	pub depends_extra: BTreeMap<String, Vec<String>>,
 }
 
-#[derive(Debug, Deserialize)]
+/// The contents of the legacy `depends` file of a package entry.
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "UPPERCASE")]
-#[serde(deny_unknown_fields)]
-struct DatabasePackageDepends {
+pub(crate) struct DatabasePackageDepends {
 	#[serde(default)]
-	depends: Vec<Dependency>,
+	pub(crate) depends: Vec<Dependency>,
 	#[serde(default)]
-	conflicts: Vec<Dependency>,
+	pub(crate) conflicts: Vec<Dependency>,
 	#[serde(default)]
-	provides: Vec<Provides>,
+	pub(crate) provides: Vec<Provides>,
 	#[serde(default)]
-	optdepends: Vec<OptionalDependency>,
+	pub(crate) optdepends: Vec<OptionalDependency>,
 	#[serde(default)]
-	makedepends: Vec<Dependency>,
+	pub(crate) makedepends: Vec<Dependency>,
 	#[serde(default)]
-	checkdepends: Vec<Dependency>,
+	pub(crate) checkdepends: Vec<Dependency>,
+	#[serde(flatten)]
+	pub(crate) extra: BTreeMap<String, Vec<String>>,
+}
+
+/// Options for reading repository databases.
+#[derive(Clone, Debug, Default)]
+pub struct ReadOptions {
+	/// Reject database entries with unknown fields, instead of storing them in [`DatabasePackage::extra`].
+	pub strict: bool,
+
+	/// How strictly to validate the versions of packages, dependencies and provides.
+	///
+	/// Lenient by default, since real world databases contain versions that makepkg should not have generated.
+	/// Soname versions (like `libfoo.so=1-64`) are library versions rather than pkgvers, so they are never validated.
+	pub version_mode: ParseMode,
+}
+
+impl ReadOptions {
+	/// Create the default options: lenient parsing.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Enable or disable strict parsing.
+	pub fn strict(mut self, strict: bool) -> Self {
+		self.strict = strict;
+		self
+	}
+
+	/// Set the parse mode used to validate versions.
+	pub fn version_mode(mut self, mode: ParseMode) -> Self {
+		self.version_mode = mode;
+		self
+	}
+
+	/// Check a parsed package against the options.
+	///
+	/// The `source` is used in the error message.
+	fn check(&self, package: &DatabasePackage, source: impl std::fmt::Display) -> Result<(), ParseError> {
+		if self.strict {
+			if let Some(entry) = package.extra.get("XDATA").and_then(|entries| entries.first()) {
+				return Err(ParseError::new(
+					Some(source.to_string()),
+					None,
+					format_args!("invalid %XDATA% entry {}", entry),
+				));
+			}
+			if let Some(key) = package.extra.keys().chain(package.depends_extra.keys()).next() {
+				return Err(ParseError::new(Some(source.to_string()), None, format_args!("unknown field %{}%", key)));
+			}
+		}
+		self.check_versions(package)
+			.map_err(|message| ParseError::new(Some(source.to_string()), None, message))
+	}
+
+	/// Validate all versions of a package according to the version parse mode.
+	///
+	/// Provides and dependencies that were recognized as sonames are skipped.
+	fn check_versions(&self, package: &DatabasePackage) -> Result<(), String> {
+		let mode = self.version_mode;
+		package
+			.version
+			.validate(mode)
+			.map_err(|e| format!("invalid %VERSION% {}: {}", package.version, e))?;
+
+		let dependencies = [
+			("DEPENDS", &package.depends),
+			("CONFLICTS", &package.conflicts),
+			("MAKEDEPENDS", &package.makedepends),
+			("CHECKDEPENDS", &package.checkdepends),
+		];
+		for (key, dependencies) in dependencies {
+			for dependency in dependencies.iter().filter(|x| x.soname().is_none()) {
+				if let Some(constraint) = &dependency.version {
+					constraint
+						.version
+						.validate(mode)
+						.map_err(|e| format!("invalid %{}% entry {}: {}", key, dependency, e))?;
+				}
+			}
+		}
+		for dependency in &package.optdepends {
+			if let Some(constraint) = &dependency.version {
+				constraint
+					.version
+					.validate(mode)
+					.map_err(|e| format!("invalid %OPTDEPENDS% entry {}: {}", dependency, e))?;
+			}
+		}
+		for provides in package.provides.iter().filter(|x| x.soname().is_none()) {
+			if let Some(version) = &provides.version {
+				version
+					.validate(mode)
+					.map_err(|e| format!("invalid %PROVIDES% entry {}: {}", provides, e))?;
+			}
+		}
+		Ok(())
+	}
+}
+
+impl<'de> Deserialize<'de> for DatabasePackage {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let mut package = Self::deserialize(deserializer)?;
+		package.xdata = take_xdata(&mut package.extra);
+		Ok(package)
+	}
+}
+
+/// Borrowed version of the `desc` file of a [`DatabasePackage`] for serialization.
+#[derive(Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct DatabasePackageDescRef<'a> {
+	filename: &'a str,
+	name: &'a str,
+	base: Option<&'a str>,
+	version: &'a PackageVersion,
+	#[serde(rename = "DESC")]
+	description: &'a str,
+	groups: &'a [String],
+	#[serde(rename = "CSIZE")]
+	compressed_size: u64,
+	#[serde(rename = "ISIZE")]
+	installed_size: u64,
+	md5sum: &'a str,
+	sha256sum: &'a str,
+	pgpsig: Option<&'a str>,
+	url: Option<&'a str>,
+	#[serde(rename = "LICENSE")]
+	licenses: &'a [String],
+	arch: &'a str,
+	#[serde(rename = "BUILDDATE")]
+	build_date: i64,
+	packager: &'a str,
+	replaces: &'a [String],
+	conflicts: &'a [Dependency],
+	provides: &'a [Provides],
+	depends: &'a [Dependency],
+	optdepends: &'a [OptionalDependency],
+	makedepends: &'a [Dependency],
+	checkdepends: &'a [Dependency],
+	xdata: XdataRef<'a>,
+	#[serde(flatten)]
+	extra: ExtraRef<'a>,
+}
+
+impl<'a> From<&'a DatabasePackage> for DatabasePackageDescRef<'a> {
+	fn from(other: &'a DatabasePackage) -> Self {
+		Self {
+			filename: &other.filename,
+			name: &other.name,
+			base: other.base.as_deref(),
+			version: &other.version,
+			description: &other.description,
+			groups: &other.groups,
+			compressed_size: other.compressed_size,
+			installed_size: other.installed_size,
+			md5sum: &other.md5sum,
+			sha256sum: &other.sha256sum,
+			pgpsig: other.pgpsig.as_deref(),
+			url: other.url.as_deref(),
+			licenses: &other.licenses,
+			arch: &other.arch,
+			build_date: other.build_date,
+			packager: &other.packager,
+			replaces: &other.replaces,
+			conflicts: &other.conflicts,
+			provides: &other.provides,
+			depends: &other.depends,
+			optdepends: &other.optdepends,
+			makedepends: &other.makedepends,
+			checkdepends: &other.checkdepends,
+			xdata: XdataRef::new(&other.xdata, &[&other.extra, &other.depends_extra]),
+			extra: ExtraRef::new(&[&other.extra, &other.depends_extra]),
+		}
+	}
+}
+
+/// The `%XDATA%` section of a package for serialization.
+///
+/// The valid entries and the malformed entries from the unknown fields are written as a single section,
+/// so that none of them are lost when the section is parsed again.
+struct XdataRef<'a> {
+	valid: &'a [ExtraData],
+	malformed: Vec<&'a String>,
+}
+
+impl<'a> XdataRef<'a> {
+	fn new(valid: &'a [ExtraData], extra: &[&'a BTreeMap<String, Vec<String>>]) -> Self {
+		let malformed = extra.iter().filter_map(|extra| extra.get("XDATA")).flatten().collect();
+		Self { valid, malformed }
+	}
+}
+
+impl Serialize for XdataRef<'_> {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		use serde::ser::SerializeSeq;
+		let mut seq = serializer.serialize_seq(Some(self.valid.len() + self.malformed.len()))?;
+		for entry in self.valid {
+			seq.serialize_element(entry)?;
+		}
+		for entry in &self.malformed {
+			seq.serialize_element(entry)?;
+		}
+		seq.end()
+	}
+}
+
+/// The unknown fields of a package for serialization, without the malformed `%XDATA%` entries.
+///
+/// Those are written by [`XdataRef`] instead.
+/// Fields with the same key in multiple maps are merged into a single section.
+struct ExtraRef<'a>(BTreeMap<&'a str, Vec<&'a String>>);
+
+impl<'a> ExtraRef<'a> {
+	fn new(extra: &[&'a BTreeMap<String, Vec<String>>]) -> Self {
+		let mut merged = BTreeMap::new();
+		for (key, values) in extra.iter().flat_map(|extra| extra.iter()) {
+			if key != "XDATA" {
+				merged.entry(key.as_str()).or_insert_with(Vec::new).extend(values);
+			}
+		}
+		Self(merged)
+	}
+}
+
+impl Serialize for ExtraRef<'_> {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		self.0.serialize(serializer)
+	}
+}
+
+/// Parse the `%XDATA%` entries from the unknown fields of a package.
+///
+/// Malformed entries are left in the unknown fields, so that they do not make the whole database unreadable.
+fn take_xdata(extra: &mut BTreeMap<String, Vec<String>>) -> Vec<ExtraData> {
+	let entries = match extra.remove("XDATA") {
+		Some(entries) => entries,
+		None => return Vec::new(),
+	};
+
+	let mut xdata = Vec::with_capacity(entries.len());
+	let mut malformed = Vec::new();
+	for entry in entries {
+		match entry.parse() {
+			Ok(parsed) => xdata.push(parsed),
+			Err(_) => malformed.push(entry),
+		}
+	}
+	if !malformed.is_empty() {
+		extra.insert("XDATA".into(), malformed);
+	}
+	xdata
 }
 
 impl DatabasePackage {
+	/// Read a package entry from a directory, as found in an extracted repository database.
 	pub fn from_directory(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+		Self::from_directory_with_options(path, &ReadOptions::default())
+	}
+
+	/// Read a package entry from a directory with custom options.
+	pub fn from_directory_with_options(path: impl AsRef<Path>, options: &ReadOptions) -> Result<Self, ParseError> {
 		let path = path.as_ref();
 		let mut package: Self = from_file(path.join("desc"))?;
+		// Only databases written by older versions of repo-add have a separate depends file.
 		let depends_path = path.join("depends");
 		if depends_path.exists() {
 			package.add_depends(from_file(&depends_path)?);
 		}
+		let files_path = path.join("files");
+		if files_path.exists() {
+			package.files = Some(from_file(&files_path)?);
+		}
+		options.check(&package, path.display())?;
 		Ok(package)
 	}
 
+	/// Get the package type from the extra data, if present.
+	///
+	/// Typical values are `pkg`, `split`, `debug` and `src`.
+	pub fn pkgtype(&self) -> Option<&str> {
+		self.xdata.iter().find(|x| x.key == "pkgtype").map(|x| x.value.as_str())
+	}
+
+	/// Serialize the `desc` file of the package.
+	pub fn to_desc(&self) -> Result<String, SerializeError> {
+		to_string(&DatabasePackageDescRef::from(self))
+	}
+
+	/// Write the package entry to a directory, as found in an extracted repository database.
+	///
+	/// The directory is created if it does not exist yet.
+	/// All fields are written to the `desc` file, and the `files` file is only written if the file list is known.
+	pub fn write_directory(&self, path: impl AsRef<Path>) -> Result<(), WriteDbDirError> {
+		let path = path.as_ref();
+		std::fs::create_dir_all(path).map_err(|e| WriteDbDirError::CreateDir(path.into(), e))?;
+		to_file(path.join("desc"), &DatabasePackageDescRef::from(self))?;
+		if let Some(files) = &self.files {
+			to_file(path.join("files"), files)?;
+		}
+		Ok(())
+	}
+
 	fn add_depends(&mut self, mut other: DatabasePackageDepends) {
 		self.depends.append(&mut other.depends);
 		self.conflicts.append(&mut other.conflicts);
@@ -114,6 +452,7 @@ impl DatabasePackage {
 		self.optdepends.append(&mut other.optdepends);
 		self.makedepends.append(&mut other.makedepends);
 		self.checkdepends.append(&mut other.checkdepends);
+		self.depends_extra.append(&mut other.extra);
 	}
 }
 
@@ -125,6 +464,11 @@ pub enum ReadDbDirError {
 
 /// Read packages information from a folder containing an extracted repository database.
 pub fn read_db_dir(path: impl AsRef<Path>) -> Result<Vec<DatabasePackage>, ReadDbDirError> {
+	read_db_dir_with_options(path, &ReadOptions::default())
+}
+
+/// Read packages information from a folder containing an extracted repository database with custom options.
+pub fn read_db_dir_with_options(path: impl AsRef<Path>, options: &ReadOptions) -> Result<Vec<DatabasePackage>, ReadDbDirError> {
 	let path = path.as_ref();
 	let readdir_error = |e| ReadDbDirError::ReadDir(path.into(), e);
 
@@ -136,19 +480,32 @@ pub fn read_db_dir(path: impl AsRef<Path>) -> Result<Vec<DatabasePackage>, ReadD
 		if !stat.file_type().is_dir() {
 			continue;
 		}
-		packages.push(DatabasePackage::from_directory(entry.path())?);
+		packages.push(DatabasePackage::from_directory_with_options(entry.path(), options)?);
 	}
 
 	Ok(packages)
 }
 
+#[derive(Debug)]
+pub enum WriteDbDirError {
+	CreateDir(PathBuf, std::io::Error),
+	Serialize(SerializeError),
+}
+
 impl From<ParseError> for ReadDbDirError {
 	fn from(other: ParseError) -> Self {
 		Self::Parse(other)
 	}
 }
 
+impl From<SerializeError> for WriteDbDirError {
+	fn from(other: SerializeError) -> Self {
+		Self::Serialize(other)
+	}
+}
+
 impl std::error::Error for ReadDbDirError {}
+impl std::error::Error for WriteDbDirError {}
 
 impl std::fmt::Display for ReadDbDirError {
 	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -159,14 +516,38 @@ impl std::fmt::Display for ReadDbDirError {
 	}
 }
 
+impl std::fmt::Display for WriteDbDirError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::CreateDir(path, e) => write!(f, "failed to create directory {}: {}", path.display(), e),
+			Self::Serialize(e) => e.fmt(f),
+		}
+	}
+}
+
+/// Create a package entry for tests from `tests/database-package/desc`, with a different name and version.
+#[cfg(test)]
+pub(crate) fn test_package(name: &str, version: &str) -> DatabasePackage {
+	let mut package: DatabasePackage = from_bytes(include_bytes!("../../tests/database-package/desc")).unwrap();
+	package.name = name.into();
+	package.version = version.parse().unwrap();
+	package
+}
+
 #[cfg(test)]
 mod test {
 	use super::*;
+	use crate::package::PackageName;
 	use crate::version::Version;
 	use assert2::{assert, let_assert};
 
+	fn name(input: &str) -> PackageName {
+		input.parse().unwrap()
+	}
+
 	const PACKAGE_DESC: &[u8] = include_bytes!("../../tests/database-package/desc");
 	const PACKAGE_DEPENDS: &[u8] = include_bytes!("../../tests/database-package/depends");
+	const PACKAGE_FILES: &[u8] = include_bytes!("../../tests/database-package/files");
 
 	#[test]
 	fn test_parse_package_desc() {
@@ -196,22 +577,213 @@ mod test {
 	fn test_parse_package_depends() {
 		let_assert!(Ok(parsed) = from_bytes::<DatabasePackageDepends>(PACKAGE_DEPENDS));
 		assert!(parsed.depends == vec![
-			Dependency::unconstrained("coreutils"),
-			Dependency::unconstrained("linux-firmware"),
-			Dependency::unconstrained("kmod"),
-			Dependency::constrained_greater_equal("mkinitcpio", Version::new(0, "0.7", None)),
+			Dependency::unconstrained(name("coreutils")),
+			Dependency::unconstrained(name("linux-firmware")),
+			Dependency::unconstrained(name("kmod")),
+			Dependency::constrained_greater_equal(name("mkinitcpio"), Version::new(0, "0.7", None)),
 		]);
 		assert!(parsed.conflicts == vec![
-			Dependency::unconstrained("linux"),
+			Dependency::unconstrained(name("linux")),
 		]);
 		assert!(parsed.provides == vec![
-			Provides::versioned("linux", Version::new(0, "5.8.9", None)),
-			Provides::unversioned("WIREGUARD-MODULE"),
+			Provides::versioned(name("linux"), Version::new(0, "5.8.9", None)),
+			Provides::unversioned(name("WIREGUARD-MODULE")),
 		]);
 		assert!(parsed.optdepends == vec![
-			OptionalDependency::new("crda", None, "to set the correct wireless channels of your country"),
+			OptionalDependency::new(name("crda"), None, "to set the correct wireless channels of your country"),
 		]);
 		assert!(parsed.makedepends == vec![]);
 		assert!(parsed.checkdepends == vec![]);
 	}
+
+	#[test]
+	fn test_parse_invalid_package_name() {
+		let_assert!(Err(e) = from_str::<DatabasePackageDepends>("%DEPENDS%\ncoreutils\n-kmod>=1\n\n"));
+		assert!(e.line() == Some(3));
+		assert!(e.to_string() == "line 3: invalid dependency declaration: package name starts with a hyphen");
+		let_assert!(Err(e) = from_str::<DatabasePackageDepends>("%DEPENDS%\ncoreutils\n\n%PROVIDES%\nfoo bar=1.0\n"));
+		assert!(e.to_string() == "line 5: invalid provides declaration: package name contains invalid character: ' '");
+	}
+
+	#[test]
+	fn test_serialize_package_desc() {
+		let_assert!(Ok(parsed) = from_bytes::<DatabasePackage>(PACKAGE_DESC));
+		let_assert!(Ok(serialized) = parsed.to_desc());
+		assert!(serialized.as_bytes() == PACKAGE_DESC);
+	}
+
+	#[test]
+	fn test_serialize_package_depends() {
+		let_assert!(Ok(parsed) = from_bytes::<DatabasePackageDepends>(PACKAGE_DEPENDS));
+		let_assert!(Ok(serialized) = to_bytes(&parsed));
+		assert!(serialized == PACKAGE_DEPENDS);
+	}
+
+	#[test]
+	fn test_package_directory_round_trip() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		std::fs::write(dir.join("desc"), PACKAGE_DESC).unwrap();
+		std::fs::write(dir.join("depends"), PACKAGE_DEPENDS).unwrap();
+
+		// The legacy depends file is merged into the desc file, like repo-add does since pacman 5.
+		let_assert!(Ok(package) = DatabasePackage::from_directory(dir));
+		let out = dir.join("out");
+		let_assert!(Ok(()) = package.write_directory(&out));
+		assert!(!out.join("depends").exists());
+		let_assert!(Ok(desc) = std::fs::read(out.join("desc")));
+		let expected: &[u8] = concat!(
+			"%CONFLICTS%\nlinux\n\n",
+			"%PROVIDES%\nlinux=5.8.9\nWIREGUARD-MODULE\n\n",
+			"%DEPENDS%\ncoreutils\nlinux-firmware\nkmod\nmkinitcpio>=0.7\n\n",
+			"%OPTDEPENDS%\ncrda: to set the correct wireless channels of your country\n\n",
+		)
+		.as_bytes();
+		assert!(desc == [PACKAGE_DESC, expected].concat());
+
+		let_assert!(Ok(reread) = DatabasePackage::from_directory(&out));
+		assert!(reread.depends == package.depends);
+		assert!(reread.conflicts == package.conflicts);
+		assert!(reread.provides == package.provides);
+		assert!(reread.optdepends == package.optdepends);
+	}
+
+	#[test]
+	#[rustfmt::skip]
+	fn test_unknown_fields_and_xdata() {
+		let mut desc = PACKAGE_DESC.to_vec();
+		desc.extend_from_slice(b"%XDATA%\npkgtype=pkg\n\n%SHINY%\nnew\nfield\n\n");
+
+		let_assert!(Ok(parsed) = from_bytes::<DatabasePackage>(&desc));
+		assert!(parsed.xdata == vec![ExtraData::new("pkgtype", "pkg")]);
+		assert!(parsed.pkgtype() == Some("pkg"));
+		assert!(parsed.extra.len() == 1);
+		assert!(parsed.extra["SHINY"] == ["new", "field"]);
+
+		// Unknown fields are written back after the known fields.
+		let_assert!(Ok(serialized) = parsed.to_desc());
+		assert!(serialized.ends_with("%XDATA%\npkgtype=pkg\n\n%SHINY%\nnew\nfield\n\n"));
+	}
+
+	#[test]
+	fn test_malformed_xdata() {
+		let mut desc = PACKAGE_DESC.to_vec();
+		desc.extend_from_slice(b"%XDATA%\npkgtype=pkg\nbroken\n\n");
+
+		// Malformed entries are kept as unknown field in lenient mode.
+		let_assert!(Ok(parsed) = from_bytes::<DatabasePackage>(&desc));
+		assert!(parsed.xdata == vec![ExtraData::new("pkgtype", "pkg")]);
+		assert!(parsed.extra["XDATA"] == ["broken"]);
+
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		std::fs::write(dir.join("desc"), &desc).unwrap();
+		let_assert!(Ok(_) = DatabasePackage::from_directory(dir));
+		let_assert!(Err(e) = DatabasePackage::from_directory_with_options(dir, &ReadOptions::new().strict(true)));
+		assert!(e.to_string().contains("invalid %XDATA% entry broken"));
+	}
+
+	#[test]
+	#[rustfmt::skip]
+	fn test_mixed_xdata_round_trip() {
+		let mut desc = PACKAGE_DESC.to_vec();
+		desc.extend_from_slice(b"%XDATA%\npkgtype=pkg\nbroken\nfoo=bar\n\n%SHINY%\nnew\n\n");
+		let_assert!(Ok(parsed) = from_bytes::<DatabasePackage>(&desc));
+
+		// Valid and malformed entries are written as a single section.
+		let_assert!(Ok(serialized) = parsed.to_desc());
+		assert!(serialized.ends_with("%XDATA%\npkgtype=pkg\nfoo=bar\nbroken\n\n%SHINY%\nnew\n\n"));
+
+		let_assert!(Ok(reparsed) = from_bytes::<DatabasePackage>(serialized.as_bytes()));
+		assert!(reparsed.xdata == vec![ExtraData::new("pkgtype", "pkg"), ExtraData::new("foo", "bar")]);
+		assert!(reparsed.extra["XDATA"] == ["broken"]);
+		assert!(reparsed.extra["SHINY"] == ["new"]);
+	}
+
+	#[test]
+	fn test_unknown_depends_fields() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		std::fs::write(dir.join("desc"), PACKAGE_DESC).unwrap();
+		std::fs::write(dir.join("depends"), "%DEPENDS%\nkmod\n\n%SHINY%\nnew\n\n").unwrap();
+
+		// Unknown fields from the depends file are kept apart, and written after the known fields.
+		let_assert!(Ok(package) = DatabasePackage::from_directory(dir));
+		assert!(package.extra.is_empty());
+		assert!(package.depends_extra["SHINY"] == ["new"]);
+		let_assert!(Ok(desc) = package.to_desc());
+		assert!(desc.ends_with("%REPLACES%\nlinux-armv8\n\n%DEPENDS%\nkmod\n\n%SHINY%\nnew\n\n"));
+
+		let_assert!(Err(e) = DatabasePackage::from_directory_with_options(dir, &ReadOptions::new().strict(true)));
+		assert!(e.to_string().contains("unknown field %SHINY%"));
+	}
+
+	#[test]
+	fn test_strict_mode() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let mut desc = PACKAGE_DESC.to_vec();
+		desc.extend_from_slice(b"%SHINY%\nnew\n\n");
+		std::fs::write(dir.join("desc"), &desc).unwrap();
+
+		let_assert!(Ok(_) = DatabasePackage::from_directory(dir));
+		let_assert!(Err(e) = DatabasePackage::from_directory_with_options(dir, &ReadOptions::new().strict(true)));
+		assert!(e.to_string().contains("unknown field %SHINY%"));
+	}
+
+	#[test]
+	fn test_strict_version_mode() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		std::fs::write(dir.join("desc"), PACKAGE_DESC).unwrap();
+		std::fs::write(dir.join("depends"), "%PROVIDES%\nfoo=1 2-1\n\n").unwrap();
+
+		let strict = ReadOptions::new().version_mode(ParseMode::Strict);
+		let_assert!(Ok(_) = DatabasePackage::from_directory(dir));
+		let_assert!(Err(e) = DatabasePackage::from_directory_with_options(dir, &strict));
+		assert!(e.to_string().contains("invalid %PROVIDES% entry foo=1 2-1: invalid pkgver in version"));
+
+		std::fs::write(dir.join("depends"), "%PROVIDES%\nfoo=1.2-1\n\n").unwrap();
+		let_assert!(Ok(_) = DatabasePackage::from_directory_with_options(dir, &strict));
+
+		// Soname versions are not pkgvers, so they are not validated.
+		std::fs::write(dir.join("depends"), "%DEPENDS%\nlibbar.so=1 2-64\n\n%PROVIDES%\nlibfoo.so=1 2-64\n\n").unwrap();
+		let_assert!(Ok(package) = DatabasePackage::from_directory_with_options(dir, &strict));
+		assert!(package.provides[0].soname().is_some());
+		assert!(package.depends[0].soname().is_some());
+	}
+
+	#[test]
+	fn test_parse_package_files() {
+		let_assert!(Ok(parsed) = from_bytes::<DatabasePackageFiles>(PACKAGE_FILES));
+		assert!(parsed.files.len() == 13);
+		assert!(parsed.files[1] == "boot/Image");
+		assert!(
+			parsed.backup
+				== vec![BackupEntry {
+					path: "etc/mkinitcpio.d/linux-aarch64.preset".into(),
+					md5sum: "9a4b3c1d0e6f1a2b3c4d5e6f7a8b9c0d".into(),
+				}]
+		);
+
+		let_assert!(Ok(serialized) = to_bytes(&parsed));
+		assert!(serialized == PACKAGE_FILES);
+	}
+
+	#[test]
+	fn test_find_file_owners() {
+		let_assert!(Ok(mut package) = from_bytes::<DatabasePackage>(PACKAGE_DESC));
+		assert!(!package.owns_file("/boot/Image"));
+
+		package.files = Some(from_bytes(PACKAGE_FILES).unwrap());
+		assert!(package.owns_file("/boot/Image"));
+		assert!(package.owns_file("boot/Image"));
+		assert!(package.owns_file("/usr/lib/modules"));
+		assert!(package.owns_file("/usr/lib/modules/"));
+		assert!(!package.owns_file("/usr/lib/libfoo.so"));
+
+		let packages = [package];
+		assert!(find_file_owners(&packages, "/etc/mkinitcpio.d/linux-aarch64.preset").len() == 1);
+		assert!(find_file_owners(&packages, "/etc/pacman.conf").is_empty());
+	}
 }
@@ -0,0 +1,557 @@
+use serde::ser;
+use serde::ser::Error as _;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+/// Serializer for the ALPM database format.
+///
+/// The serializer writes one `%KEY%` section for every field of a struct (or entry of a map).
+/// Fields without a value (`None`, empty lists or empty strings) are omitted entirely,
+/// just like `repo-add` does.
+pub struct Serializer<W> {
+	writer: W,
+}
+
+impl<W: Write> Serializer<W> {
+	pub fn new(writer: W) -> Self {
+		Self { writer }
+	}
+
+	/// Get the wrapped writer back.
+	pub fn into_inner(self) -> W {
+		self.writer
+	}
+
+	/// Write a single field with all of its lines.
+	///
+	/// If there are no lines, nothing is written at all.
+	fn write_field(&mut self, key: &str, lines: &[String]) -> Result<(), Error> {
+		if lines.is_empty() {
+			return Ok(());
+		}
+
+		let mut write = || -> std::io::Result<()> {
+			writeln!(self.writer, "%{}%", key)?;
+			for line in lines {
+				writeln!(self.writer, "{}", line)?;
+			}
+			writeln!(self.writer)
+		};
+		write().map_err(|e| Error::custom(format_args!("failed to write field {}: {}", key, e)))
+	}
+}
+
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+	let data = to_bytes(value)?;
+	// The serializer only writes UTF-8 strings, so this can not fail.
+	Ok(String::from_utf8(data).unwrap())
+}
+
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+	let mut data = Vec::new();
+	to_writer(&mut data, value)?;
+	Ok(data)
+}
+
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(writer: W, value: &T) -> Result<(), Error> {
+	let mut serializer = Serializer::new(writer);
+	value.serialize(&mut serializer)
+}
+
+pub fn to_file<T: Serialize + ?Sized>(path: impl AsRef<Path>, value: &T) -> Result<(), Error> {
+	let path = path.as_ref();
+	let with_source = |message: String| Error {
+		source: Some(path.display().to_string()),
+		message,
+	};
+
+	let data = to_bytes(value).map_err(|e| with_source(e.message))?;
+	std::fs::write(path, data).map_err(|e| with_source(format!("failed to write file: {}", e)))
+}
+
+#[derive(Debug)]
+pub struct Error {
+	source: Option<String>,
+	message: String,
+}
+
+fn unexpected_top_level_type(name: &str) -> Error {
+	Error::custom(format_args!(
+		"the top level type must be a struct or map for the ALPM database format, but it is {}",
+		name
+	))
+}
+
+impl<'a, W: Write> ser::Serializer for &'a mut Serializer<W> {
+	type Error = Error;
+	type Ok = ();
+	type SerializeMap = MapSerializer<'a, W>;
+	type SerializeSeq = ser::Impossible<(), Error>;
+	type SerializeStruct = Self;
+	type SerializeStructVariant = ser::Impossible<(), Error>;
+	type SerializeTuple = ser::Impossible<(), Error>;
+	type SerializeTupleStruct = ser::Impossible<(), Error>;
+	type SerializeTupleVariant = ser::Impossible<(), Error>;
+
+	fn serialize_bool(self, _value: bool) -> Result<(), Error> {
+		Err(unexpected_top_level_type("a boolean"))
+	}
+
+	fn serialize_i8(self, _value: i8) -> Result<(), Error> {
+		Err(unexpected_top_level_type("an i8"))
+	}
+
+	fn serialize_i16(self, _value: i16) -> Result<(), Error> {
+		Err(unexpected_top_level_type("an i16"))
+	}
+
+	fn serialize_i32(self, _value: i32) -> Result<(), Error> {
+		Err(unexpected_top_level_type("an i32"))
+	}
+
+	fn serialize_i64(self, _value: i64) -> Result<(), Error> {
+		Err(unexpected_top_level_type("an i64"))
+	}
+
+	fn serialize_u8(self, _value: u8) -> Result<(), Error> {
+		Err(unexpected_top_level_type("a u8"))
+	}
+
+	fn serialize_u16(self, _value: u16) -> Result<(), Error> {
+		Err(unexpected_top_level_type("a u16"))
+	}
+
+	fn serialize_u32(self, _value: u32) -> Result<(), Error> {
+		Err(unexpected_top_level_type("a u32"))
+	}
+
+	fn serialize_u64(self, _value: u64) -> Result<(), Error> {
+		Err(unexpected_top_level_type("a u64"))
+	}
+
+	fn serialize_f32(self, _value: f32) -> Result<(), Error> {
+		Err(unexpected_top_level_type("an f32"))
+	}
+
+	fn serialize_f64(self, _value: f64) -> Result<(), Error> {
+		Err(unexpected_top_level_type("an f64"))
+	}
+
+	fn serialize_char(self, _value: char) -> Result<(), Error> {
+		Err(unexpected_top_level_type("a character"))
+	}
+
+	fn serialize_str(self, _value: &str) -> Result<(), Error> {
+		Err(unexpected_top_level_type("a string"))
+	}
+
+	fn serialize_bytes(self, _value: &[u8]) -> Result<(), Error> {
+		Err(unexpected_top_level_type("a byte string"))
+	}
+
+	fn serialize_none(self) -> Result<(), Error> {
+		Err(unexpected_top_level_type("an optional value"))
+	}
+
+	fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<(), Error> {
+		Err(unexpected_top_level_type("an optional value"))
+	}
+
+	fn serialize_unit(self) -> Result<(), Error> {
+		Err(unexpected_top_level_type("a unit value"))
+	}
+
+	fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+		Err(unexpected_top_level_type("a unit struct"))
+	}
+
+	fn serialize_unit_variant(self, _name: &'static str, _index: u32, _variant: &'static str) -> Result<(), Error> {
+		Err(unexpected_top_level_type("an enum"))
+	}
+
+	fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), Error> {
+		// ALPM database files don't do newtype structs, just serialize the inner value directly.
+		// If it's not a struct, we'll still give an error.
+		value.serialize(self)
+	}
+
+	fn serialize_newtype_variant<T: Serialize + ?Sized>(
+		self,
+		_name: &'static str,
+		_index: u32,
+		_variant: &'static str,
+		_value: &T,
+	) -> Result<(), Error> {
+		Err(unexpected_top_level_type("an enum"))
+	}
+
+	fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+		Err(unexpected_top_level_type("a list or sequence"))
+	}
+
+	fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+		Err(unexpected_top_level_type("a tuple"))
+	}
+
+	fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, Error> {
+		Err(unexpected_top_level_type("a tuple struct"))
+	}
+
+	fn serialize_tuple_variant(
+		self,
+		_name: &'static str,
+		_index: u32,
+		_variant: &'static str,
+		_len: usize,
+	) -> Result<Self::SerializeTupleVariant, Error> {
+		Err(unexpected_top_level_type("an enum"))
+	}
+
+	fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+		Ok(MapSerializer { parent: self, key: None })
+	}
+
+	fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Error> {
+		Ok(self)
+	}
+
+	fn serialize_struct_variant(
+		self,
+		_name: &'static str,
+		_index: u32,
+		_variant: &'static str,
+		_len: usize,
+	) -> Result<Self::SerializeStructVariant, Error> {
+		Err(unexpected_top_level_type("an enum"))
+	}
+}
+
+impl<W: Write> ser::SerializeStruct for &'_ mut Serializer<W> {
+	type Error = Error;
+	type Ok = ();
+
+	fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+		let mut lines = Vec::new();
+		value.serialize(FieldSerializer::new(&mut lines))?;
+		self.write_field(key, &lines)
+	}
+
+	fn end(self) -> Result<(), Error> {
+		Ok(())
+	}
+}
+
+/// Serializer for the entries of a top level map.
+pub struct MapSerializer<'a, W> {
+	parent: &'a mut Serializer<W>,
+	key: Option<String>,
+}
+
+impl<W: Write> ser::SerializeMap for MapSerializer<'_, W> {
+	type Error = Error;
+	type Ok = ();
+
+	fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+		let mut lines = Vec::new();
+		key.serialize(FieldSerializer::new(&mut lines))?;
+		if lines.len() != 1 {
+			return Err(Error::custom("ALPM database keys must be a single non-empty line"));
+		}
+		self.key = lines.pop();
+		Ok(())
+	}
+
+	fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+		let key = self
+			.key
+			.take()
+			.ok_or_else(|| Error::custom("serialize_value called before serialize_key"))?;
+		let mut lines = Vec::new();
+		value.serialize(FieldSerializer::new(&mut lines))?;
+		self.parent.write_field(&key, &lines)
+	}
+
+	fn end(self) -> Result<(), Error> {
+		Ok(())
+	}
+}
+
+/// Serializer that can only serialize unstructured values.
+///
+/// Every value is turned into one or more lines.
+/// Lists are supported, but only one level deep.
+struct FieldSerializer<'a> {
+	lines: &'a mut Vec<String>,
+	in_sequence: bool,
+}
+
+impl<'a> FieldSerializer<'a> {
+	fn new(lines: &'a mut Vec<String>) -> Self {
+		Self { lines, in_sequence: false }
+	}
+
+	fn push_line(self, line: String) -> Result<(), Error> {
+		if line.contains('\n') || line.contains('\r') {
+			Err(Error::custom(format_args!(
+				"ALPM database values can not contain line breaks: {:?}",
+				line
+			)))
+		} else if line.starts_with('%') && line.ends_with('%') {
+			Err(Error::custom(format_args!(
+				"ALPM database values can not have the form \"%NAME%\": {:?}",
+				line
+			)))
+		} else {
+			// Empty lines are skipped by the parser, so we don't write them either.
+			if !line.is_empty() {
+				self.lines.push(line);
+			}
+			Ok(())
+		}
+	}
+}
+
+impl<'a> ser::Serializer for FieldSerializer<'a> {
+	type Error = Error;
+	type Ok = ();
+	type SerializeMap = ser::Impossible<(), Error>;
+	type SerializeSeq = Self;
+	type SerializeStruct = ser::Impossible<(), Error>;
+	type SerializeStructVariant = ser::Impossible<(), Error>;
+	type SerializeTuple = ser::Impossible<(), Error>;
+	type SerializeTupleStruct = ser::Impossible<(), Error>;
+	type SerializeTupleVariant = ser::Impossible<(), Error>;
+
+	fn serialize_bool(self, value: bool) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_i8(self, value: i8) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_i16(self, value: i16) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_i32(self, value: i32) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_i64(self, value: i64) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_u8(self, value: u8) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_u16(self, value: u16) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_u32(self, value: u32) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_u64(self, value: u64) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_f32(self, value: f32) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_f64(self, value: f64) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_char(self, value: char) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_str(self, value: &str) -> Result<(), Error> {
+		self.push_line(value.to_string())
+	}
+
+	fn serialize_bytes(self, _value: &[u8]) -> Result<(), Error> {
+		Err(Error::custom("unsupported data type: bytes"))
+	}
+
+	fn serialize_none(self) -> Result<(), Error> {
+		// None values are not present in the output at all.
+		Ok(())
+	}
+
+	fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+		value.serialize(self)
+	}
+
+	fn serialize_unit(self) -> Result<(), Error> {
+		Err(Error::custom("ALPM database format does not support unit values"))
+	}
+
+	fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+		Err(Error::custom("ALPM database format does not support unit structs"))
+	}
+
+	fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<(), Error> {
+		self.push_line(variant.to_string())
+	}
+
+	fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<(), Error> {
+		// ALPM database files don't describe their types, so just serialize the inner value directly.
+		value.serialize(self)
+	}
+
+	fn serialize_newtype_variant<T: Serialize + ?Sized>(
+		self,
+		_name: &'static str,
+		_index: u32,
+		_variant: &'static str,
+		_value: &T,
+	) -> Result<(), Error> {
+		Err(Error::custom("ALPM database format only supports enums without values"))
+	}
+
+	fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+		if self.in_sequence {
+			Err(Error::custom("ALPM database format does not support nested lists"))
+		} else {
+			Ok(self)
+		}
+	}
+
+	fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+		Err(Error::custom("ALPM database format does not support tuples"))
+	}
+
+	fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct, Error> {
+		Err(Error::custom("ALPM database format does not support tuple structs"))
+	}
+
+	fn serialize_tuple_variant(
+		self,
+		_name: &'static str,
+		_index: u32,
+		_variant: &'static str,
+		_len: usize,
+	) -> Result<Self::SerializeTupleVariant, Error> {
+		Err(Error::custom("ALPM database format only supports enums without values"))
+	}
+
+	fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+		Err(Error::custom("ALPM database format does not support maps"))
+	}
+
+	fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct, Error> {
+		Err(Error::custom("ALPM database format does not support nested structs"))
+	}
+
+	fn serialize_struct_variant(
+		self,
+		_name: &'static str,
+		_index: u32,
+		_variant: &'static str,
+		_len: usize,
+	) -> Result<Self::SerializeStructVariant, Error> {
+		Err(Error::custom("ALPM database format only supports enums without values"))
+	}
+}
+
+impl ser::SerializeSeq for FieldSerializer<'_> {
+	type Error = Error;
+	type Ok = ();
+
+	fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+		let serializer = FieldSerializer {
+			lines: self.lines,
+			in_sequence: true,
+		};
+		value.serialize(serializer)
+	}
+
+	fn end(self) -> Result<(), Error> {
+		Ok(())
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match &self.source {
+			Some(source) => write!(f, "{}: {}", source, self.message),
+			None => f.write_str(&self.message),
+		}
+	}
+}
+
+impl ser::Error for Error {
+	fn custom<T: std::fmt::Display>(msg: T) -> Self {
+		Self {
+			source: None,
+			message: msg.to_string(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::{assert, let_assert};
+	use serde::Serialize;
+
+	#[test]
+	#[rustfmt::skip]
+	fn simple() {
+		#[derive(Serialize)]
+		#[serde(rename_all = "UPPERCASE")]
+		struct Test {
+			foo: Vec<String>,
+			bar: Vec<i32>,
+			baz: bool,
+			empty: Vec<String>,
+			missing: Option<String>,
+		}
+
+		let value = Test {
+			foo: vec!["aap".into(), "noot".into(), "mies".into()],
+			bar: vec![10, -5, 8],
+			baz: true,
+			empty: vec![],
+			missing: None,
+		};
+
+		let_assert!(Ok(serialized) = to_string(&value));
+		assert!(serialized == [
+			"%FOO%",
+			"aap",
+			"noot",
+			"mies",
+			"",
+			"%BAR%",
+			"10",
+			"-5",
+			"8",
+			"",
+			"%BAZ%",
+			"true",
+			"",
+			"",
+		].join("\n"));
+	}
+
+	#[test]
+	fn reject_line_breaks() {
+		#[derive(Serialize)]
+		#[serde(rename_all = "UPPERCASE")]
+		struct Test {
+			foo: String,
+		}
+
+		let_assert!(Err(e) = to_string(&Test { foo: "aap\nnoot".into() }));
+		assert!(e.to_string().contains("line breaks"));
+	}
+}
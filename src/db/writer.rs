@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::archive::{read_db_archive, Compression, ReadDbArchiveError};
+use super::{DatabasePackage, SerializeError};
+
+#[derive(Debug)]
+pub enum WriteDbArchiveError {
+	UnknownExtension(PathBuf),
+	Read(ReadDbArchiveError),
+	Serialize(SerializeError),
+	Encode(std::io::Error),
+	Write(PathBuf, std::io::Error),
+}
+
+impl Compression {
+	/// Determine the compression format for a database archive from the file name.
+	///
+	/// The file name must end in `.db.tar`, `.db.tar.gz`, `.db.tar.xz` or `.db.tar.zst`, just like for `repo-add`.
+	pub fn from_db_path(path: impl AsRef<Path>) -> Option<Self> {
+		let name = path.as_ref().file_name()?.to_str()?;
+		if name.ends_with(".db.tar") {
+			Some(Self::None)
+		} else if name.ends_with(".db.tar.gz") {
+			Some(Self::Gzip)
+		} else if name.ends_with(".db.tar.xz") {
+			Some(Self::Xz)
+		} else if name.ends_with(".db.tar.zst") {
+			Some(Self::Zstd)
+		} else {
+			None
+		}
+	}
+
+	/// Wrap a writer in an encoder for the compression format.
+	fn encoder<'a, W: Write + 'a>(self, writer: W) -> std::io::Result<Encoder<'a, W>> {
+		match self {
+			Self::None => Ok(Encoder::None(writer)),
+			Self::Gzip => Ok(Encoder::Gzip(flate2::write::GzEncoder::new(writer, flate2::Compression::best()))),
+			Self::Xz => Ok(Encoder::Xz(xz2::write::XzEncoder::new(writer, 6))),
+			Self::Zstd => Ok(Encoder::Zstd(zstd::stream::write::Encoder::new(writer, 19)?)),
+		}
+	}
+}
+
+/// Writer that compresses data before passing it on.
+enum Encoder<'a, W: Write> {
+	None(W),
+	Gzip(flate2::write::GzEncoder<W>),
+	Xz(xz2::write::XzEncoder<W>),
+	Zstd(zstd::stream::write::Encoder<'a, W>),
+}
+
+impl<W: Write> Encoder<'_, W> {
+	/// Finish the compressed stream and return the wrapped writer.
+	fn finish(self) -> std::io::Result<W> {
+		match self {
+			Self::None(x) => Ok(x),
+			Self::Gzip(x) => x.finish(),
+			Self::Xz(x) => x.finish(),
+			Self::Zstd(x) => x.finish(),
+		}
+	}
+}
+
+impl<W: Write> Write for Encoder<'_, W> {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		match self {
+			Self::None(x) => x.write(buf),
+			Self::Gzip(x) => x.write(buf),
+			Self::Xz(x) => x.write(buf),
+			Self::Zstd(x) => x.write(buf),
+		}
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		match self {
+			Self::None(x) => x.flush(),
+			Self::Gzip(x) => x.flush(),
+			Self::Xz(x) => x.flush(),
+			Self::Zstd(x) => x.flush(),
+		}
+	}
+}
+
+/// Serialize a database archive with the given packages in memory.
+///
+/// Each package gets a `$pkgname-$pkgver-$pkgrel` directory with a single `desc` file holding all fields,
+/// just like `repo-add` writes them.
+pub fn write_db_archive_bytes<'a>(
+	packages: impl IntoIterator<Item = &'a DatabasePackage>,
+	compression: Compression,
+) -> Result<Vec<u8>, WriteDbArchiveError> {
+	let encoder = compression.encoder(Vec::new()).map_err(WriteDbArchiveError::Encode)?;
+	let mut builder = tar::Builder::new(encoder);
+
+	for package in packages {
+		let dir_name = format!("{}-{}", package.name, package.version);
+		let mtime = package.build_date.max(0) as u64;
+
+		let mut header = tar::Header::new_ustar();
+		header.set_entry_type(tar::EntryType::Directory);
+		header.set_mode(0o755);
+		header.set_mtime(mtime);
+		header.set_size(0);
+		header.set_cksum();
+		builder
+			.append_data(&mut header, &dir_name, std::io::empty())
+			.map_err(WriteDbArchiveError::Encode)?;
+
+		let desc = package.to_desc()?;
+		append_file(&mut builder, &format!("{}/desc", dir_name), mtime, desc.as_bytes())?;
+	}
+
+	let encoder = builder.into_inner().map_err(WriteDbArchiveError::Encode)?;
+	encoder.finish().map_err(WriteDbArchiveError::Encode)
+}
+
+/// Write a database archive with the given packages.
+///
+/// The compression format is determined from the file name, see [`Compression::from_db_path`].
+/// Any existing archive is replaced atomically, and a `$repo.db` symlink pointing to the archive is created next to it.
+pub fn write_db_archive<'a>(path: impl AsRef<Path>, packages: impl IntoIterator<Item = &'a DatabasePackage>) -> Result<(), WriteDbArchiveError> {
+	let path = path.as_ref();
+	let compression = Compression::from_db_path(path).ok_or_else(|| WriteDbArchiveError::UnknownExtension(path.into()))?;
+	let data = write_db_archive_bytes(packages, compression)?;
+
+	// Write to a temporary file first, so readers never see a partially written database.
+	let mut tmp_name = path.as_os_str().to_owned();
+	tmp_name.push(".tmp");
+	let tmp_path = PathBuf::from(tmp_name);
+	std::fs::write(&tmp_path, &data).map_err(|e| WriteDbArchiveError::Write(tmp_path.clone(), e))?;
+	std::fs::rename(&tmp_path, path).map_err(|e| WriteDbArchiveError::Write(path.into(), e))?;
+
+	make_db_symlink(path)
+}
+
+/// Add packages to a database archive, replacing existing entries with the same package name.
+///
+/// If the archive does not exist yet, it is created.
+/// The existing entries and new packages are written to the new archive in a single pass.
+pub fn add_to_db_archive<'a>(path: impl AsRef<Path>, packages: impl IntoIterator<Item = &'a DatabasePackage>) -> Result<(), WriteDbArchiveError> {
+	let path = path.as_ref();
+	let existing = if path.exists() {
+		read_db_archive(path).map_err(WriteDbArchiveError::Read)?
+	} else {
+		Vec::new()
+	};
+
+	let mut merged = BTreeMap::new();
+	for package in &existing {
+		merged.insert(package.name.as_str(), package);
+	}
+	for package in packages {
+		merged.insert(package.name.as_str(), package);
+	}
+
+	write_db_archive(path, merged.into_values())
+}
+
+/// Add a regular file to a tar archive.
+fn append_file<W: Write>(builder: &mut tar::Builder<W>, path: &str, mtime: u64, data: &[u8]) -> Result<(), WriteDbArchiveError> {
+	let mut header = tar::Header::new_ustar();
+	header.set_entry_type(tar::EntryType::Regular);
+	header.set_mode(0o644);
+	header.set_mtime(mtime);
+	header.set_size(data.len() as u64);
+	header.set_cksum();
+	builder.append_data(&mut header, path, data).map_err(WriteDbArchiveError::Encode)
+}
+
+/// Create or replace the `$repo.db` symlink for a `$repo.db.tar.*` archive.
+fn make_db_symlink(archive_path: &Path) -> Result<(), WriteDbArchiveError> {
+	let file_name = match archive_path.file_name().and_then(|x| x.to_str()) {
+		Some(x) => x,
+		None => return Err(WriteDbArchiveError::UnknownExtension(archive_path.into())),
+	};
+	let link_name = match file_name.find(".db.tar") {
+		Some(i) => &file_name[..i + 3],
+		None => return Err(WriteDbArchiveError::UnknownExtension(archive_path.into())),
+	};
+	let link_path = archive_path.with_file_name(link_name);
+
+	match std::fs::remove_file(&link_path) {
+		Ok(()) => (),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+		Err(e) => return Err(WriteDbArchiveError::Write(link_path, e)),
+	}
+	std::os::unix::fs::symlink(file_name, &link_path).map_err(|e| WriteDbArchiveError::Write(link_path, e))
+}
+
+impl From<SerializeError> for WriteDbArchiveError {
+	fn from(other: SerializeError) -> Self {
+		Self::Serialize(other)
+	}
+}
+
+impl std::error::Error for WriteDbArchiveError {}
+
+impl std::fmt::Display for WriteDbArchiveError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::UnknownExtension(path) => write!(
+				f,
+				"invalid database path {}: file name must end in .db.tar, .db.tar.gz, .db.tar.xz or .db.tar.zst",
+				path.display()
+			),
+			Self::Read(e) => e.fmt(f),
+			Self::Serialize(e) => e.fmt(f),
+			Self::Encode(e) => write!(f, "failed to create archive: {}", e),
+			Self::Write(path, e) => write!(f, "failed to write {}: {}", path.display(), e),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::db::{read_db_archive_bytes, test_package, ReadOptions};
+	use assert2::{assert, let_assert};
+
+	#[test]
+	fn round_trip() {
+		for &compression in &[Compression::None, Compression::Gzip, Compression::Xz, Compression::Zstd] {
+			let packages = vec![test_package("aap", "5.8.9-1"), test_package("noot", "5.8.9-2")];
+			let_assert!(Ok(data) = write_db_archive_bytes(&packages, compression));
+			let_assert!(Ok(parsed) = read_db_archive_bytes(&data, None, &ReadOptions::default()));
+			assert!(parsed.len() == 2);
+			assert!(parsed[0].name == "aap");
+			assert!(parsed[0].to_desc().unwrap() == packages[0].to_desc().unwrap());
+			assert!(parsed[1].name == "noot");
+			assert!(parsed[1].to_desc().unwrap() == packages[1].to_desc().unwrap());
+		}
+	}
+
+	#[test]
+	fn dependencies_in_desc() {
+		let mut package = test_package("aap", "5.8.9-1");
+		package.depends = vec!["coreutils".parse().unwrap()];
+		package.provides = vec!["linux=5.8.9".parse().unwrap()];
+		let_assert!(Ok(data) = write_db_archive_bytes(&[package], Compression::None));
+
+		let mut archive = tar::Archive::new(data.as_slice());
+		let paths: Vec<_> = archive
+			.entries()
+			.unwrap()
+			.map(|entry| entry.unwrap().path().unwrap().display().to_string())
+			.collect();
+		assert!(paths == ["aap-5.8.9-1", "aap-5.8.9-1/desc"]);
+
+		let_assert!(Ok(parsed) = read_db_archive_bytes(&data, None, &ReadOptions::default()));
+		assert!(parsed[0].depends == ["coreutils".parse().unwrap()]);
+		assert!(parsed[0].provides == ["linux=5.8.9".parse().unwrap()]);
+	}
+
+	#[test]
+	fn compression_from_path() {
+		assert!(Compression::from_db_path("foo/core.db.tar") == Some(Compression::None));
+		assert!(Compression::from_db_path("foo/core.db.tar.gz") == Some(Compression::Gzip));
+		assert!(Compression::from_db_path("foo/core.db.tar.xz") == Some(Compression::Xz));
+		assert!(Compression::from_db_path("foo/core.db.tar.zst") == Some(Compression::Zstd));
+		assert!(Compression::from_db_path("foo/core.db") == None);
+		assert!(Compression::from_db_path("foo/core.tar.gz") == None);
+	}
+
+	#[test]
+	fn add_and_replace() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let db_path = dir.join("test.db.tar.gz");
+
+		let_assert!(Ok(()) = add_to_db_archive(&db_path, &[test_package("aap", "5.8.9-1"), test_package("noot", "5.8.9-1")]));
+		let_assert!(Ok(()) = add_to_db_archive(&db_path, &[test_package("noot", "5.8.9-2"), test_package("mies", "5.8.9-1")]));
+
+		let_assert!(Ok(link) = std::fs::read_link(dir.join("test.db")));
+		assert!(link == Path::new("test.db.tar.gz"));
+
+		let_assert!(Ok(parsed) = read_db_archive(dir.join("test.db")));
+		let names: Vec<_> = parsed.iter().map(|x| (x.name.as_str(), x.version.pkgrel.as_str())).collect();
+		assert!(names == [("aap", "1"), ("mies", "1"), ("noot", "2")]);
+	}
+}
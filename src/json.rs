@@ -0,0 +1,343 @@
+//! JSON export of repository databases.
+//!
+//! [`DatabasePackage`] does not implement [`Serialize`] itself,
+//! and the [`Serialize`] implementations of versions and dependencies produce plain strings for the ALPM database format.
+//! The wrapper types in this module are the way to serialize packages to JSON,
+//! where versions and dependencies are structured objects.
+//!
+//! This module is only available with the `json` feature.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::db::{read_db_dir, BackupEntry, DatabasePackage, DatabasePackageFiles, ReadDbDirError};
+use crate::package::{Dependency, ExtraData, OptionalDependency, Provides, VersionConstraint};
+use crate::version::{PackageVersion, Version};
+
+/// Error when exporting a database directory to JSON.
+#[derive(Debug)]
+pub enum DbToJsonError {
+	Read(ReadDbDirError),
+	Write(PathBuf, serde_json::Error),
+}
+
+/// A database package that serializes to structured JSON.
+///
+/// Create it with [`JsonPackage::from`], or use [`to_json_value`] and friends for a list of packages.
+#[derive(Debug, Serialize)]
+pub struct JsonPackage<'a> {
+	filename: &'a str,
+	name: &'a str,
+	base: Option<&'a str>,
+	version: JsonVersion<'a>,
+	description: &'a str,
+	groups: &'a [String],
+	compressed_size: u64,
+	installed_size: u64,
+	md5sum: &'a str,
+	sha256sum: &'a str,
+	pgpsig: Option<&'a str>,
+	url: Option<&'a str>,
+	licenses: &'a [String],
+	arch: &'a str,
+	build_date: i64,
+	packager: &'a str,
+	replaces: &'a [String],
+	xdata: Vec<JsonExtraData<'a>>,
+	depends: Vec<JsonDependency<'a>>,
+	conflicts: Vec<JsonDependency<'a>>,
+	provides: Vec<JsonProvides<'a>>,
+	optdepends: Vec<JsonOptionalDependency<'a>>,
+	makedepends: Vec<JsonDependency<'a>>,
+	checkdepends: Vec<JsonDependency<'a>>,
+	files: Option<JsonFiles<'a>>,
+	extra: &'a BTreeMap<String, Vec<String>>,
+}
+
+/// A version as JSON object with separate `epoch`, `pkgver` and `pkgrel` fields.
+#[derive(Debug, Serialize)]
+pub struct JsonVersion<'a> {
+	epoch: i32,
+	pkgver: &'a str,
+	pkgrel: Option<&'a str>,
+}
+
+/// A version constraint as JSON object with an `operator` and a structured `version`.
+#[derive(Debug, Serialize)]
+pub struct JsonVersionConstraint<'a> {
+	operator: String,
+	version: JsonVersion<'a>,
+}
+
+/// A dependency as JSON object with a `name` and optional structured `constraint`.
+#[derive(Debug, Serialize)]
+pub struct JsonDependency<'a> {
+	name: &'a str,
+	constraint: Option<JsonVersionConstraint<'a>>,
+}
+
+/// An optional dependency as JSON object with a `name`, optional structured `constraint` and `description`.
+#[derive(Debug, Serialize)]
+pub struct JsonOptionalDependency<'a> {
+	name: &'a str,
+	constraint: Option<JsonVersionConstraint<'a>>,
+	description: &'a str,
+}
+
+/// A provision as JSON object with a `name` and optional structured `version`.
+#[derive(Debug, Serialize)]
+pub struct JsonProvides<'a> {
+	name: &'a str,
+	version: Option<JsonVersion<'a>>,
+}
+
+/// An extra data entry as JSON object with a `key` and `value`.
+#[derive(Debug, Serialize)]
+pub struct JsonExtraData<'a> {
+	key: &'a str,
+	value: &'a str,
+}
+
+/// The file list of a package as JSON object.
+#[derive(Debug, Serialize)]
+pub struct JsonFiles<'a> {
+	files: &'a [String],
+	backup: Vec<JsonBackupEntry<'a>>,
+}
+
+/// A backup entry as JSON object with a `path` and `md5sum`.
+#[derive(Debug, Serialize)]
+pub struct JsonBackupEntry<'a> {
+	path: &'a str,
+	md5sum: &'a str,
+}
+
+impl<'a> From<&'a DatabasePackage> for JsonPackage<'a> {
+	fn from(other: &'a DatabasePackage) -> Self {
+		Self {
+			filename: &other.filename,
+			name: &other.name,
+			base: other.base.as_deref(),
+			version: (&other.version).into(),
+			description: &other.description,
+			groups: &other.groups,
+			compressed_size: other.compressed_size,
+			installed_size: other.installed_size,
+			md5sum: &other.md5sum,
+			sha256sum: &other.sha256sum,
+			pgpsig: other.pgpsig.as_deref(),
+			url: other.url.as_deref(),
+			licenses: &other.licenses,
+			arch: &other.arch,
+			build_date: other.build_date,
+			packager: &other.packager,
+			replaces: &other.replaces,
+			xdata: other.xdata.iter().map(Into::into).collect(),
+			depends: other.depends.iter().map(Into::into).collect(),
+			conflicts: other.conflicts.iter().map(Into::into).collect(),
+			provides: other.provides.iter().map(Into::into).collect(),
+			optdepends: other.optdepends.iter().map(Into::into).collect(),
+			makedepends: other.makedepends.iter().map(Into::into).collect(),
+			checkdepends: other.checkdepends.iter().map(Into::into).collect(),
+			files: other.files.as_ref().map(Into::into),
+			extra: &other.extra,
+		}
+	}
+}
+
+impl<'a> From<&'a Version> for JsonVersion<'a> {
+	fn from(other: &'a Version) -> Self {
+		Self {
+			epoch: other.epoch,
+			pkgver: &other.pkgver,
+			pkgrel: other.pkgrel.as_deref(),
+		}
+	}
+}
+
+impl<'a> From<&'a PackageVersion> for JsonVersion<'a> {
+	fn from(other: &'a PackageVersion) -> Self {
+		Self {
+			epoch: other.epoch,
+			pkgver: &other.pkgver,
+			pkgrel: Some(&other.pkgrel),
+		}
+	}
+}
+
+impl<'a> From<&'a VersionConstraint> for JsonVersionConstraint<'a> {
+	fn from(other: &'a VersionConstraint) -> Self {
+		Self {
+			operator: other.constraint.to_string(),
+			version: (&other.version).into(),
+		}
+	}
+}
+
+impl<'a> From<&'a Dependency> for JsonDependency<'a> {
+	fn from(other: &'a Dependency) -> Self {
+		Self {
+			name: &other.name,
+			constraint: other.version.as_ref().map(Into::into),
+		}
+	}
+}
+
+impl<'a> From<&'a OptionalDependency> for JsonOptionalDependency<'a> {
+	fn from(other: &'a OptionalDependency) -> Self {
+		Self {
+			name: &other.name,
+			constraint: other.version.as_ref().map(Into::into),
+			description: &other.description,
+		}
+	}
+}
+
+impl<'a> From<&'a Provides> for JsonProvides<'a> {
+	fn from(other: &'a Provides) -> Self {
+		Self {
+			name: &other.name,
+			version: other.version.as_ref().map(Into::into),
+		}
+	}
+}
+
+impl<'a> From<&'a ExtraData> for JsonExtraData<'a> {
+	fn from(other: &'a ExtraData) -> Self {
+		Self {
+			key: &other.key,
+			value: &other.value,
+		}
+	}
+}
+
+impl<'a> From<&'a DatabasePackageFiles> for JsonFiles<'a> {
+	fn from(other: &'a DatabasePackageFiles) -> Self {
+		Self {
+			files: &other.files,
+			backup: other.backup.iter().map(Into::into).collect(),
+		}
+	}
+}
+
+impl<'a> From<&'a BackupEntry> for JsonBackupEntry<'a> {
+	fn from(other: &'a BackupEntry) -> Self {
+		Self {
+			path: &other.path,
+			md5sum: &other.md5sum,
+		}
+	}
+}
+
+/// Convert a list of packages to a JSON value.
+pub fn to_json_value(packages: &[DatabasePackage]) -> serde_json::Value {
+	let packages: Vec<JsonPackage> = packages.iter().map(Into::into).collect();
+	// Serializing to a value can only fail for maps with non-string keys, which are not used here.
+	serde_json::to_value(packages).unwrap()
+}
+
+/// Serialize a list of packages as pretty-printed JSON.
+pub fn to_json_string(packages: &[DatabasePackage]) -> Result<String, serde_json::Error> {
+	let packages: Vec<JsonPackage> = packages.iter().map(Into::into).collect();
+	serde_json::to_string_pretty(&packages)
+}
+
+/// Serialize a list of packages as pretty-printed JSON to a writer.
+pub fn to_json_writer(writer: impl std::io::Write, packages: &[DatabasePackage]) -> Result<(), serde_json::Error> {
+	let packages: Vec<JsonPackage> = packages.iter().map(Into::into).collect();
+	serde_json::to_writer_pretty(writer, &packages)
+}
+
+/// Read a folder containing an extracted repository database and write all packages as JSON.
+///
+/// The JSON document is a list of packages, as produced by [`to_json_writer`].
+pub fn db_dir_to_json(db_dir: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<(), DbToJsonError> {
+	let output = output.as_ref();
+	let packages = read_db_dir(db_dir).map_err(DbToJsonError::Read)?;
+	let file = std::fs::File::create(output).map_err(|e| DbToJsonError::Write(output.into(), serde_json::Error::io(e)))?;
+	let mut writer = std::io::BufWriter::new(file);
+	to_json_writer(&mut writer, &packages).map_err(|e| DbToJsonError::Write(output.into(), e))?;
+	std::io::Write::flush(&mut writer).map_err(|e| DbToJsonError::Write(output.into(), serde_json::Error::io(e)))
+}
+
+impl std::error::Error for DbToJsonError {}
+
+impl std::fmt::Display for DbToJsonError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Read(e) => e.fmt(f),
+			Self::Write(path, e) => write!(f, "failed to write JSON to {}: {}", path.display(), e),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::db::test_package;
+	use assert2::{assert, let_assert};
+
+	#[test]
+	fn package_to_json() {
+		let mut package = test_package("linux", "5.8.9-2");
+		package.depends = vec!["glibc>=2.35".parse().unwrap(), "zlib".parse().unwrap()];
+		package.provides = vec!["libfoo.so=1-64".parse().unwrap()];
+		package.optdepends = vec!["perl: for scripts".parse().unwrap()];
+
+		let value = to_json_value(std::slice::from_ref(&package));
+		let json = &value[0];
+		assert!(json["name"] == package.name.as_str());
+		assert!(json["version"]["epoch"] == package.version.epoch);
+		assert!(json["version"]["pkgver"] == package.version.pkgver.as_str());
+		assert!(json["version"]["pkgrel"] == package.version.pkgrel.as_str());
+		assert!(json["compressed_size"] == package.compressed_size);
+
+		assert!(json["depends"][0]["name"] == "glibc");
+		assert!(json["depends"][0]["constraint"]["operator"] == ">=");
+		assert!(json["depends"][0]["constraint"]["version"] == serde_json::json!({"epoch": 0, "pkgver": "2.35", "pkgrel": null}));
+		assert!(json["depends"][1] == serde_json::json!({"name": "zlib", "constraint": null}));
+		assert!(json["provides"][0] == serde_json::json!({"name": "libfoo.so", "version": {"epoch": 0, "pkgver": "1", "pkgrel": "64"}}));
+		assert!(json["optdepends"][0] == serde_json::json!({"name": "perl", "constraint": null, "description": "for scripts"}));
+		assert!(json["files"].is_null());
+
+		let_assert!(Ok(string) = to_json_string(&[package]));
+		let_assert!(Ok(parsed) = serde_json::from_str::<serde_json::Value>(&string));
+		assert!(parsed == value);
+	}
+
+	#[test]
+	fn package_with_dependencies_to_json() {
+		let mut package = test_package("linux", "5.8.9-2");
+		package.depends = vec!["glibc>=2.35".parse().unwrap()];
+		package.conflicts = vec!["linux".parse().unwrap()];
+		package.makedepends = vec!["bc".parse().unwrap()];
+		package.checkdepends = vec!["python=3.10".parse().unwrap()];
+
+		let_assert!(Ok(json) = serde_json::to_value(JsonPackage::from(&package)));
+		assert!(json["version"] == serde_json::json!({"epoch": 0, "pkgver": "5.8.9", "pkgrel": "2"}));
+		assert!(json["depends"][0]["constraint"]["version"]["pkgver"] == "2.35");
+		assert!(json["conflicts"] == serde_json::json!([{"name": "linux", "constraint": null}]));
+		assert!(json["makedepends"] == serde_json::json!([{"name": "bc", "constraint": null}]));
+		assert!(json["checkdepends"][0]["constraint"]["operator"] == "=");
+		assert!(json["provides"] == serde_json::json!([]));
+	}
+
+	#[test]
+	fn db_dir_to_json_file() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let package_dir = dir.join("db").join("package-1.0-1");
+		std::fs::create_dir_all(&package_dir).unwrap();
+		std::fs::write(package_dir.join("desc"), include_bytes!("../tests/database-package/desc")).unwrap();
+		std::fs::write(package_dir.join("depends"), include_bytes!("../tests/database-package/depends")).unwrap();
+
+		let_assert!(Ok(()) = db_dir_to_json(dir.join("db"), dir.join("db.json")));
+		let_assert!(Ok(data) = std::fs::read(dir.join("db.json")));
+		let_assert!(Ok(serde_json::Value::Array(packages)) = serde_json::from_slice(&data));
+		assert!(packages.len() == 1);
+		assert!(packages[0]["depends"].as_array().map(|x| x.len()) > Some(0));
+
+		let_assert!(Err(DbToJsonError::Read(_)) = db_dir_to_json(dir.join("missing"), dir.join("missing.json")));
+	}
+}
@@ -24,7 +24,11 @@
 #[macro_use]
 pub mod msg;
 
+pub mod config;
 pub mod db;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod package;
 pub mod parse;
+pub mod signature;
 pub mod version;
@@ -0,0 +1,273 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::mtree::MtreeError;
+use super::pkginfo::{KeyValueError, PackageInfo};
+use crate::db::{Compression, DatabasePackage};
+
+/// Error when reading a package archive.
+#[derive(Debug)]
+pub enum ReadPackageError {
+	Open(PathBuf, std::io::Error),
+	Read(PathBuf, std::io::Error),
+	UnknownCompression(PathBuf),
+	MissingPkgInfo(PathBuf),
+	InvalidPkgInfo(PathBuf, KeyValueError),
+	InvalidBuildInfo(PathBuf, KeyValueError),
+	InvalidMtree(PathBuf, MtreeError),
+	ReadSignature(PathBuf, std::io::Error),
+}
+
+/// The size and checksums of a package file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PackageChecksums {
+	pub size: u64,
+	pub md5sum: String,
+	pub sha256sum: String,
+}
+
+impl PackageInfo {
+	/// Read the `.PKGINFO` file from a package archive.
+	///
+	/// The archive may be uncompressed, or compressed with gzip, xz or zstd.
+	pub fn from_package_file(path: impl AsRef<Path>) -> Result<Self, ReadPackageError> {
+		let path = path.as_ref();
+		let data = read_archive_file(path, ".PKGINFO")?.ok_or_else(|| ReadPackageError::MissingPkgInfo(path.into()))?;
+		let data = String::from_utf8_lossy(&data);
+		data.parse().map_err(|e| ReadPackageError::InvalidPkgInfo(path.into(), e))
+	}
+}
+
+impl PackageChecksums {
+	/// Compute the size and checksums of a file.
+	pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ReadPackageError> {
+		use sha2::Digest;
+
+		let path = path.as_ref();
+		let mut file = std::fs::File::open(path).map_err(|e| ReadPackageError::Open(path.into(), e))?;
+		let mut md5 = md5::Md5::new();
+		let mut sha256 = sha2::Sha256::new();
+		let mut size = 0;
+		let mut buffer = vec![0; 64 * 1024];
+		loop {
+			let read = file.read(&mut buffer).map_err(|e| ReadPackageError::Read(path.into(), e))?;
+			if read == 0 {
+				break;
+			}
+			md5.update(&buffer[..read]);
+			sha256.update(&buffer[..read]);
+			size += read as u64;
+		}
+
+		Ok(Self {
+			size,
+			md5sum: format!("{:x}", md5.finalize()),
+			sha256sum: format!("{:x}", sha256.finalize()),
+		})
+	}
+}
+
+impl DatabasePackage {
+	/// Create a database entry for a package archive, like `repo-add` does.
+	///
+	/// The package information is taken from the `.PKGINFO` file in the archive.
+	/// The compressed size and checksums are computed from the archive itself.
+	/// If a detached signature exists next to the package (with an added `.sig` extension),
+	/// it is included as base64 encoded `%PGPSIG%`.
+	pub fn from_package_file(path: impl AsRef<Path>) -> Result<Self, ReadPackageError> {
+		let path = path.as_ref();
+		let info = PackageInfo::from_package_file(path)?;
+		let checksums = PackageChecksums::from_file(path)?;
+
+		let mut sig_path = path.as_os_str().to_owned();
+		sig_path.push(".sig");
+		let sig_path = PathBuf::from(sig_path);
+		let pgpsig = match std::fs::read(&sig_path) {
+			Ok(data) => Some(base64::Engine::encode(&base64::engine::general_purpose::STANDARD, data)),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+			Err(e) => return Err(ReadPackageError::ReadSignature(sig_path, e)),
+		};
+
+		let filename = path.file_name().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+
+		Ok(Self::from_package_info(info, filename, checksums, pgpsig))
+	}
+
+	/// Create a database entry from the parsed `.PKGINFO` and checksums of a package file.
+	pub fn from_package_info(info: PackageInfo, filename: String, checksums: PackageChecksums, pgpsig: Option<String>) -> Self {
+		Self {
+			filename,
+			name: info.name,
+			base: info.base,
+			version: info.version,
+			description: info.description,
+			groups: info.groups,
+			compressed_size: checksums.size,
+			installed_size: info.installed_size,
+			md5sum: checksums.md5sum,
+			sha256sum: checksums.sha256sum,
+			pgpsig,
+			url: info.url,
+			licenses: info.licenses,
+			arch: info.arch,
+			build_date: info.build_date,
+			packager: info.packager,
+			replaces: info.replaces,
+			xdata: info.xdata,
+			depends: info.depends,
+			conflicts: info.conflicts,
+			provides: info.provides,
+			optdepends: info.optdepends,
+			makedepends: info.makedepends,
+			checkdepends: info.checkdepends,
+			files: None,
+			extra: Default::default(),
+			depends_extra: Default::default(),
+		}
+	}
+}
+
+/// Read a single file from the root of a package archive.
+///
+/// Returns `Ok(None)` if the archive does not contain the file.
+pub(crate) fn read_archive_file(path: &Path, name: &str) -> Result<Option<Vec<u8>>, ReadPackageError> {
+	let read_error = |e| ReadPackageError::Read(path.into(), e);
+	let mut file = std::fs::File::open(path).map_err(|e| ReadPackageError::Open(path.into(), e))?;
+
+	// Read enough data to detect the compression format, then put it back in front of the stream.
+	let mut header = Vec::with_capacity(512);
+	(&mut file).take(512).read_to_end(&mut header).map_err(read_error)?;
+	let compression = Compression::detect(&header).ok_or_else(|| ReadPackageError::UnknownCompression(path.into()))?;
+	let decoder = compression.decoder(header.as_slice().chain(file)).map_err(read_error)?;
+
+	let mut archive = tar::Archive::new(decoder);
+	for entry in archive.entries().map_err(read_error)? {
+		let mut entry = entry.map_err(read_error)?;
+		let entry_path = entry.path().map_err(read_error)?;
+		if entry_path.to_str().map(|x| x.trim_start_matches("./")) != Some(name) {
+			continue;
+		}
+		let mut buffer = Vec::with_capacity(entry.header().size().unwrap_or(0) as usize);
+		entry.read_to_end(&mut buffer).map_err(read_error)?;
+		return Ok(Some(buffer));
+	}
+
+	Ok(None)
+}
+
+impl std::error::Error for ReadPackageError {}
+
+impl std::fmt::Display for ReadPackageError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Open(path, e) => write!(f, "failed to open {}: {}", path.display(), e),
+			Self::Read(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+			Self::UnknownCompression(path) => write!(f, "unrecognized archive format: {}", path.display()),
+			Self::MissingPkgInfo(path) => write!(f, "missing .PKGINFO in package {}", path.display()),
+			Self::InvalidPkgInfo(path, e) => write!(f, "invalid .PKGINFO in package {}: {}", path.display(), e),
+			Self::InvalidBuildInfo(path, e) => write!(f, "invalid .BUILDINFO in package {}: {}", path.display(), e),
+			Self::InvalidMtree(path, e) => write!(f, "invalid mtree file in {}: {}", path.display(), e),
+			Self::ReadSignature(path, e) => write!(f, "failed to read signature {}: {}", path.display(), e),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::{assert, let_assert};
+
+	const PKGINFO: &[u8] = include_bytes!("../../tests/package/.PKGINFO");
+	const BUILDINFO: &[u8] = include_bytes!("../../tests/package/.BUILDINFO");
+
+	fn make_package() -> Vec<u8> {
+		let mut builder = tar::Builder::new(Vec::new());
+		for (path, data) in &[
+			(".BUILDINFO", BUILDINFO),
+			(".PKGINFO", PKGINFO),
+			("usr/bin/pacman", b"#!/bin/sh\n".as_slice()),
+		] {
+			let mut header = tar::Header::new_gnu();
+			header.set_size(data.len() as u64);
+			header.set_mode(0o644);
+			header.set_cksum();
+			builder.append_data(&mut header, path, *data).unwrap();
+		}
+		zstd::stream::encode_all(builder.into_inner().unwrap().as_slice(), 0).unwrap()
+	}
+
+	#[test]
+	fn database_package_from_package_file() {
+		use sha2::Digest;
+
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let pkg_path = dir.join("pacman-6.0.1-5-x86_64.pkg.tar.zst");
+		let data = make_package();
+		std::fs::write(&pkg_path, &data).unwrap();
+		std::fs::write(dir.join("pacman-6.0.1-5-x86_64.pkg.tar.zst.sig"), b"signature").unwrap();
+
+		let_assert!(Ok(package) = DatabasePackage::from_package_file(&pkg_path));
+		assert!(package.filename == "pacman-6.0.1-5-x86_64.pkg.tar.zst");
+		assert!(package.compressed_size == data.len() as u64);
+		assert!(package.sha256sum == format!("{:x}", sha2::Sha256::digest(&data)));
+		assert!(package.md5sum == format!("{:x}", md5::Md5::digest(&data)));
+		assert!(package.pgpsig.as_deref() == Some("c2lnbmF0dXJl"));
+		assert!(package.makedepends.len() == 3);
+
+		let_assert!(Ok(Some(buildinfo)) = crate::package::BuildInfo::from_package_file(&pkg_path));
+		assert!(buildinfo.name == "pacman");
+		assert!(buildinfo.installed.len() == 16);
+
+		let expected = format!(
+			concat!(
+				"%FILENAME%\npacman-6.0.1-5-x86_64.pkg.tar.zst\n\n",
+				"%NAME%\npacman\n\n",
+				"%BASE%\npacman\n\n",
+				"%VERSION%\n6.0.1-5\n\n",
+				"%DESC%\nA library-based package manager with dependency support\n\n",
+				"%GROUPS%\nbase-devel\n\n",
+				"%CSIZE%\n{}\n\n",
+				"%ISIZE%\n4753874\n\n",
+				"%MD5SUM%\n{}\n\n",
+				"%SHA256SUM%\n{}\n\n",
+				"%PGPSIG%\nc2lnbmF0dXJl\n\n",
+				"%URL%\nhttps://www.archlinux.org/pacman/\n\n",
+				"%LICENSE%\nGPL\n\n",
+				"%ARCH%\nx86_64\n\n",
+				"%BUILDDATE%\n1651010052\n\n",
+				"%PACKAGER%\nMorten Linderud <foxboron@archlinux.org>\n\n",
+				"%REPLACES%\npacman-contrib<1.0\n\n",
+				"%PROVIDES%\nlibalpm.so=13-64\n\n",
+				"%DEPENDS%\nbash\nglibc\nlibarchive\ncurl\ngpgme\npacman-mirrorlist\narchlinux-keyring\n\n",
+				"%OPTDEPENDS%\nperl-locale-gettext: translation support in makepkg-template\n\n",
+				"%MAKEDEPENDS%\nmeson\nasciidoc\ndoxygen\n\n",
+				"%CHECKDEPENDS%\npython\nfakechroot\n\n",
+				"%XDATA%\npkgtype=pkg\n\n",
+			),
+			data.len(),
+			package.md5sum,
+			package.sha256sum,
+		);
+		assert!(package.to_desc().unwrap() == expected);
+
+		std::fs::remove_file(dir.join("pacman-6.0.1-5-x86_64.pkg.tar.zst.sig")).unwrap();
+		let_assert!(Ok(package) = DatabasePackage::from_package_file(&pkg_path));
+		assert!(package.pgpsig == None);
+	}
+
+	#[test]
+	fn missing_pkginfo() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let pkg_path = dir.join("empty.pkg.tar");
+		let mut builder = tar::Builder::new(Vec::new());
+		let mut header = tar::Header::new_ustar();
+		header.set_size(0);
+		header.set_cksum();
+		builder.append_data(&mut header, "foo", std::io::empty()).unwrap();
+		std::fs::write(&pkg_path, builder.into_inner().unwrap()).unwrap();
+
+		let_assert!(Err(ReadPackageError::MissingPkgInfo(_)) = DatabasePackage::from_package_file(&pkg_path));
+	}
+}
@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::archive::{read_archive_file, ReadPackageError};
+use super::pkginfo::{key_value_lines, parse_value, KeyValueError};
+use crate::parse::rpartition;
+use crate::version::{PackageVersion, PackageVersionFromStrError};
+
+/// The contents of a `.BUILDINFO` file from a package archive.
+///
+/// The file describes the environment the package was built in,
+/// which is needed to reproduce the build.
+#[derive(Clone, Debug)]
+pub struct BuildInfo {
+	/// The version of the `.BUILDINFO` format.
+	pub format: u32,
+	pub name: String,
+	pub base: Option<String>,
+	pub version: PackageVersion,
+	pub arch: String,
+	pub pkgbuild_sha256sum: String,
+	pub packager: String,
+	pub build_date: i64,
+	pub builddir: String,
+	pub startdir: Option<String>,
+	pub buildtool: Option<String>,
+	pub buildtoolver: Option<String>,
+
+	/// The `BUILDENV` settings of `makepkg`, with a leading `!` for disabled settings.
+	pub buildenv: Vec<String>,
+
+	/// The `OPTIONS` settings of `makepkg`, with a leading `!` for disabled options.
+	pub options: Vec<String>,
+
+	/// The packages that were installed in the build environment.
+	pub installed: Vec<InstalledPackage>,
+
+	/// Unknown keys with all their values.
+	pub extra: BTreeMap<String, Vec<String>>,
+}
+
+/// A package that was installed when a package was built.
+///
+/// This is written as `$pkgname-$pkgver-$pkgrel-$arch` in `.BUILDINFO` files.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InstalledPackage {
+	pub name: String,
+	pub version: PackageVersion,
+	pub arch: String,
+}
+
+/// Error when parsing an [`InstalledPackage`] from a string.
+#[derive(Debug, Eq, PartialEq)]
+pub enum InstalledPackageFromStrError {
+	MissingArch,
+	MissingPkgrel,
+	MissingPkgver,
+	InvalidVersion(PackageVersionFromStrError),
+}
+
+impl InstalledPackage {
+	pub fn new(name: impl Into<String>, version: PackageVersion, arch: impl Into<String>) -> Self {
+		Self {
+			name: name.into(),
+			version,
+			arch: arch.into(),
+		}
+	}
+}
+
+impl BuildInfo {
+	/// Read the `.BUILDINFO` file from a package archive.
+	///
+	/// Returns `Ok(None)` if the package does not contain a `.BUILDINFO` file.
+	pub fn from_package_file(path: impl AsRef<Path>) -> Result<Option<Self>, ReadPackageError> {
+		let path = path.as_ref();
+		let data = match read_archive_file(path, ".BUILDINFO")? {
+			Some(x) => x,
+			None => return Ok(None),
+		};
+		let data = String::from_utf8_lossy(&data);
+		let info = data.parse().map_err(|e| ReadPackageError::InvalidBuildInfo(path.into(), e))?;
+		Ok(Some(info))
+	}
+}
+
+impl std::str::FromStr for BuildInfo {
+	type Err = KeyValueError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let mut info = Self {
+			format: 0,
+			name: String::new(),
+			base: None,
+			version: PackageVersion::new(0, "", ""),
+			arch: String::new(),
+			pkgbuild_sha256sum: String::new(),
+			packager: String::new(),
+			build_date: 0,
+			builddir: String::new(),
+			startdir: None,
+			buildtool: None,
+			buildtoolver: None,
+			buildenv: Vec::new(),
+			options: Vec::new(),
+			installed: Vec::new(),
+			extra: BTreeMap::new(),
+		};
+		let mut have_version = false;
+
+		for entry in key_value_lines(input) {
+			let (line, key, value) = entry?;
+			match key {
+				"format" => info.format = parse_value(line, key, value)?,
+				"pkgname" => info.name = value.into(),
+				"pkgbase" => info.base = Some(value.into()),
+				"pkgver" => {
+					info.version = parse_value(line, key, value)?;
+					have_version = true;
+				},
+				"pkgarch" => info.arch = value.into(),
+				"pkgbuild_sha256sum" => info.pkgbuild_sha256sum = value.into(),
+				"packager" => info.packager = value.into(),
+				"builddate" => info.build_date = parse_value(line, key, value)?,
+				"builddir" => info.builddir = value.into(),
+				"startdir" => info.startdir = Some(value.into()),
+				"buildtool" => info.buildtool = Some(value.into()),
+				"buildtoolver" => info.buildtoolver = Some(value.into()),
+				"buildenv" => info.buildenv.push(value.into()),
+				"options" => info.options.push(value.into()),
+				"installed" => info.installed.push(parse_value(line, key, value)?),
+				_ => info.extra.entry(key.into()).or_default().push(value.into()),
+			}
+		}
+
+		if info.format == 0 {
+			return Err(KeyValueError::missing_key("format"));
+		}
+		if info.name.is_empty() {
+			return Err(KeyValueError::missing_key("pkgname"));
+		}
+		if !have_version {
+			return Err(KeyValueError::missing_key("pkgver"));
+		}
+		Ok(info)
+	}
+}
+
+impl std::str::FromStr for InstalledPackage {
+	type Err = InstalledPackageFromStrError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let (rest, arch) = rpartition(input, '-').ok_or(InstalledPackageFromStrError::MissingArch)?;
+		let (rest, _pkgrel) = rpartition(rest, '-').ok_or(InstalledPackageFromStrError::MissingPkgrel)?;
+		let (name, _pkgver) = rpartition(rest, '-').ok_or(InstalledPackageFromStrError::MissingPkgver)?;
+		let version = input[name.len() + 1..input.len() - arch.len() - 1]
+			.parse()
+			.map_err(InstalledPackageFromStrError::InvalidVersion)?;
+		Ok(Self::new(name, version, arch))
+	}
+}
+
+impl std::fmt::Display for InstalledPackage {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}-{}-{}", self.name, self.version, self.arch)
+	}
+}
+
+impl std::error::Error for InstalledPackageFromStrError {}
+
+impl std::fmt::Display for InstalledPackageFromStrError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::MissingArch => write!(f, "missing architecture"),
+			Self::MissingPkgrel => write!(f, "missing pkgrel"),
+			Self::MissingPkgver => write!(f, "missing pkgver"),
+			Self::InvalidVersion(e) => e.fmt(f),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::{assert, let_assert};
+
+	#[test]
+	fn parse_buildinfo() {
+		let_assert!(Ok(info) = include_str!("../../tests/package/.BUILDINFO").parse::<BuildInfo>());
+		assert!(info.format == 2);
+		assert!(info.name == "pacman");
+		assert!(info.version == PackageVersion::new(0, "6.0.1", "5"));
+		assert!(info.arch == "x86_64");
+		assert!(info.pkgbuild_sha256sum == "9e3c3c2a0d6ad1e77b03a91dec0bb5fb0e8b7c1a6d3c0a2c3a2fbd0b6d2a7f31");
+		assert!(info.builddir == "/build");
+		assert!(info.buildtoolver.as_deref() == Some("1:20220207-1-any"));
+		assert!(info.buildenv.len() == 5);
+		assert!(info.options.len() == 9);
+		assert!(info.options[2] == "!libtool");
+		assert!(info.installed.len() == 16);
+		assert!(info.installed[0] == InstalledPackage::new("acl", PackageVersion::new(0, "2.3.1", "2"), "x86_64"));
+		assert!(info.installed[9] == InstalledPackage::new("ca-certificates-mozilla", PackageVersion::new(0, "3.77", "1"), "x86_64"));
+		assert!(info.installed[15] == InstalledPackage::new("lz4", PackageVersion::new(1, "1.9.3", "2"), "x86_64"));
+		assert!(info.extra.is_empty());
+	}
+
+	#[test]
+	fn parse_installed_package() {
+		let_assert!(Ok(installed) = "archlinux-keyring-20220424-1-any".parse::<InstalledPackage>());
+		assert!(installed.name == "archlinux-keyring");
+		assert!(installed.version == PackageVersion::new(0, "20220424", "1"));
+		assert!(installed.arch == "any");
+		assert!(installed.to_string() == "archlinux-keyring-20220424-1-any");
+
+		assert!("foo-1-x86_64".parse::<InstalledPackage>() == Err(InstalledPackageFromStrError::MissingPkgver));
+		assert!("x86_64".parse::<InstalledPackage>() == Err(InstalledPackageFromStrError::MissingArch));
+	}
+}
@@ -0,0 +1,649 @@
+use std::cmp::Ordering;
+
+use crate::db::DatabasePackage;
+use crate::parse::partition;
+use crate::version::{vercmp, Version, VersionFromStrError};
+
+mod archive;
+mod buildinfo;
+mod mtree;
+mod name;
+mod pkginfo;
+mod range;
+mod soname;
+mod verify;
+
+pub use archive::{PackageChecksums, ReadPackageError};
+pub use buildinfo::{BuildInfo, InstalledPackage, InstalledPackageFromStrError};
+pub use mtree::{Mtree, MtreeEntry, MtreeError, MtreeFileType};
+pub use name::{validate_package_name, PackageName, PackageNameError, MAX_PACKAGE_NAME_LENGTH};
+pub use pkginfo::{KeyValueError, PackageInfo};
+pub use range::VersionRange;
+pub use soname::{find_soname_dependents, find_soname_providers, Soname, SonameFromStrError};
+pub use verify::{verify_tree, Mismatch, ModifiedFile, VerifyError, VerifyOptions, VerifyReport};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Provides {
+	pub name: PackageName,
+	pub version: Option<Version>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Dependency {
+	pub name: PackageName,
+	pub version: Option<VersionConstraint>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OptionalDependency {
+	pub name: PackageName,
+	pub version: Option<VersionConstraint>,
+	pub description: String,
+}
+
+/// An extra data entry, as found in `%XDATA%` in databases or `xdata` in `.PKGINFO` files.
+///
+/// Extra data entries have the form `key=value`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExtraData {
+	pub key: String,
+	pub value: String,
+}
+
+/// Error when parsing a [`Provides`], [`Dependency`] or [`OptionalDependency`] from a string.
+#[derive(Debug, Eq, PartialEq)]
+pub enum DependencyFromStrError {
+	InvalidName(PackageNameError),
+	InvalidVersion(VersionFromStrError),
+}
+
+/// Error when parsing [`ExtraData`] from a string.
+#[derive(Debug, Eq, PartialEq)]
+pub enum ExtraDataFromStrError {
+	MissingSeparator,
+	EmptyKey,
+}
+
+/// A version constraint operator.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Constraint {
+	Equal,
+	Greater,
+	GreaterEqual,
+	Less,
+	LessEqual,
+}
+
+/// A version constraint as used for package dependencies.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VersionConstraint {
+	pub version: Version,
+	pub constraint: Constraint,
+}
+
+/// Error when parsing a [`VersionConstraint`] from a string.
+#[derive(Debug, Eq, PartialEq)]
+pub enum VersionConstraintFromStrError {
+	MissingOperator,
+	InvalidVersion(VersionFromStrError),
+}
+
+impl Provides {
+	pub fn new(name: PackageName, version: Option<Version>) -> Self {
+		Self { name, version }
+	}
+
+	pub fn unversioned(name: PackageName) -> Self {
+		Self::new(name, None)
+	}
+
+	pub fn versioned(name: PackageName, version: Version) -> Self {
+		Self::new(name, Some(version))
+	}
+}
+
+impl Dependency {
+	pub fn new(name: PackageName, version: Option<VersionConstraint>) -> Self {
+		Self { name, version }
+	}
+
+	pub fn unconstrained(name: PackageName) -> Self {
+		Self::new(name, None)
+	}
+
+	pub fn constrained(name: PackageName, version: VersionConstraint) -> Self {
+		Self::new(name, Some(version))
+	}
+
+	pub fn constrained_equal(name: PackageName, version: Version) -> Self {
+		let version = Some(VersionConstraint {
+			version,
+			constraint: Constraint::Equal,
+		});
+		Self::new(name, version)
+	}
+
+	pub fn constrained_less(name: PackageName, version: Version) -> Self {
+		let version = Some(VersionConstraint {
+			version,
+			constraint: Constraint::Less,
+		});
+		Self::new(name, version)
+	}
+
+	pub fn constrained_less_equal(name: PackageName, version: Version) -> Self {
+		let version = Some(VersionConstraint {
+			version,
+			constraint: Constraint::LessEqual,
+		});
+		Self::new(name, version)
+	}
+
+	pub fn constrained_greater(name: PackageName, version: Version) -> Self {
+		let version = Some(VersionConstraint {
+			version,
+			constraint: Constraint::Greater,
+		});
+		Self::new(name, version)
+	}
+
+	pub fn constrained_greater_equal(name: PackageName, version: Version) -> Self {
+		let version = Some(VersionConstraint {
+			version,
+			constraint: Constraint::GreaterEqual,
+		});
+		Self::new(name, version)
+	}
+}
+
+impl Dependency {
+	/// Check if a package satisfies the dependency.
+	///
+	/// This follows the semantics of libalpm:
+	/// the dependency is satisfied if the package has the right name and version,
+	/// or if the package provides the dependency (see [`Dependency::is_satisfied_by_provides`]).
+	pub fn is_satisfied_by(&self, package: &DatabasePackage) -> bool {
+		if package.name == self.name {
+			let satisfied = match &self.version {
+				None => true,
+				Some(constraint) => constraint.is_satisfied_by(&Version::from(&package.version)),
+			};
+			if satisfied {
+				return true;
+			}
+		}
+		package.provides.iter().any(|provides| self.is_satisfied_by_provides(provides))
+	}
+
+	/// Check if a provision satisfies the dependency.
+	///
+	/// An unversioned provision only satisfies unconstrained dependencies.
+	/// A versioned provision satisfies the dependency if the provided version matches the constraint.
+	pub fn is_satisfied_by_provides(&self, provides: &Provides) -> bool {
+		if provides.name != self.name {
+			return false;
+		}
+		match (&self.version, &provides.version) {
+			(None, _) => true,
+			(Some(_), None) => false,
+			(Some(constraint), Some(version)) => constraint.is_satisfied_by(version),
+		}
+	}
+}
+
+impl VersionConstraint {
+	/// Check if a version satisfies the constraint.
+	///
+	/// Versions are compared with [`vercmp`], so if either version has no pkgrel, only the epoch and pkgver are compared.
+	pub fn is_satisfied_by(&self, version: &Version) -> bool {
+		let ordering = vercmp(version, &self.version);
+		match self.constraint {
+			Constraint::Equal => ordering == Ordering::Equal,
+			Constraint::Greater => ordering == Ordering::Greater,
+			Constraint::GreaterEqual => ordering != Ordering::Less,
+			Constraint::Less => ordering == Ordering::Less,
+			Constraint::LessEqual => ordering != Ordering::Greater,
+		}
+	}
+}
+
+impl OptionalDependency {
+	pub fn new(name: PackageName, version: Option<VersionConstraint>, description: impl Into<String>) -> Self {
+		Self {
+			name,
+			version,
+			description: description.into(),
+		}
+	}
+}
+
+impl ExtraData {
+	pub fn new(key: impl Into<String>, value: impl Into<String>) -> Self {
+		Self {
+			key: key.into(),
+			value: value.into(),
+		}
+	}
+}
+
+impl std::str::FromStr for ExtraData {
+	type Err = ExtraDataFromStrError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let (key, value) = partition(input, '=').ok_or(ExtraDataFromStrError::MissingSeparator)?;
+		if key.is_empty() {
+			Err(ExtraDataFromStrError::EmptyKey)
+		} else {
+			Ok(Self::new(key, value))
+		}
+	}
+}
+
+impl std::str::FromStr for VersionConstraint {
+	type Err = VersionConstraintFromStrError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let (constraint, version) = parse_constraint(input).ok_or(VersionConstraintFromStrError::MissingOperator)?;
+		let version = version.parse().map_err(VersionConstraintFromStrError::InvalidVersion)?;
+		Ok(Self { version, constraint })
+	}
+}
+
+impl std::str::FromStr for Provides {
+	type Err = DependencyFromStrError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		if let Some((name, version)) = partition(input, '=') {
+			Ok(Provides::new(name.parse()?, Some(version.parse()?)))
+		} else {
+			Ok(Provides::new(input.parse()?, None))
+		}
+	}
+}
+
+impl std::str::FromStr for Dependency {
+	type Err = DependencyFromStrError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		if let Some(start) = input.find(is_constraint_char) {
+			let name = &input[..start];
+			let (constraint, version) = parse_constraint(&input[start..]).unwrap();
+			let version = VersionConstraint {
+				version: version.parse()?,
+				constraint,
+			};
+			Ok(Dependency::new(name.parse()?, Some(version)))
+		} else {
+			Ok(Dependency::new(input.parse()?, None))
+		}
+	}
+}
+
+impl std::str::FromStr for OptionalDependency {
+	type Err = DependencyFromStrError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let (depends, description) = match input.find(": ") {
+			Some(i) => (&input[..i], &input[i + 2..]),
+			None => (input, ""),
+		};
+
+		let Dependency { name, version, .. } = depends.parse()?;
+
+		Ok(Self {
+			name,
+			version,
+			description: description.into(),
+		})
+	}
+}
+
+/// Check if a character is part of a version constraint operator.
+fn is_constraint_char(c: char) -> bool {
+	c == '>' || c == '<' || c == '='
+}
+
+/// Parse a version constraint.
+fn parse_constraint(input: &str) -> Option<(Constraint, &str)> {
+	if let Some(version) = input.strip_prefix(">=") {
+		Some((Constraint::GreaterEqual, version))
+	} else if let Some(version) = input.strip_prefix("<=") {
+		Some((Constraint::LessEqual, version))
+	} else if let Some(version) = input.strip_prefix(">") {
+		Some((Constraint::Greater, version))
+	} else if let Some(version) = input.strip_prefix("<") {
+		Some((Constraint::Less, version))
+	} else if let Some(version) = input.strip_prefix("==") {
+		// Shame on you, packagers.
+		Some((Constraint::Equal, version))
+	} else if let Some(version) = input.strip_prefix("=") {
+		Some((Constraint::Equal, version))
+	} else {
+		None
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for ExtraData {
+	fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct Visitor;
+		impl<'de> serde::de::Visitor<'de> for Visitor {
+			type Value = ExtraData;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "an extra data entry in the form key=value")
+			}
+
+			fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+				value.parse().map_err(|e| E::custom(format_args!("invalid extra data entry: {}", e)))
+			}
+		}
+
+		deserializer.deserialize_str(Visitor)
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for VersionConstraint {
+	fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct Visitor;
+		impl<'de> serde::de::Visitor<'de> for Visitor {
+			type Value = VersionConstraint;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "a version constraint")
+			}
+
+			fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+				value.parse().map_err(|e| E::custom(format_args!("invalid version constraint: {}", e)))
+			}
+		}
+
+		deserializer.deserialize_str(Visitor)
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for Provides {
+	fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct Visitor;
+		impl<'de> serde::de::Visitor<'de> for Visitor {
+			type Value = Provides;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "a provided package name with optional version")
+			}
+
+			fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+				value.parse().map_err(|e| E::custom(format_args!("invalid provides declaration: {}", e)))
+			}
+		}
+
+		deserializer.deserialize_str(Visitor)
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for Dependency {
+	fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct Visitor;
+		impl<'de> serde::de::Visitor<'de> for Visitor {
+			type Value = Dependency;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "a dependency name with optional version constraint")
+			}
+
+			fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+				value
+					.parse()
+					.map_err(|e| E::custom(format_args!("invalid dependency declaration: {}", e)))
+			}
+		}
+
+		deserializer.deserialize_str(Visitor)
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for OptionalDependency {
+	fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct Visitor;
+		impl<'de> serde::de::Visitor<'de> for Visitor {
+			type Value = OptionalDependency;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "an optional dependency name with optional version constraint and optional description")
+			}
+
+			fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+				value
+					.parse()
+					.map_err(|e| E::custom(format_args!("invalid optional dependency declaration: {}", e)))
+			}
+		}
+
+		deserializer.deserialize_str(Visitor)
+	}
+}
+
+impl From<PackageNameError> for DependencyFromStrError {
+	fn from(other: PackageNameError) -> Self {
+		Self::InvalidName(other)
+	}
+}
+
+impl From<VersionFromStrError> for DependencyFromStrError {
+	fn from(other: VersionFromStrError) -> Self {
+		Self::InvalidVersion(other)
+	}
+}
+
+impl std::error::Error for DependencyFromStrError {}
+
+impl std::fmt::Display for DependencyFromStrError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::InvalidName(e) => e.fmt(f),
+			Self::InvalidVersion(e) => write!(f, "invalid version: {}", e),
+		}
+	}
+}
+
+impl std::error::Error for ExtraDataFromStrError {}
+
+impl std::fmt::Display for ExtraDataFromStrError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::MissingSeparator => write!(f, "missing '=' between key and value"),
+			Self::EmptyKey => write!(f, "empty key"),
+		}
+	}
+}
+
+impl std::error::Error for VersionConstraintFromStrError {}
+
+impl std::fmt::Display for VersionConstraintFromStrError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::MissingOperator => write!(f, "missing comparison operator"),
+			Self::InvalidVersion(e) => e.fmt(f),
+		}
+	}
+}
+
+impl std::fmt::Display for ExtraData {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}={}", self.key, self.value)
+	}
+}
+
+impl std::fmt::Display for Constraint {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Equal => f.write_str("="),
+			Self::Greater => f.write_str(">"),
+			Self::GreaterEqual => f.write_str(">="),
+			Self::Less => f.write_str("<"),
+			Self::LessEqual => f.write_str("<="),
+		}
+	}
+}
+
+impl std::fmt::Display for VersionConstraint {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}{}", self.constraint, self.version)
+	}
+}
+
+impl std::fmt::Display for Provides {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match &self.version {
+			Some(version) => write!(f, "{}={}", self.name, version),
+			None => f.write_str(&self.name),
+		}
+	}
+}
+
+impl std::fmt::Display for Dependency {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match &self.version {
+			Some(version) => write!(f, "{}{}", self.name, version),
+			None => f.write_str(&self.name),
+		}
+	}
+}
+
+impl std::fmt::Display for OptionalDependency {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(&self.name)?;
+		if let Some(version) = &self.version {
+			write!(f, "{}", version)?;
+		}
+		if !self.description.is_empty() {
+			write!(f, ": {}", self.description)?;
+		}
+		Ok(())
+	}
+}
+
+impl serde::Serialize for VersionConstraint {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl serde::Serialize for Provides {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl serde::Serialize for Dependency {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl serde::Serialize for OptionalDependency {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl serde::Serialize for ExtraData {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::db::test_package;
+	use assert2::{assert, let_assert};
+
+	fn dependency(input: &str) -> Dependency {
+		input.parse().unwrap()
+	}
+
+	#[test]
+	fn display_round_trip() {
+		#[track_caller]
+		fn check<T>(input: &str)
+		where
+			T: std::str::FromStr + std::fmt::Display,
+			T::Err: std::fmt::Debug,
+		{
+			let parsed: T = input.parse().unwrap();
+			assert!(parsed.to_string() == input);
+		}
+
+		check::<VersionConstraint>(">=1.0");
+		check::<VersionConstraint>("<2:1.0-3");
+		check::<VersionConstraint>("=1:0.5");
+		check::<VersionConstraint>(">0.7-1.1");
+		check::<VersionConstraint>("<=5");
+		check::<Provides>("linux=5.8.9");
+		check::<Provides>("libalpm.so=13-64");
+		check::<Provides>("java-runtime=1:17");
+		check::<Provides>("WIREGUARD-MODULE");
+		check::<Dependency>("mkinitcpio>=0.7");
+		check::<Dependency>("glibc<2:2.35-1");
+		check::<Dependency>("coreutils");
+		check::<OptionalDependency>("crda: to set the correct wireless channels of your country");
+		check::<OptionalDependency>("python>=3.10: for the python bindings");
+		check::<OptionalDependency>("perl");
+		check::<ExtraData>("pkgtype=pkg");
+
+		// The deprecated `==` operator is normalized.
+		let_assert!(Ok(dependency) = "foo==1.0".parse::<Dependency>());
+		assert!(dependency.to_string() == "foo=1.0");
+
+		assert!("1.0".parse::<VersionConstraint>() == Err(VersionConstraintFromStrError::MissingOperator));
+	}
+
+	#[test]
+	fn invalid_names() {
+		let_assert!(Err(DependencyFromStrError::InvalidName(PackageNameError::LeadingDot)) = ".foo>=1".parse::<Dependency>());
+		let_assert!(Err(DependencyFromStrError::InvalidName(PackageNameError::Empty)) = ">=1".parse::<Dependency>());
+		let_assert!(Err(DependencyFromStrError::InvalidName(PackageNameError::InvalidCharacter('/'))) = "a/b=1".parse::<Provides>());
+		let_assert!(Err(DependencyFromStrError::InvalidName(_)) = "foo bar: description".parse::<OptionalDependency>());
+		let_assert!(Err(DependencyFromStrError::InvalidVersion(_)) = "foo=1-a".parse::<Provides>());
+	}
+
+	#[test]
+	fn version_constraint_is_satisfied_by() {
+		let constraint = |x: &str| dependency(x).version.unwrap();
+		let version = |x: &str| -> Version { x.parse().unwrap() };
+
+		assert!(constraint("foo>=0.7").is_satisfied_by(&version("0.7")));
+		assert!(constraint("foo>=0.7").is_satisfied_by(&version("0.7-1")));
+		assert!(constraint("foo>=0.7").is_satisfied_by(&version("31-1")));
+		assert!(!constraint("foo>=0.7").is_satisfied_by(&version("0.6-3")));
+		assert!(constraint("foo>0.7").is_satisfied_by(&version("0.7.1-1")));
+		assert!(!constraint("foo>0.7").is_satisfied_by(&version("0.7-2")));
+		assert!(constraint("foo=1.0").is_satisfied_by(&version("1.0-5")));
+		assert!(!constraint("foo=1.0-1").is_satisfied_by(&version("1.0-5")));
+		assert!(constraint("foo<1.0-2").is_satisfied_by(&version("1.0-1")));
+		assert!(!constraint("foo<1.0").is_satisfied_by(&version("1.0-1")));
+		assert!(constraint("foo<=1.0").is_satisfied_by(&version("1.0-1")));
+		assert!(!constraint("foo<=1.0").is_satisfied_by(&version("1:0.1-1")));
+	}
+
+	#[test]
+	fn dependency_is_satisfied_by() {
+		let mut mkinitcpio = test_package("mkinitcpio", "31-2");
+		mkinitcpio.provides = vec!["initramfs".parse().unwrap()];
+		assert!(dependency("mkinitcpio").is_satisfied_by(&mkinitcpio));
+		assert!(dependency("mkinitcpio>=0.7").is_satisfied_by(&mkinitcpio));
+		assert!(!dependency("mkinitcpio<30").is_satisfied_by(&mkinitcpio));
+		assert!(dependency("initramfs").is_satisfied_by(&mkinitcpio));
+		assert!(!dependency("initramfs>=1").is_satisfied_by(&mkinitcpio));
+		assert!(!dependency("dracut").is_satisfied_by(&mkinitcpio));
+
+		let mut pacman = test_package("pacman", "6.0.1-5");
+		pacman.provides = vec!["libalpm.so=13-64".parse().unwrap(), "pacman-git=6.0.1".parse().unwrap()];
+		assert!(dependency("libalpm.so").is_satisfied_by(&pacman));
+		assert!(dependency("libalpm.so=13-64").is_satisfied_by(&pacman));
+		assert!(!dependency("libalpm.so=12-64").is_satisfied_by(&pacman));
+		assert!(dependency("pacman-git>=6").is_satisfied_by(&pacman));
+		assert!(dependency("pacman-git=6.0.1-5").is_satisfied_by(&pacman));
+		assert!(!dependency("pacman-git>6.0.1").is_satisfied_by(&pacman));
+	}
+}
@@ -0,0 +1,338 @@
+use std::io::Read;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+
+use super::archive::{read_archive_file, ReadPackageError};
+
+/// The contents of an mtree file, as found in `.MTREE` in package archives.
+///
+/// Pacman also stores the mtree file of each installed package in the local database.
+#[derive(Clone, Debug, Default)]
+pub struct Mtree {
+	pub entries: Vec<MtreeEntry>,
+}
+
+/// A single file, directory or other node in an mtree file.
+///
+/// Keywords that are not given (directly or through `/set`) are `None`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MtreeEntry {
+	/// The path of the entry, relative to the root of the tree and without leading `./`.
+	pub path: PathBuf,
+	pub file_type: Option<MtreeFileType>,
+	pub mode: Option<u32>,
+	pub uid: Option<u32>,
+	pub gid: Option<u32>,
+	pub size: Option<u64>,
+
+	/// The modification time in seconds since the Unix epoch, without the fractional part.
+	pub time: Option<i64>,
+	pub md5digest: Option<String>,
+	pub sha256digest: Option<String>,
+
+	/// The target of a symbolic link.
+	pub link: Option<PathBuf>,
+}
+
+/// The type of an entry in an mtree file.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MtreeFileType {
+	File,
+	Dir,
+	Link,
+	Block,
+	Char,
+	Fifo,
+	Socket,
+}
+
+/// Error when parsing an mtree file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MtreeError {
+	/// The line number of the error, starting at 1.
+	pub line: Option<usize>,
+
+	/// The error message.
+	pub message: String,
+}
+
+impl MtreeError {
+	fn new(line: usize, message: impl std::fmt::Display) -> Self {
+		let line = Some(line);
+		let message = message.to_string();
+		Self { line, message }
+	}
+}
+
+impl Mtree {
+	/// Parse an mtree file that may or may not be gzip compressed.
+	pub fn from_bytes(data: &[u8]) -> Result<Self, MtreeError> {
+		if data.starts_with(&[0x1F, 0x8B]) {
+			let mut decompressed = Vec::new();
+			flate2::read::MultiGzDecoder::new(data)
+				.read_to_end(&mut decompressed)
+				.map_err(|e| MtreeError {
+					line: None,
+					message: format!("failed to decompress mtree: {}", e),
+				})?;
+			Self::parse(&decompressed)
+		} else {
+			Self::parse(data)
+		}
+	}
+
+	/// Read a (possibly gzip compressed) mtree file, such as the `mtree` file in the local database.
+	pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ReadPackageError> {
+		let path = path.as_ref();
+		let data = std::fs::read(path).map_err(|e| ReadPackageError::Open(path.into(), e))?;
+		Self::from_bytes(&data).map_err(|e| ReadPackageError::InvalidMtree(path.into(), e))
+	}
+
+	/// Read the `.MTREE` file from a package archive.
+	///
+	/// Returns `Ok(None)` if the package does not contain a `.MTREE` file.
+	pub fn from_package_file(path: impl AsRef<Path>) -> Result<Option<Self>, ReadPackageError> {
+		let path = path.as_ref();
+		let data = match read_archive_file(path, ".MTREE")? {
+			Some(x) => x,
+			None => return Ok(None),
+		};
+		let mtree = Self::from_bytes(&data).map_err(|e| ReadPackageError::InvalidMtree(path.into(), e))?;
+		Ok(Some(mtree))
+	}
+
+	/// Find the entry for a path.
+	pub fn get(&self, path: impl AsRef<Path>) -> Option<&MtreeEntry> {
+		let path = path.as_ref();
+		self.entries.iter().find(|entry| entry.path == path)
+	}
+
+	/// Parse an uncompressed mtree file.
+	fn parse(data: &[u8]) -> Result<Self, MtreeError> {
+		let mut defaults = MtreeEntry::default();
+		let mut entries = Vec::new();
+
+		let mut lines = data.split(|&c| c == b'\n').enumerate();
+		while let Some((i, line)) = lines.next() {
+			let line_number = i + 1;
+
+			// Join lines ending with a backslash.
+			let mut line = line.to_vec();
+			while line.last() == Some(&b'\\') {
+				line.pop();
+				match lines.next() {
+					Some((_, next)) => line.extend_from_slice(next),
+					None => break,
+				}
+			}
+
+			let line = String::from_utf8(line).map_err(|_| MtreeError::new(line_number, "invalid UTF-8"))?;
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let mut words = line.split_ascii_whitespace();
+			let first = words.next().unwrap();
+			match first {
+				"/set" => {
+					for word in words {
+						set_keyword(&mut defaults, word).map_err(|e| MtreeError::new(line_number, e))?;
+					}
+				},
+				"/unset" => {
+					for word in words {
+						unset_keyword(&mut defaults, word);
+					}
+				},
+				_ if first.starts_with('/') => return Err(MtreeError::new(line_number, format_args!("unknown special command: {}", first))),
+				_ => {
+					let path = unescape(first).map_err(|e| MtreeError::new(line_number, e))?;
+					let path = path.strip_prefix("./").unwrap_or(&path).to_path_buf();
+					if path.as_os_str().is_empty() || path == Path::new(".") {
+						continue;
+					}
+					let mut entry = MtreeEntry { path, ..defaults.clone() };
+					for word in words {
+						set_keyword(&mut entry, word).map_err(|e| MtreeError::new(line_number, e))?;
+					}
+					entries.push(entry);
+				},
+			}
+		}
+
+		Ok(Self { entries })
+	}
+}
+
+/// Set a `keyword=value` pair on an entry.
+///
+/// Unknown keywords are ignored.
+fn set_keyword(entry: &mut MtreeEntry, word: &str) -> Result<(), String> {
+	let (key, value) = match word.split_once('=') {
+		Some(x) => x,
+		None => return Ok(()),
+	};
+	let invalid = |_| format!("invalid value for {}: {}", key, value);
+	match key {
+		"type" => entry.file_type = Some(MtreeFileType::from_keyword(value).ok_or_else(|| format!("unknown file type: {}", value))?),
+		"mode" => entry.mode = Some(u32::from_str_radix(value, 8).map_err(invalid)?),
+		"uid" => entry.uid = Some(value.parse().map_err(invalid)?),
+		"gid" => entry.gid = Some(value.parse().map_err(invalid)?),
+		"size" => entry.size = Some(value.parse().map_err(invalid)?),
+		"time" => {
+			let seconds = value.split('.').next().unwrap_or(value);
+			entry.time = Some(seconds.parse().map_err(invalid)?);
+		},
+		"md5digest" | "md5" => entry.md5digest = Some(value.to_ascii_lowercase()),
+		"sha256digest" | "sha256" => entry.sha256digest = Some(value.to_ascii_lowercase()),
+		"link" => entry.link = Some(unescape(value)?),
+		_ => (),
+	}
+	Ok(())
+}
+
+/// Unset a keyword on an entry.
+fn unset_keyword(entry: &mut MtreeEntry, keyword: &str) {
+	match keyword {
+		"all" => *entry = MtreeEntry::default(),
+		"type" => entry.file_type = None,
+		"mode" => entry.mode = None,
+		"uid" => entry.uid = None,
+		"gid" => entry.gid = None,
+		"size" => entry.size = None,
+		"time" => entry.time = None,
+		"md5digest" | "md5" => entry.md5digest = None,
+		"sha256digest" | "sha256" => entry.sha256digest = None,
+		"link" => entry.link = None,
+		_ => (),
+	}
+}
+
+/// Decode the octal escape sequences (like `\040` for a space) in an mtree path.
+fn unescape(input: &str) -> Result<PathBuf, String> {
+	let input = input.as_bytes();
+	let mut output = Vec::with_capacity(input.len());
+	let mut i = 0;
+	while i < input.len() {
+		if input[i] != b'\\' {
+			output.push(input[i]);
+			i += 1;
+			continue;
+		}
+
+		let escaped = input.get(i + 1..i + 4).and_then(|x| std::str::from_utf8(x).ok());
+		match escaped.and_then(|x| u8::from_str_radix(x, 8).ok()) {
+			Some(byte) => {
+				output.push(byte);
+				i += 4;
+			},
+			None => return Err(format!("invalid escape sequence in path: {}", String::from_utf8_lossy(input))),
+		}
+	}
+	Ok(PathBuf::from(std::ffi::OsString::from_vec(output)))
+}
+
+impl MtreeFileType {
+	/// Parse a file type from the value of a `type` keyword.
+	fn from_keyword(input: &str) -> Option<Self> {
+		match input {
+			"file" => Some(Self::File),
+			"dir" => Some(Self::Dir),
+			"link" => Some(Self::Link),
+			"block" => Some(Self::Block),
+			"char" => Some(Self::Char),
+			"fifo" => Some(Self::Fifo),
+			"socket" => Some(Self::Socket),
+			_ => None,
+		}
+	}
+}
+
+impl std::fmt::Display for MtreeFileType {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::File => f.write_str("file"),
+			Self::Dir => f.write_str("dir"),
+			Self::Link => f.write_str("link"),
+			Self::Block => f.write_str("block"),
+			Self::Char => f.write_str("char"),
+			Self::Fifo => f.write_str("fifo"),
+			Self::Socket => f.write_str("socket"),
+		}
+	}
+}
+
+impl std::error::Error for MtreeError {}
+
+impl std::fmt::Display for MtreeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self.line {
+			Some(line) => write!(f, "line {}: {}", line, self.message),
+			None => f.write_str(&self.message),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::{assert, let_assert};
+	use std::io::Write;
+
+	const MTREE: &str = concat!(
+		"#mtree\n",
+		"/set type=file uid=0 gid=0 mode=644\n",
+		"./.PKGINFO time=1651010052.0 size=11\n",
+		"/set mode=755\n",
+		"./usr time=1651010052.0 type=dir\n",
+		"./usr/bin time=1651010052.0 type=dir\n",
+		"./usr/bin/hello\\040world time=1651010052.0 size=6 \\\n",
+		"    sha256digest=5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03\n",
+		"./usr/bin/hi time=1651010052.0 mode=777 type=link link=hello\\040world\n",
+		"/unset all\n",
+		"./etc type=dir mode=755\n",
+	);
+
+	#[test]
+	fn parse_mtree() {
+		let_assert!(Ok(mtree) = Mtree::from_bytes(MTREE.as_bytes()));
+		assert!(mtree.entries.len() == 6);
+
+		let_assert!(Some(pkginfo) = mtree.get(".PKGINFO"));
+		assert!(pkginfo.file_type == Some(MtreeFileType::File));
+		assert!(pkginfo.mode == Some(0o644));
+		assert!(pkginfo.uid == Some(0));
+		assert!(pkginfo.size == Some(11));
+		assert!(pkginfo.time == Some(1651010052));
+
+		let_assert!(Some(hello) = mtree.get("usr/bin/hello world"));
+		assert!(hello.mode == Some(0o755));
+		assert!(hello.sha256digest.as_deref() == Some("5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"));
+
+		let_assert!(Some(link) = mtree.get("usr/bin/hi"));
+		assert!(link.file_type == Some(MtreeFileType::Link));
+		assert!(link.link.as_deref() == Some(Path::new("hello world")));
+
+		let_assert!(Some(etc) = mtree.get("etc"));
+		assert!(etc.uid == None);
+	}
+
+	#[test]
+	fn parse_gzipped_mtree() {
+		let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+		encoder.write_all(MTREE.as_bytes()).unwrap();
+		let_assert!(Ok(mtree) = Mtree::from_bytes(&encoder.finish().unwrap()));
+		assert!(mtree.entries.len() == 6);
+	}
+
+	#[test]
+	fn parse_errors() {
+		let_assert!(Err(e) = Mtree::from_bytes(b"#mtree\n./foo mode=abc\n"));
+		assert!(e.to_string() == "line 2: invalid value for mode: abc");
+		let_assert!(Err(e) = Mtree::from_bytes(b"./foo type=banana\n"));
+		assert!(e.to_string() == "line 1: unknown file type: banana");
+		let_assert!(Err(e) = Mtree::from_bytes(b"./foo\\04\n"));
+		assert!(e.line == Some(1));
+	}
+}
@@ -0,0 +1,212 @@
+/// The maximum length of a package name in bytes.
+///
+/// Package names end up in file names, so they can not be longer than the file name limit of most file systems.
+pub const MAX_PACKAGE_NAME_LENGTH: usize = 255;
+
+/// A validated package name.
+///
+/// Package names follow the same rules as enforced by `makepkg`:
+/// they may only contain ASCII letters and digits and the characters `@._+-`,
+/// and they may not start with a hyphen or a dot.
+/// Additionally, the name may not be longer than [`MAX_PACKAGE_NAME_LENGTH`] bytes.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct PackageName(String);
+
+/// Error when validating a [`PackageName`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PackageNameError {
+	Empty,
+	TooLong(usize),
+	LeadingHyphen,
+	LeadingDot,
+	InvalidCharacter(char),
+}
+
+impl PackageName {
+	/// Create a package name, checking that it is valid.
+	pub fn new(name: impl Into<String>) -> Result<Self, PackageNameError> {
+		let name = name.into();
+		validate_package_name(&name)?;
+		Ok(Self(name))
+	}
+
+	/// Get the package name as string slice.
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+
+	/// Convert the package name into a string.
+	pub fn into_string(self) -> String {
+		self.0
+	}
+}
+
+/// Check if a string is a valid package name.
+pub fn validate_package_name(name: &str) -> Result<(), PackageNameError> {
+	if name.is_empty() {
+		return Err(PackageNameError::Empty);
+	}
+	if name.len() > MAX_PACKAGE_NAME_LENGTH {
+		return Err(PackageNameError::TooLong(name.len()));
+	}
+	if name.starts_with('-') {
+		return Err(PackageNameError::LeadingHyphen);
+	}
+	if name.starts_with('.') {
+		return Err(PackageNameError::LeadingDot);
+	}
+	if let Some(c) = name.chars().find(|&c| !is_package_name_char(c)) {
+		return Err(PackageNameError::InvalidCharacter(c));
+	}
+	Ok(())
+}
+
+/// Check if a character is allowed in package names.
+fn is_package_name_char(c: char) -> bool {
+	c.is_ascii_alphanumeric() || c == '@' || c == '.' || c == '_' || c == '+' || c == '-'
+}
+
+impl std::str::FromStr for PackageName {
+	type Err = PackageNameError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		Self::new(input)
+	}
+}
+
+impl std::ops::Deref for PackageName {
+	type Target = str;
+
+	fn deref(&self) -> &str {
+		&self.0
+	}
+}
+
+impl AsRef<str> for PackageName {
+	fn as_ref(&self) -> &str {
+		&self.0
+	}
+}
+
+impl std::borrow::Borrow<str> for PackageName {
+	fn borrow(&self) -> &str {
+		&self.0
+	}
+}
+
+impl From<PackageName> for String {
+	fn from(other: PackageName) -> Self {
+		other.0
+	}
+}
+
+impl PartialEq<str> for PackageName {
+	fn eq(&self, other: &str) -> bool {
+		self.0 == other
+	}
+}
+
+impl PartialEq<&str> for PackageName {
+	fn eq(&self, other: &&str) -> bool {
+		self.0 == *other
+	}
+}
+
+impl PartialEq<String> for PackageName {
+	fn eq(&self, other: &String) -> bool {
+		&self.0 == other
+	}
+}
+
+impl PartialEq<PackageName> for String {
+	fn eq(&self, other: &PackageName) -> bool {
+		self == &other.0
+	}
+}
+
+impl PartialEq<PackageName> for str {
+	fn eq(&self, other: &PackageName) -> bool {
+		self == other.0
+	}
+}
+
+impl std::fmt::Display for PackageName {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+impl std::error::Error for PackageNameError {}
+
+impl std::fmt::Display for PackageNameError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Empty => write!(f, "package name is empty"),
+			Self::TooLong(len) => write!(f, "package name is too long ({} bytes, maximum is {})", len, MAX_PACKAGE_NAME_LENGTH),
+			Self::LeadingHyphen => write!(f, "package name starts with a hyphen"),
+			Self::LeadingDot => write!(f, "package name starts with a dot"),
+			Self::InvalidCharacter(c) => write!(f, "package name contains invalid character: {:?}", c),
+		}
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for PackageName {
+	fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct Visitor;
+		impl<'de> serde::de::Visitor<'de> for Visitor {
+			type Value = PackageName;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "a package name")
+			}
+
+			fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+				value.parse().map_err(E::custom)
+			}
+		}
+
+		deserializer.deserialize_str(Visitor)
+	}
+}
+
+impl serde::Serialize for PackageName {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.0)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::assert;
+
+	#[test]
+	fn valid_names() {
+		for name in [
+			"pacman",
+			"lib32-glibc",
+			"gtk2+",
+			"python-zope.interface",
+			"foo@bar",
+			"WIREGUARD-MODULE",
+			"libalpm.so",
+			"7zip",
+			"a_b",
+		] {
+			assert!(PackageName::new(name).map(PackageName::into_string) == Ok(name.to_string()));
+		}
+		assert!(PackageName::new("a".repeat(MAX_PACKAGE_NAME_LENGTH)).is_ok());
+	}
+
+	#[test]
+	fn invalid_names() {
+		assert!(PackageName::new("") == Err(PackageNameError::Empty));
+		assert!(PackageName::new("-foo") == Err(PackageNameError::LeadingHyphen));
+		assert!(PackageName::new(".foo") == Err(PackageNameError::LeadingDot));
+		assert!(PackageName::new("foo bar") == Err(PackageNameError::InvalidCharacter(' ')));
+		assert!(PackageName::new("foo/bar") == Err(PackageNameError::InvalidCharacter('/')));
+		assert!(PackageName::new("foo:bar") == Err(PackageNameError::InvalidCharacter(':')));
+		assert!(PackageName::new("caf\u{e9}") == Err(PackageNameError::InvalidCharacter('\u{e9}')));
+		assert!(PackageName::new("a".repeat(MAX_PACKAGE_NAME_LENGTH + 1)) == Err(PackageNameError::TooLong(256)));
+	}
+}
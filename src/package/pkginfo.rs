@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use super::{Dependency, ExtraData, OptionalDependency, Provides};
+use crate::version::PackageVersion;
+
+/// The contents of a `.PKGINFO` file from a package archive.
+#[derive(Clone, Debug)]
+pub struct PackageInfo {
+	pub name: String,
+	pub base: Option<String>,
+	pub version: PackageVersion,
+	pub description: String,
+	pub url: Option<String>,
+	pub build_date: i64,
+	pub packager: String,
+	pub installed_size: u64,
+	pub arch: String,
+	pub licenses: Vec<String>,
+	pub replaces: Vec<String>,
+	pub groups: Vec<String>,
+	pub conflicts: Vec<Dependency>,
+	pub provides: Vec<Provides>,
+	pub backup: Vec<String>,
+	pub depends: Vec<Dependency>,
+	pub optdepends: Vec<OptionalDependency>,
+	pub makedepends: Vec<Dependency>,
+	pub checkdepends: Vec<Dependency>,
+	pub xdata: Vec<ExtraData>,
+
+	/// Unknown keys with all their values.
+	pub extra: BTreeMap<String, Vec<String>>,
+}
+
+/// Error when parsing a `key = value` file like `.PKGINFO`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct KeyValueError {
+	/// The line number of the error, starting at 1.
+	pub line: Option<usize>,
+
+	/// The error message.
+	pub message: String,
+}
+
+impl KeyValueError {
+	pub(crate) fn new(line: usize, message: impl std::fmt::Display) -> Self {
+		let line = Some(line);
+		let message = message.to_string();
+		Self { line, message }
+	}
+
+	pub(crate) fn missing_key(key: &str) -> Self {
+		let message = format!("missing {}", key);
+		Self { line: None, message }
+	}
+}
+
+/// Iterate over the `key = value` lines of a `.PKGINFO` or `.BUILDINFO` file.
+///
+/// Empty lines and comments are skipped.
+/// Each item holds the line number, the key and the value.
+pub(crate) fn key_value_lines(input: &str) -> impl Iterator<Item = Result<(usize, &str, &str), KeyValueError>> {
+	input.lines().enumerate().filter_map(|(i, line)| {
+		let line_number = i + 1;
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			return None;
+		}
+		match line.split_once(" = ") {
+			Some((key, value)) => Some(Ok((line_number, key.trim(), value.trim()))),
+			None => match line.strip_suffix(" =") {
+				Some(key) => Some(Ok((line_number, key.trim(), ""))),
+				None => Some(Err(KeyValueError::new(line_number, "expected a line in the form `key = value`"))),
+			},
+		}
+	})
+}
+
+/// Parse a value with [`std::str::FromStr`], including the key in the error message.
+pub(crate) fn parse_value<T>(line: usize, key: &str, value: &str) -> Result<T, KeyValueError>
+where
+	T: std::str::FromStr,
+	T::Err: std::fmt::Display,
+{
+	value
+		.parse()
+		.map_err(|e| KeyValueError::new(line, format_args!("invalid value for {}: {}", key, e)))
+}
+
+impl std::str::FromStr for PackageInfo {
+	type Err = KeyValueError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let mut info = Self {
+			name: String::new(),
+			base: None,
+			version: PackageVersion::new(0, "", ""),
+			description: String::new(),
+			url: None,
+			build_date: 0,
+			packager: String::new(),
+			installed_size: 0,
+			arch: String::new(),
+			licenses: Vec::new(),
+			replaces: Vec::new(),
+			groups: Vec::new(),
+			conflicts: Vec::new(),
+			provides: Vec::new(),
+			backup: Vec::new(),
+			depends: Vec::new(),
+			optdepends: Vec::new(),
+			makedepends: Vec::new(),
+			checkdepends: Vec::new(),
+			xdata: Vec::new(),
+			extra: BTreeMap::new(),
+		};
+		let mut have_version = false;
+
+		for entry in key_value_lines(input) {
+			let (line, key, value) = entry?;
+			match key {
+				"pkgname" => info.name = value.into(),
+				"pkgbase" => info.base = Some(value.into()),
+				"pkgver" => {
+					info.version = parse_value(line, key, value)?;
+					have_version = true;
+				},
+				"pkgdesc" => info.description = value.into(),
+				"url" => info.url = Some(value.into()),
+				"builddate" => info.build_date = parse_value(line, key, value)?,
+				"packager" => info.packager = value.into(),
+				"size" => info.installed_size = parse_value(line, key, value)?,
+				"arch" => info.arch = value.into(),
+				"license" => info.licenses.push(value.into()),
+				"replaces" => info.replaces.push(value.into()),
+				"group" => info.groups.push(value.into()),
+				"conflict" => info.conflicts.push(parse_value(line, key, value)?),
+				"provides" => info.provides.push(parse_value(line, key, value)?),
+				"backup" => info.backup.push(value.into()),
+				"depend" => info.depends.push(parse_value(line, key, value)?),
+				"optdepend" => info.optdepends.push(parse_value(line, key, value)?),
+				"makedepend" => info.makedepends.push(parse_value(line, key, value)?),
+				"checkdepend" => info.checkdepends.push(parse_value(line, key, value)?),
+				"xdata" => info.xdata.push(parse_value(line, key, value)?),
+				_ => info.extra.entry(key.into()).or_default().push(value.into()),
+			}
+		}
+
+		if info.name.is_empty() {
+			return Err(KeyValueError::missing_key("pkgname"));
+		}
+		if !have_version {
+			return Err(KeyValueError::missing_key("pkgver"));
+		}
+		Ok(info)
+	}
+}
+
+impl std::error::Error for KeyValueError {}
+
+impl std::fmt::Display for KeyValueError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self.line {
+			Some(line) => write!(f, "line {}: {}", line, self.message),
+			None => f.write_str(&self.message),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::{assert, let_assert};
+
+	#[test]
+	fn parse_pkginfo() {
+		let_assert!(Ok(info) = include_str!("../../tests/package/.PKGINFO").parse::<PackageInfo>());
+		assert!(info.name == "pacman");
+		assert!(info.base.as_deref() == Some("pacman"));
+		assert!(info.version == PackageVersion::new(0, "6.0.1", "5"));
+		assert!(info.build_date == 1651010052);
+		assert!(info.installed_size == 4753874);
+		assert!(info.backup == ["etc/pacman.conf", "etc/makepkg.conf"]);
+		assert!(info.depends.len() == 7);
+		assert!(info.optdepends[0].description == "translation support in makepkg-template");
+		assert!(info.provides[0].to_string() == "libalpm.so=13-64");
+		assert!(info.makedepends.len() == 3);
+		assert!(info.checkdepends.len() == 2);
+		assert!(info.xdata == [ExtraData::new("pkgtype", "pkg")]);
+		assert!(info.extra.is_empty());
+	}
+
+	#[test]
+	fn parse_errors() {
+		let_assert!(Err(e) = "pkgname = foo\npkgver\n".parse::<PackageInfo>());
+		assert!(e.line == Some(2));
+		let_assert!(Err(e) = "pkgname = foo\npkgver = 1.0-1\nsize = big\n".parse::<PackageInfo>());
+		assert!(e.to_string() == "line 3: invalid value for size: invalid digit found in string");
+		let_assert!(Err(e) = "pkgname = foo\n".parse::<PackageInfo>());
+		assert!(e.to_string() == "missing pkgver");
+	}
+}
@@ -0,0 +1,377 @@
+use std::cmp::Ordering;
+
+use super::{Constraint, VersionConstraint};
+use crate::version::{compare_version_string, vercmp, Version};
+
+/// A set of versions, built from one or more [`VersionConstraint`]s.
+///
+/// Ranges can be combined with [`VersionRange::intersection`] and [`VersionRange::union`],
+/// which makes it possible to check if a set of constraints can be satisfied at all.
+///
+/// Versions are compared with [`vercmp`], just like [`VersionConstraint::is_satisfied_by`] does.
+/// So a bound without pkgrel matches any pkgrel of the same epoch and pkgver.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VersionRange {
+	/// The non-empty intervals that make up the range, sorted and without overlap.
+	intervals: Vec<Interval>,
+}
+
+/// A contiguous interval of versions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Interval {
+	lower: Bound,
+	upper: Bound,
+}
+
+/// One end of an interval.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Bound {
+	Unbounded,
+	Included(Version),
+	Excluded(Version),
+}
+
+/// The position of a bound between versions, used to order bounds.
+///
+/// A version without pkgrel matches every pkgrel of the same epoch and pkgver,
+/// so a cut before such a version lies before all of its pkgrels, and a cut after it lies after all of them.
+#[derive(Copy, Clone, Debug)]
+enum Cut<'a> {
+	Start,
+	Before(&'a Version),
+	After(&'a Version),
+	End,
+}
+
+impl VersionRange {
+	/// Create a range that contains all versions.
+	pub fn any() -> Self {
+		Self {
+			intervals: vec![Interval {
+				lower: Bound::Unbounded,
+				upper: Bound::Unbounded,
+			}],
+		}
+	}
+
+	/// Create a range that contains no versions at all.
+	pub fn none() -> Self {
+		Self { intervals: Vec::new() }
+	}
+
+	/// Create a range with all versions that satisfy a constraint.
+	pub fn from_constraint(constraint: &VersionConstraint) -> Self {
+		let version = || constraint.version.clone();
+		let (lower, upper) = match constraint.constraint {
+			Constraint::Equal => (Bound::Included(version()), Bound::Included(version())),
+			Constraint::Greater => (Bound::Excluded(version()), Bound::Unbounded),
+			Constraint::GreaterEqual => (Bound::Included(version()), Bound::Unbounded),
+			Constraint::Less => (Bound::Unbounded, Bound::Excluded(version())),
+			Constraint::LessEqual => (Bound::Unbounded, Bound::Included(version())),
+		};
+		Self::from_interval(Interval { lower, upper })
+	}
+
+	/// Create a range with all versions that satisfy all of the constraints.
+	///
+	/// If there are no constraints, the range contains all versions.
+	pub fn from_constraints<'a>(constraints: impl IntoIterator<Item = &'a VersionConstraint>) -> Self {
+		constraints
+			.into_iter()
+			.fold(Self::any(), |range, constraint| range.intersection(&Self::from_constraint(constraint)))
+	}
+
+	/// Get the range of versions that are in both ranges.
+	pub fn intersection(&self, other: &Self) -> Self {
+		let mut intervals = Vec::new();
+		for a in &self.intervals {
+			for b in &other.intervals {
+				let interval = Interval {
+					lower: std::cmp::max_by_key(&a.lower, &b.lower, |x| x.lower_cut()).clone(),
+					upper: std::cmp::min_by_key(&a.upper, &b.upper, |x| x.upper_cut()).clone(),
+				};
+				if !interval.is_empty() {
+					intervals.push(interval);
+				}
+			}
+		}
+		Self::from_intervals(intervals)
+	}
+
+	/// Get the range of versions that are in either range.
+	///
+	/// Overlapping and adjacent intervals are merged.
+	pub fn union(&self, other: &Self) -> Self {
+		Self::from_intervals(self.intervals.iter().chain(&other.intervals).cloned().collect())
+	}
+
+	/// Check if the range contains no versions.
+	pub fn is_empty(&self) -> bool {
+		self.intervals.is_empty()
+	}
+
+	/// Check if the range contains a version.
+	pub fn contains(&self, version: &Version) -> bool {
+		self.intervals.iter().any(|interval| interval.contains(version))
+	}
+
+	/// Create a range from a single interval, dropping it if it is empty.
+	fn from_interval(interval: Interval) -> Self {
+		if interval.is_empty() {
+			Self::none()
+		} else {
+			Self { intervals: vec![interval] }
+		}
+	}
+
+	/// Create a range from non-empty intervals, sorting them and merging overlapping and adjacent intervals.
+	fn from_intervals(mut input: Vec<Interval>) -> Self {
+		input.sort_by(|a, b| a.lower.lower_cut().cmp(&b.lower.lower_cut()));
+		let mut intervals: Vec<Interval> = Vec::with_capacity(input.len());
+		for interval in input {
+			match intervals.last_mut() {
+				Some(last) if interval.lower.lower_cut() <= last.upper.upper_cut() => {
+					if interval.upper.upper_cut() > last.upper.upper_cut() {
+						last.upper = interval.upper;
+					}
+				},
+				_ => intervals.push(interval),
+			}
+		}
+		Self { intervals }
+	}
+}
+
+impl Interval {
+	/// Check if there is no version that lies within the bounds.
+	fn is_empty(&self) -> bool {
+		self.lower.lower_cut() >= self.upper.upper_cut()
+	}
+
+	/// Check if a version lies within the bounds.
+	fn contains(&self, version: &Version) -> bool {
+		let above_lower = match &self.lower {
+			Bound::Unbounded => true,
+			Bound::Included(lower) => vercmp(version, lower) != Ordering::Less,
+			Bound::Excluded(lower) => vercmp(version, lower) == Ordering::Greater,
+		};
+		let below_upper = match &self.upper {
+			Bound::Unbounded => true,
+			Bound::Included(upper) => vercmp(version, upper) != Ordering::Greater,
+			Bound::Excluded(upper) => vercmp(version, upper) == Ordering::Less,
+		};
+		above_lower && below_upper
+	}
+}
+
+impl Bound {
+	/// Get the position of the bound when it is used as lower bound.
+	fn lower_cut(&self) -> Cut<'_> {
+		match self {
+			Bound::Unbounded => Cut::Start,
+			Bound::Included(version) => Cut::Before(version),
+			Bound::Excluded(version) => Cut::After(version),
+		}
+	}
+
+	/// Get the position of the bound when it is used as upper bound.
+	fn upper_cut(&self) -> Cut<'_> {
+		match self {
+			Bound::Unbounded => Cut::End,
+			Bound::Included(version) => Cut::After(version),
+			Bound::Excluded(version) => Cut::Before(version),
+		}
+	}
+}
+
+impl Ord for Cut<'_> {
+	fn cmp(&self, other: &Self) -> Ordering {
+		let (a, b) = match (self, other) {
+			(Cut::Start, Cut::Start) | (Cut::End, Cut::End) => return Ordering::Equal,
+			(Cut::Start, _) | (_, Cut::End) => return Ordering::Less,
+			(_, Cut::Start) | (Cut::End, _) => return Ordering::Greater,
+			(Cut::Before(a) | Cut::After(a), Cut::Before(b) | Cut::After(b)) => (a, b),
+		};
+
+		let ordering = a.epoch.cmp(&b.epoch).then_with(|| compare_version_string(&a.pkgver, &b.pkgver));
+		if ordering != Ordering::Equal {
+			return ordering;
+		}
+
+		let after_a = matches!(self, Cut::After(_));
+		let after_b = matches!(other, Cut::After(_));
+		match (&a.pkgrel, &b.pkgrel) {
+			(Some(pkgrel_a), Some(pkgrel_b)) => compare_version_string(pkgrel_a, pkgrel_b).then(after_a.cmp(&after_b)),
+			(None, None) => after_a.cmp(&after_b),
+			(None, Some(_)) if after_a => Ordering::Greater,
+			(None, Some(_)) => Ordering::Less,
+			(Some(_), None) if after_b => Ordering::Less,
+			(Some(_), None) => Ordering::Greater,
+		}
+	}
+}
+
+impl PartialOrd for Cut<'_> {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for Cut<'_> {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for Cut<'_> {}
+
+impl From<&VersionConstraint> for VersionRange {
+	fn from(other: &VersionConstraint) -> Self {
+		Self::from_constraint(other)
+	}
+}
+
+impl From<VersionConstraint> for VersionRange {
+	fn from(other: VersionConstraint) -> Self {
+		Self::from_constraint(&other)
+	}
+}
+
+impl std::fmt::Display for Interval {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match (&self.lower, &self.upper) {
+			(Bound::Unbounded, Bound::Unbounded) => write!(f, "*"),
+			(Bound::Included(lower), Bound::Included(upper)) if lower == upper => write!(f, "={}", lower),
+			(lower, upper) => {
+				match lower {
+					Bound::Unbounded => (),
+					Bound::Included(x) => write!(f, ">={}", x)?,
+					Bound::Excluded(x) => write!(f, ">{}", x)?,
+				}
+				if lower != &Bound::Unbounded && upper != &Bound::Unbounded {
+					write!(f, " ")?;
+				}
+				match upper {
+					Bound::Unbounded => Ok(()),
+					Bound::Included(x) => write!(f, "<={}", x),
+					Bound::Excluded(x) => write!(f, "<{}", x),
+				}
+			},
+		}
+	}
+}
+
+impl std::fmt::Display for VersionRange {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		if self.intervals.is_empty() {
+			return write!(f, "(none)");
+		}
+		for (i, interval) in self.intervals.iter().enumerate() {
+			if i > 0 {
+				write!(f, " || ")?;
+			}
+			write!(f, "{}", interval)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::assert;
+
+	fn range(constraints: &[&str]) -> VersionRange {
+		let constraints: Vec<VersionConstraint> = constraints.iter().map(|x| x.parse().unwrap()).collect();
+		VersionRange::from_constraints(&constraints)
+	}
+
+	fn version(input: &str) -> Version {
+		input.parse().unwrap()
+	}
+
+	#[test]
+	fn intersection() {
+		let both = range(&[">=1.2", "<2"]);
+		assert!(!both.is_empty());
+		assert!(both.to_string() == ">=1.2 <2");
+		assert!(both.contains(&version("1.2")));
+		assert!(both.contains(&version("1.9.9-3")));
+		assert!(!both.contains(&version("1.1")));
+		assert!(!both.contains(&version("2")));
+		assert!(!both.contains(&version("1:1.5")));
+
+		assert!(range(&[">=2", "<1.2"]).is_empty());
+		assert!(range(&[">1.0", "<1.0"]).is_empty());
+		assert!(range(&[">=1.0", "<=1.0"]).to_string() == "=1.0");
+		assert!(range(&["=1.0", "=1.1"]).is_empty());
+		assert!(range(&[">=1.0", ">1.1", "<=3", "<2"]).to_string() == ">1.1 <2");
+		assert!(range(&[]) == VersionRange::any());
+		assert!(range(&[]).to_string() == "*");
+	}
+
+	#[test]
+	fn intersection_with_pkgrel() {
+		// Bounds without pkgrel match all pkgrels.
+		assert!(!range(&[">=1.0-2", "<=1.0"]).is_empty());
+		assert!(range(&[">1.0", "<1.0-5"]).is_empty());
+		assert!(range(&[">=1.0", ">=1.0-2"]).to_string() == ">=1.0-2");
+		assert!(range(&[">1.0", ">1.0-2"]).to_string() == ">1.0");
+		assert!(range(&[">=1.0-2", ">1.0"]).to_string() == ">1.0");
+
+		let exact = range(&["=1.0"]);
+		assert!(exact.contains(&version("1.0-1")));
+		assert!(exact.contains(&version("1.0-7")));
+		assert!(!range(&["=1.0-1"]).contains(&version("1.0-7")));
+	}
+
+	#[test]
+	fn bound_without_pkgrel_covers_all_pkgrels() {
+		let range_a = range(&[">=1.0", "<1.0-5"]);
+		assert!(range_a.to_string() == ">=1.0 <1.0-5");
+		assert!(range_a.contains(&version("1.0-3")));
+
+		let range_b = range(&["=1.0", "<1.0-5"]);
+		assert!(range_b.to_string() == ">=1.0 <1.0-5");
+		assert!(range_b.contains(&version("1.0-3")));
+
+		let range_c = range(&[">1.0-2", "<=1.0"]);
+		assert!(range_c.to_string() == ">1.0-2 <=1.0");
+		assert!(range_c.contains(&version("1.0-3")));
+		assert!(!range_c.contains(&version("1.0-2")));
+
+		assert!(range(&[">1.0-2", "<1.0"]).is_empty());
+		assert!(range(&[">=1.0-2", "<1.0-2"]).is_empty());
+		assert!(range(&["=1.0-2", "<=1.0"]).to_string() == "=1.0-2");
+	}
+
+	#[test]
+	fn union() {
+		let old = range(&["<1"]);
+		let new = range(&[">=2"]);
+		let either = old.union(&new);
+		assert!(either.to_string() == "<1 || >=2");
+		assert!(either.contains(&version("0.5")));
+		assert!(either.contains(&version("3")));
+		assert!(!either.contains(&version("1.5")));
+
+		assert!(either.intersection(&range(&[">=0.8", "<=2.5"])).to_string() == ">=0.8 <1 || >=2 <=2.5");
+		assert!(either.intersection(&range(&[">=1", "<2"])).is_empty());
+		assert!(VersionRange::none().union(&old) == old);
+		assert!(VersionRange::none().to_string() == "(none)");
+	}
+
+	#[test]
+	fn union_merges_intervals() {
+		assert!(range(&[">=2"]).union(&range(&["<1"])).to_string() == "<1 || >=2");
+		assert!(range(&["<2"]).union(&range(&[">1"])) == VersionRange::any());
+		assert!(range(&[">=1", "<2"]).union(&range(&[">=2", "<3"])).to_string() == ">=1 <3");
+		assert!(range(&[">=1", "<=2"]).union(&range(&[">2", "<3"])).to_string() == ">=1 <3");
+		assert!(range(&[">=1", "<2"]).union(&range(&[">2", "<3"])).to_string() == ">=1 <2 || >2 <3");
+		assert!(range(&[">=1", "<3"]).union(&range(&[">=1.5", "<2"])).to_string() == ">=1 <3");
+		assert!(range(&["=1.0"]).union(&range(&["=1.0-2"])).to_string() == "=1.0");
+
+		let merged = range(&["<1"]).union(&range(&[">=3"])).union(&range(&[">=1", "<3"]));
+		assert!(merged == VersionRange::any());
+	}
+}
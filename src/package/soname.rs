@@ -0,0 +1,277 @@
+use super::{Constraint, Dependency, PackageName, PackageNameError, Provides, VersionConstraint};
+use crate::db::DatabasePackage;
+use crate::parse::{partition, rpartition};
+use crate::version::Version;
+
+/// A shared library dependency or provision, like `libfoo.so=1-64`.
+///
+/// makepkg adds these to packages that provide or link against a shared library
+/// that is listed by name (without version) in the `provides` or `depends` array of the `PKGBUILD`.
+/// The version is the soname version of the library,
+/// and the bits are the word size of the architecture the library was built for (usually 32 or 64).
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Soname {
+	/// The name of the library, including the `.so` suffix.
+	pub name: PackageName,
+
+	/// The soname version of the library.
+	pub version: String,
+
+	/// The word size of the architecture of the library.
+	pub bits: u8,
+}
+
+/// Error when parsing a [`Soname`] from a string.
+#[derive(Debug, Eq, PartialEq)]
+pub enum SonameFromStrError {
+	InvalidName(PackageNameError),
+	MissingSoSuffix,
+	MissingVersion,
+	MissingBits,
+	InvalidBits,
+}
+
+impl Soname {
+	pub fn new(name: PackageName, version: impl Into<String>, bits: u8) -> Self {
+		Self {
+			name,
+			version: version.into(),
+			bits,
+		}
+	}
+
+	/// Interpret a parsed version as soname version and word size.
+	pub(super) fn from_name_version(name: &PackageName, version: &Version) -> Option<Self> {
+		if !is_soname(name) || version.epoch != 0 || version.pkgver.is_empty() {
+			return None;
+		}
+		let bits = version.pkgrel.as_deref()?.parse().ok()?;
+		Some(Self {
+			name: name.clone(),
+			version: version.pkgver.clone(),
+			bits,
+		})
+	}
+
+	/// Get the version as it appears in `provides` and `depends` entries.
+	pub fn to_version(&self) -> Version {
+		Version::new(0, self.version.as_str(), Some(self.bits.to_string()))
+	}
+
+	/// Create a provision for the shared library.
+	pub fn to_provides(&self) -> Provides {
+		Provides::versioned(self.name.clone(), self.to_version())
+	}
+
+	/// Create a dependency on the shared library.
+	pub fn to_dependency(&self) -> Dependency {
+		Dependency::constrained(self.name.clone(), VersionConstraint {
+			version: self.to_version(),
+			constraint: Constraint::Equal,
+		})
+	}
+}
+
+impl Provides {
+	/// Get the provided shared library, if the name and version form a soname like `libfoo.so=1-64`.
+	pub fn soname(&self) -> Option<Soname> {
+		Soname::from_name_version(&self.name, self.version.as_ref()?)
+	}
+}
+
+impl Dependency {
+	/// Get the required shared library, if the name and version constraint form a soname like `libfoo.so=1-64`.
+	///
+	/// Only dependencies with an `=` constraint are recognized as sonames, since that is what makepkg generates.
+	pub fn soname(&self) -> Option<Soname> {
+		match &self.version {
+			Some(constraint) if constraint.constraint == Constraint::Equal => Soname::from_name_version(&self.name, &constraint.version),
+			_ => None,
+		}
+	}
+}
+
+impl DatabasePackage {
+	/// Get the shared libraries the package provides.
+	pub fn soname_provides(&self) -> impl Iterator<Item = Soname> + '_ {
+		self.provides.iter().filter_map(Provides::soname)
+	}
+
+	/// Get the shared libraries the package depends on.
+	pub fn soname_depends(&self) -> impl Iterator<Item = Soname> + '_ {
+		self.depends.iter().filter_map(Dependency::soname)
+	}
+
+	/// Check if the package provides a shared library.
+	pub fn provides_soname(&self, soname: &Soname) -> bool {
+		self.soname_provides().any(|x| x == *soname)
+	}
+
+	/// Check if the package depends on a shared library.
+	pub fn depends_on_soname(&self, soname: &Soname) -> bool {
+		self.soname_depends().any(|x| x == *soname)
+	}
+}
+
+/// Find all packages that provide a shared library.
+pub fn find_soname_providers<'a>(packages: impl IntoIterator<Item = &'a DatabasePackage>, soname: &Soname) -> Vec<&'a DatabasePackage> {
+	packages.into_iter().filter(|package| package.provides_soname(soname)).collect()
+}
+
+/// Find all packages that depend on a shared library.
+pub fn find_soname_dependents<'a>(packages: impl IntoIterator<Item = &'a DatabasePackage>, soname: &Soname) -> Vec<&'a DatabasePackage> {
+	packages.into_iter().filter(|package| package.depends_on_soname(soname)).collect()
+}
+
+/// Check if a name looks like the name of a shared library.
+fn is_soname(name: &str) -> bool {
+	name.len() > 3 && name.ends_with(".so")
+}
+
+impl std::str::FromStr for Soname {
+	type Err = SonameFromStrError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		let (name, version) = partition(input, '=').ok_or(SonameFromStrError::MissingVersion)?;
+		let name: PackageName = name.parse().map_err(SonameFromStrError::InvalidName)?;
+		if !is_soname(&name) {
+			return Err(SonameFromStrError::MissingSoSuffix);
+		}
+		let (version, bits) = rpartition(version, '-').ok_or(SonameFromStrError::MissingBits)?;
+		if version.is_empty() {
+			return Err(SonameFromStrError::MissingVersion);
+		}
+		let bits = bits.parse().map_err(|_| SonameFromStrError::InvalidBits)?;
+		Ok(Self {
+			name,
+			version: version.into(),
+			bits,
+		})
+	}
+}
+
+impl std::fmt::Display for Soname {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}={}-{}", self.name, self.version, self.bits)
+	}
+}
+
+impl std::error::Error for SonameFromStrError {}
+
+impl std::fmt::Display for SonameFromStrError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::InvalidName(e) => e.fmt(f),
+			Self::MissingSoSuffix => write!(f, "library name does not end in .so"),
+			Self::MissingVersion => write!(f, "missing soname version"),
+			Self::MissingBits => write!(f, "missing architecture word size"),
+			Self::InvalidBits => write!(f, "invalid architecture word size"),
+		}
+	}
+}
+
+impl<'de> serde::Deserialize<'de> for Soname {
+	fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		struct Visitor;
+		impl<'de> serde::de::Visitor<'de> for Visitor {
+			type Value = Soname;
+
+			fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+				write!(f, "a soname like libfoo.so=1-64")
+			}
+
+			fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+				value.parse().map_err(|e| E::custom(format_args!("invalid soname: {}", e)))
+			}
+		}
+
+		deserializer.deserialize_str(Visitor)
+	}
+}
+
+impl serde::Serialize for Soname {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::db::test_package;
+	use assert2::{assert, let_assert};
+
+	fn name(input: &str) -> PackageName {
+		input.parse().unwrap()
+	}
+
+	#[test]
+	fn parse_soname() {
+		let_assert!(Ok(soname) = "libssl.so=3-64".parse::<Soname>());
+		assert!(soname == Soname::new(name("libssl.so"), "3", 64));
+		assert!(soname.to_string() == "libssl.so=3-64");
+		assert!("libalpm.so=13.0.1-32".parse::<Soname>() == Ok(Soname::new(name("libalpm.so"), "13.0.1", 32)));
+
+		assert!("libssl.so".parse::<Soname>() == Err(SonameFromStrError::MissingVersion));
+		assert!("libssl.so=3".parse::<Soname>() == Err(SonameFromStrError::MissingBits));
+		assert!("libssl.so=-64".parse::<Soname>() == Err(SonameFromStrError::MissingVersion));
+		assert!("libssl.so=3-x86_64".parse::<Soname>() == Err(SonameFromStrError::InvalidBits));
+		assert!("openssl=3-64".parse::<Soname>() == Err(SonameFromStrError::MissingSoSuffix));
+		assert!(".so=3-64".parse::<Soname>() == Err(SonameFromStrError::InvalidName(PackageNameError::LeadingDot)));
+	}
+
+	#[test]
+	fn soname_from_provides_and_depends() {
+		let soname = Soname::new(name("libssl.so"), "3", 64);
+		assert!("libssl.so=3-64".parse::<Provides>().unwrap().soname() == Some(soname.clone()));
+		assert!("libssl.so=3-64".parse::<Dependency>().unwrap().soname() == Some(soname.clone()));
+		assert!(Provides::versioned(name("libssl.so"), soname.to_version()).soname() == Some(soname.clone()));
+		assert!(Dependency::constrained_equal(name("libssl.so"), soname.to_version()).soname() == Some(soname.clone()));
+		assert!(soname.to_provides() == "libssl.so=3-64".parse::<Provides>().unwrap());
+		assert!(soname.to_dependency() == "libssl.so=3-64".parse::<Dependency>().unwrap());
+		assert!(soname.to_provides().to_string() == "libssl.so=3-64");
+		assert!(soname.to_dependency().to_string() == "libssl.so=3-64");
+
+		// The soname follows changes to the version.
+		let mut provides = soname.to_provides();
+		provides.version = Some("1.1-64".parse().unwrap());
+		assert!(provides.soname() == Some(Soname::new(name("libssl.so"), "1.1", 64)));
+		provides.version = None;
+		assert!(provides.soname() == None);
+
+		// Plain dependencies and unversioned sonames are not recognized.
+		assert!("openssl=3.0.7-4".parse::<Provides>().unwrap().soname() == None);
+		assert!("libssl.so".parse::<Provides>().unwrap().soname() == None);
+		assert!("libssl.so".parse::<Dependency>().unwrap().soname() == None);
+		assert!("libssl.so>=3-64".parse::<Dependency>().unwrap().soname() == None);
+		assert!("libssl.so=1:3-64".parse::<Dependency>().unwrap().soname() == None);
+	}
+
+	#[test]
+	fn find_by_soname() {
+		let package = |name, depends: &[&str], provides: &[&str]| {
+			let mut package = test_package(name, "1-1");
+			package.depends = depends.iter().map(|x| x.parse().unwrap()).collect();
+			package.provides = provides.iter().map(|x| x.parse().unwrap()).collect();
+			package
+		};
+		let packages = [
+			package("openssl", &["glibc"], &["libcrypto.so=3-64", "libssl.so=3-64"]),
+			package("openssl-1.1", &["glibc"], &["libcrypto.so=1.1-64", "libssl.so=1.1-64"]),
+			package("curl", &["libssl.so=3-64", "zlib"], &["libcurl.so=4-64"]),
+			package("python", &["libssl.so=1.1-64"], &[]),
+		];
+
+		let libssl = Soname::new(name("libssl.so"), "3", 64);
+		fn names(packages: Vec<&DatabasePackage>) -> Vec<&str> {
+			packages.into_iter().map(|x| x.name.as_str()).collect()
+		}
+		assert!(names(find_soname_providers(&packages, &libssl)) == ["openssl"]);
+		assert!(names(find_soname_dependents(&packages, &libssl)) == ["curl"]);
+		assert!(names(find_soname_dependents(&packages, &Soname::new(name("libssl.so"), "1.1", 64))) == ["python"]);
+		assert!(find_soname_dependents(&packages, &Soname::new(name("libssl.so"), "3", 32)).is_empty());
+
+		assert!(packages[2].soname_depends().collect::<Vec<_>>() == [libssl]);
+		assert!(packages[2].soname_provides().collect::<Vec<_>>() == [Soname::new(name("libcurl.so"), "4", 64)]);
+	}
+}
@@ -0,0 +1,410 @@
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use super::mtree::{Mtree, MtreeEntry, MtreeFileType};
+
+/// Metadata files in the root of package archives that are not installed by pacman.
+const PACKAGE_METADATA_FILES: &[&str] = &[".PKGINFO", ".BUILDINFO", ".MTREE", ".INSTALL", ".CHANGELOG"];
+
+/// Options for verifying a directory tree against mtree files.
+#[derive(Clone, Debug)]
+pub struct VerifyOptions {
+	/// Compare the owner and group of files with the mtree entries.
+	pub check_ownership: bool,
+
+	/// Report files that are not listed in any of the mtree files.
+	pub report_extra: bool,
+}
+
+/// The result of verifying a directory tree.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VerifyReport {
+	/// Entries from the mtree files that do not exist on disk.
+	pub missing: Vec<PathBuf>,
+
+	/// Entries that exist on disk, but differ from the mtree files.
+	pub modified: Vec<ModifiedFile>,
+
+	/// Files on disk that are not in any of the mtree files.
+	///
+	/// For unknown directories, only the directory itself is reported and not its contents.
+	pub extra: Vec<PathBuf>,
+}
+
+/// A file that differs from its mtree entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ModifiedFile {
+	pub path: PathBuf,
+	pub mismatches: Vec<Mismatch>,
+}
+
+/// A single difference between a file and its mtree entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Mismatch {
+	FileType {
+		expected: MtreeFileType,
+		actual: Option<MtreeFileType>,
+	},
+	Mode {
+		expected: u32,
+		actual: u32,
+	},
+	Uid {
+		expected: u32,
+		actual: u32,
+	},
+	Gid {
+		expected: u32,
+		actual: u32,
+	},
+	Size {
+		expected: u64,
+		actual: u64,
+	},
+	Sha256Digest {
+		expected: String,
+		actual: String,
+	},
+	LinkTarget {
+		expected: PathBuf,
+		actual: PathBuf,
+	},
+}
+
+/// Error when verifying a directory tree.
+#[derive(Debug)]
+pub enum VerifyError {
+	Io(PathBuf, std::io::Error),
+}
+
+impl Default for VerifyOptions {
+	fn default() -> Self {
+		Self {
+			check_ownership: true,
+			report_extra: true,
+		}
+	}
+}
+
+impl VerifyOptions {
+	/// Create the default options: check everything.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Enable or disable checking the owner and group of files.
+	///
+	/// This is useful when verifying a tree that was extracted without root privileges.
+	pub fn check_ownership(mut self, check_ownership: bool) -> Self {
+		self.check_ownership = check_ownership;
+		self
+	}
+
+	/// Enable or disable reporting of files that are not in any mtree file.
+	pub fn report_extra(mut self, report_extra: bool) -> Self {
+		self.report_extra = report_extra;
+		self
+	}
+}
+
+impl VerifyReport {
+	/// Check if the tree matches the mtree files exactly.
+	pub fn is_clean(&self) -> bool {
+		self.missing.is_empty() && self.modified.is_empty() && self.extra.is_empty()
+	}
+}
+
+/// Verify a directory tree against the mtree files of the packages that should be in it.
+///
+/// The `root` can be an extracted package or an installed root directory.
+/// For an installed root, pass the mtree files of all installed packages,
+/// or disable [`VerifyOptions::report_extra`].
+///
+/// The package metadata files in the root of the tree (`.PKGINFO`, `.MTREE`, etc.) are ignored,
+/// both in the mtree files and on disk.
+pub fn verify_tree<'a>(
+	root: impl AsRef<Path>,
+	mtrees: impl IntoIterator<Item = &'a Mtree>,
+	options: &VerifyOptions,
+) -> Result<VerifyReport, VerifyError> {
+	let root = root.as_ref();
+	let mut report = VerifyReport::default();
+	let mut checked = BTreeSet::new();
+	let mut known = BTreeSet::new();
+
+	for mtree in mtrees {
+		for entry in &mtree.entries {
+			if is_metadata_file(&entry.path) || !checked.insert(entry.path.as_path()) {
+				continue;
+			}
+			// Also consider parent directories as known, even if the mtree file does not list them.
+			known.extend(entry.path.ancestors().filter(|x| !x.as_os_str().is_empty()));
+
+			let path = root.join(&entry.path);
+			let metadata = match std::fs::symlink_metadata(&path) {
+				Ok(x) => x,
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+					report.missing.push(entry.path.clone());
+					continue;
+				},
+				Err(e) => return Err(VerifyError::Io(path, e)),
+			};
+			let mismatches = verify_entry(&path, &metadata, entry, options)?;
+			if !mismatches.is_empty() {
+				report.modified.push(ModifiedFile {
+					path: entry.path.clone(),
+					mismatches,
+				});
+			}
+		}
+	}
+
+	if options.report_extra {
+		find_extra(root, Path::new(""), &known, &mut report.extra)?;
+		report.extra.sort();
+	}
+
+	Ok(report)
+}
+
+/// Compare a file on disk with an mtree entry.
+fn verify_entry(path: &Path, metadata: &std::fs::Metadata, entry: &MtreeEntry, options: &VerifyOptions) -> Result<Vec<Mismatch>, VerifyError> {
+	let mut mismatches = Vec::new();
+
+	let file_type = file_type(metadata);
+	if let Some(expected) = entry.file_type {
+		if file_type != Some(expected) {
+			// Further comparisons make no sense if the file type is wrong.
+			mismatches.push(Mismatch::FileType { expected, actual: file_type });
+			return Ok(mismatches);
+		}
+	}
+
+	// The permissions of symlinks are meaningless on Linux.
+	if let (Some(expected), false) = (entry.mode, file_type == Some(MtreeFileType::Link)) {
+		let actual = metadata.mode() & 0o7777;
+		if actual != expected {
+			mismatches.push(Mismatch::Mode { expected, actual });
+		}
+	}
+
+	if options.check_ownership {
+		if let Some(expected) = entry.uid {
+			if metadata.uid() != expected {
+				mismatches.push(Mismatch::Uid {
+					expected,
+					actual: metadata.uid(),
+				});
+			}
+		}
+		if let Some(expected) = entry.gid {
+			if metadata.gid() != expected {
+				mismatches.push(Mismatch::Gid {
+					expected,
+					actual: metadata.gid(),
+				});
+			}
+		}
+	}
+
+	match file_type {
+		Some(MtreeFileType::File) => {
+			let size_matches = match entry.size {
+				Some(expected) if metadata.len() != expected => {
+					mismatches.push(Mismatch::Size {
+						expected,
+						actual: metadata.len(),
+					});
+					false
+				},
+				_ => true,
+			};
+			// Don't bother hashing the file if the size is already wrong.
+			if let (Some(expected), true) = (&entry.sha256digest, size_matches) {
+				let actual = file_sha256(path)?;
+				if &actual != expected {
+					mismatches.push(Mismatch::Sha256Digest {
+						expected: expected.clone(),
+						actual,
+					});
+				}
+			}
+		},
+		Some(MtreeFileType::Link) => {
+			if let Some(expected) = &entry.link {
+				let actual = std::fs::read_link(path).map_err(|e| VerifyError::Io(path.into(), e))?;
+				if &actual != expected {
+					mismatches.push(Mismatch::LinkTarget {
+						expected: expected.clone(),
+						actual,
+					});
+				}
+			}
+		},
+		_ => (),
+	}
+
+	Ok(mismatches)
+}
+
+/// Recursively find files in a directory that are not in the set of known paths.
+fn find_extra(root: &Path, dir: &Path, known: &BTreeSet<&Path>, extra: &mut Vec<PathBuf>) -> Result<(), VerifyError> {
+	let full_dir = root.join(dir);
+	let read_error = |e| VerifyError::Io(full_dir.clone(), e);
+	for entry in std::fs::read_dir(&full_dir).map_err(read_error)? {
+		let entry = entry.map_err(read_error)?;
+		let path = dir.join(entry.file_name());
+		if is_metadata_file(&path) {
+			continue;
+		}
+		if !known.contains(path.as_path()) {
+			extra.push(path);
+			continue;
+		}
+		if entry.file_type().map_err(read_error)?.is_dir() {
+			find_extra(root, &path, known, extra)?;
+		}
+	}
+	Ok(())
+}
+
+/// Check if a path refers to one of the package metadata files in the root of a package.
+fn is_metadata_file(path: &Path) -> bool {
+	path.to_str().map(|x| PACKAGE_METADATA_FILES.contains(&x)).unwrap_or(false)
+}
+
+/// Get the mtree file type of a file.
+fn file_type(metadata: &std::fs::Metadata) -> Option<MtreeFileType> {
+	let file_type = metadata.file_type();
+	if file_type.is_file() {
+		Some(MtreeFileType::File)
+	} else if file_type.is_dir() {
+		Some(MtreeFileType::Dir)
+	} else if file_type.is_symlink() {
+		Some(MtreeFileType::Link)
+	} else if file_type.is_block_device() {
+		Some(MtreeFileType::Block)
+	} else if file_type.is_char_device() {
+		Some(MtreeFileType::Char)
+	} else if file_type.is_fifo() {
+		Some(MtreeFileType::Fifo)
+	} else if file_type.is_socket() {
+		Some(MtreeFileType::Socket)
+	} else {
+		None
+	}
+}
+
+/// Compute the sha256 digest of a file as lowercase hex string.
+fn file_sha256(path: &Path) -> Result<String, VerifyError> {
+	use sha2::Digest;
+
+	let read_error = |e| VerifyError::Io(path.into(), e);
+	let mut file = std::fs::File::open(path).map_err(read_error)?;
+	let mut hasher = sha2::Sha256::new();
+	let mut buffer = vec![0; 64 * 1024];
+	loop {
+		let read = file.read(&mut buffer).map_err(read_error)?;
+		if read == 0 {
+			break;
+		}
+		hasher.update(&buffer[..read]);
+	}
+	Ok(format!("{:x}", hasher.finalize()))
+}
+
+impl std::error::Error for VerifyError {}
+
+impl std::fmt::Display for VerifyError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+		}
+	}
+}
+
+impl std::fmt::Display for Mismatch {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::FileType {
+				expected,
+				actual: Some(actual),
+			} => write!(f, "file type: expected {}, found {}", expected, actual),
+			Self::FileType { expected, actual: None } => write!(f, "file type: expected {}, found unknown file type", expected),
+			Self::Mode { expected, actual } => write!(f, "mode: expected {:o}, found {:o}", expected, actual),
+			Self::Uid { expected, actual } => write!(f, "uid: expected {}, found {}", expected, actual),
+			Self::Gid { expected, actual } => write!(f, "gid: expected {}, found {}", expected, actual),
+			Self::Size { expected, actual } => write!(f, "size: expected {}, found {}", expected, actual),
+			Self::Sha256Digest { expected, actual } => write!(f, "sha256 digest: expected {}, found {}", expected, actual),
+			Self::LinkTarget { expected, actual } => write!(f, "link target: expected {}, found {}", expected.display(), actual.display()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::{assert, let_assert};
+	use std::os::unix::fs::PermissionsExt;
+
+	const MTREE: &str = concat!(
+		"#mtree\n",
+		"/set type=file uid=0 gid=0 mode=644\n",
+		"./.PKGINFO time=1651010052.0 size=11\n",
+		"./etc time=1651010052.0 mode=755 type=dir\n",
+		"./etc/hello time=1651010052.0 size=6 sha256digest=5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03\n",
+		"./etc/modified time=1651010052.0 size=6 sha256digest=5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03\n",
+		"./etc/missing time=1651010052.0 size=6\n",
+		"./etc/link time=1651010052.0 type=link link=hello\n",
+		"./etc/mode time=1651010052.0 size=0\n",
+	);
+
+	fn write_file(path: &Path, data: &[u8], mode: u32) {
+		std::fs::write(path, data).unwrap();
+		std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+	}
+
+	#[test]
+	fn verify() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let root = tempdir.path();
+		std::fs::create_dir_all(root.join("etc/extra-dir")).unwrap();
+		std::fs::set_permissions(root.join("etc"), std::fs::Permissions::from_mode(0o755)).unwrap();
+		write_file(&root.join(".PKGINFO"), b"not checked", 0o600);
+		write_file(&root.join("etc/hello"), b"hello\n", 0o644);
+		write_file(&root.join("etc/modified"), b"hallo\n", 0o644);
+		write_file(&root.join("etc/mode"), b"", 0o600);
+		write_file(&root.join("etc/extra"), b"", 0o644);
+		write_file(&root.join("etc/extra-dir/not-reported"), b"", 0o644);
+		std::os::unix::fs::symlink("modified", root.join("etc/link")).unwrap();
+
+		let_assert!(Ok(mtree) = Mtree::from_bytes(MTREE.as_bytes()));
+		let options = VerifyOptions::new().check_ownership(false);
+		let_assert!(Ok(report) = verify_tree(root, &[mtree], &options));
+
+		assert!(report.missing == [PathBuf::from("etc/missing")]);
+		assert!(report.extra == [PathBuf::from("etc/extra"), PathBuf::from("etc/extra-dir")]);
+		assert!(report.modified.len() == 3);
+		assert!(report.modified[0].path == Path::new("etc/modified"));
+		let_assert!([Mismatch::Sha256Digest { .. }] = report.modified[0].mismatches.as_slice());
+		assert!(report.modified[1].path == Path::new("etc/link"));
+		assert!(
+			report.modified[1].mismatches
+				== [Mismatch::LinkTarget {
+					expected: "hello".into(),
+					actual: "modified".into(),
+				}]
+		);
+		assert!(report.modified[2].path == Path::new("etc/mode"));
+		assert!(
+			report.modified[2].mismatches
+				== [Mismatch::Mode {
+					expected: 0o644,
+					actual: 0o600,
+				}]
+		);
+		assert!(!report.is_clean());
+	}
+}
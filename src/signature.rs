@@ -0,0 +1,547 @@
+//! OpenPGP signature verification of packages.
+//!
+//! Signatures are verified by running `gpg` with a keyring in a GnuPG home directory,
+//! like the pacman keyring in `/etc/pacman.d/gnupg`.
+//! The results are checked against the package part of a [`SigLevel`], the same way pacman does.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::config::{SigLevel, SignatureRequirement, SignatureTrust};
+use crate::db::DatabasePackage;
+
+/// A keyring in a GnuPG home directory, used to verify signatures.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Keyring {
+	/// The GnuPG home directory.
+	homedir: PathBuf,
+
+	/// The `gpg` program to run.
+	program: PathBuf,
+}
+
+/// The result of checking a single signature.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Signature {
+	/// The long key ID of the key that made the signature.
+	pub key_id: String,
+
+	/// The fingerprint of the key, if the key is in the keyring.
+	pub fingerprint: Option<String>,
+
+	/// The status of the signature.
+	pub status: SignatureStatus,
+
+	/// How much the key that made the signature is trusted.
+	pub validity: KeyValidity,
+}
+
+/// The status of a signature.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SignatureStatus {
+	/// The signature is valid.
+	Good,
+
+	/// The signature does not match the data.
+	Bad,
+
+	/// The signature is valid, but it has expired.
+	SignatureExpired,
+
+	/// The signature is valid, but the key has expired.
+	KeyExpired,
+
+	/// The signature is valid, but the key has been revoked.
+	KeyRevoked,
+
+	/// The key is not in the keyring.
+	UnknownKey,
+
+	/// The signature could not be checked for another reason.
+	Error,
+}
+
+/// How much a key is trusted.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KeyValidity {
+	/// Nothing is known about the trust of the key.
+	Unknown,
+
+	/// The key is explicitly not trusted.
+	Never,
+
+	/// The key is marginally trusted.
+	Marginal,
+
+	/// The key is fully or ultimately trusted.
+	Full,
+}
+
+/// Error when verifying the signature of a package.
+#[derive(Debug)]
+pub enum SignatureError {
+	/// The package has no signature, but the signature level requires one.
+	Missing,
+
+	/// The `%PGPSIG%` of the package is not valid base64.
+	InvalidEncoding(base64::DecodeError),
+
+	/// Failed to run `gpg`.
+	RunGpg(PathBuf, std::io::Error),
+
+	/// `gpg` did not report any signature, or failed without reporting a bad signature, with the error output of `gpg`.
+	Gpg(String),
+
+	/// The signature data contains signed data, instead of only a detached signature.
+	Plaintext,
+
+	/// A signature is not valid.
+	Invalid(Signature),
+
+	/// A signature is valid, but the key is not trusted enough.
+	Untrusted(Signature),
+}
+
+impl Keyring {
+	/// Use the keyring in a GnuPG home directory.
+	pub fn new(homedir: impl Into<PathBuf>) -> Self {
+		Self {
+			homedir: homedir.into(),
+			program: PathBuf::from("gpg"),
+		}
+	}
+
+	/// Set the `gpg` program to run, instead of looking for `gpg` in `PATH`.
+	pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+		self.program = program.into();
+		self
+	}
+
+	/// Get the GnuPG home directory of the keyring.
+	pub fn homedir(&self) -> &Path {
+		&self.homedir
+	}
+
+	/// Check a detached signature of a file.
+	///
+	/// Returns the result for each signature in the signature data.
+	/// If `gpg` fails, the first signature that is not good is returned as error.
+	/// If all signatures are good or there are none, the error output of `gpg` is returned instead.
+	pub fn verify_detached(&self, data: &Path, signature: &[u8]) -> Result<Vec<Signature>, SignatureError> {
+		let mut child = Command::new(&self.program)
+			.arg("--homedir")
+			.arg(&self.homedir)
+			.args(["--batch", "--no-tty", "--status-fd", "1", "--verify", "-"])
+			.arg(data)
+			.stdin(Stdio::piped())
+			.stdout(Stdio::piped())
+			.stderr(Stdio::piped())
+			.spawn()
+			.map_err(|e| SignatureError::RunGpg(self.program.clone(), e))?;
+
+		// gpg may exit before reading all of the input if it is not a signature, so ignore write errors.
+		let mut stdin = child.stdin.take().unwrap();
+		let _: Result<_, _> = stdin.write_all(signature);
+		drop(stdin);
+
+		let output = child.wait_with_output().map_err(|e| SignatureError::RunGpg(self.program.clone(), e))?;
+		let signatures = parse_status(&String::from_utf8_lossy(&output.stdout))?;
+		if !output.status.success() {
+			if let Some(signature) = signatures.into_iter().find(|x| x.status != SignatureStatus::Good) {
+				return Err(SignatureError::Invalid(signature));
+			}
+		} else if !signatures.is_empty() {
+			return Ok(signatures);
+		}
+		let message = String::from_utf8_lossy(&output.stderr);
+		Err(SignatureError::Gpg(message.trim().into()))
+	}
+
+	/// Verify a package file according to the package settings of a signature level.
+	///
+	/// With [`SignatureRequirement::Optional`], packages without signature are accepted.
+	/// When a signature is present, all signatures must be valid and made by keys that are trusted enough.
+	pub fn verify_package(&self, path: &Path, signature: Option<&[u8]>, sig_level: &SigLevel) -> Result<(), SignatureError> {
+		let signature = match (sig_level.package, signature) {
+			(SignatureRequirement::Never, _) => return Ok(()),
+			(SignatureRequirement::Optional, None) => return Ok(()),
+			(SignatureRequirement::Required, None) => return Err(SignatureError::Missing),
+			(_, Some(signature)) => signature,
+		};
+		for signature in self.verify_detached(path, signature)? {
+			check_signature(signature, sig_level.package_trust)?;
+		}
+		Ok(())
+	}
+}
+
+impl DatabasePackage {
+	/// Decode the signature embedded in the database as `%PGPSIG%`.
+	pub fn pgp_signature(&self) -> Result<Option<Vec<u8>>, SignatureError> {
+		match &self.pgpsig {
+			None => Ok(None),
+			Some(pgpsig) => base64::Engine::decode(&base64::engine::general_purpose::STANDARD, pgpsig)
+				.map(Some)
+				.map_err(SignatureError::InvalidEncoding),
+		}
+	}
+}
+
+/// Check that a signature is good and that the key is trusted enough.
+fn check_signature(signature: Signature, trust: SignatureTrust) -> Result<(), SignatureError> {
+	if signature.status != SignatureStatus::Good {
+		return Err(SignatureError::Invalid(signature));
+	}
+	let trusted = match signature.validity {
+		KeyValidity::Full => true,
+		KeyValidity::Marginal | KeyValidity::Unknown => trust == SignatureTrust::TrustAll,
+		KeyValidity::Never => false,
+	};
+	if !trusted {
+		return Err(SignatureError::Untrusted(signature));
+	}
+	Ok(())
+}
+
+/// Parse the status output of `gpg --status-fd` into signature results.
+///
+/// Signed data in the status output means that `gpg` did not check the data file, so it is an error.
+fn parse_status(output: &str) -> Result<Vec<Signature>, SignatureError> {
+	let mut signatures: Vec<Signature> = Vec::new();
+	for line in output.lines() {
+		let fields: Vec<&str> = match line.strip_prefix("[GNUPG:] ") {
+			Some(line) => line.split(' ').collect(),
+			None => continue,
+		};
+		let field = |i: usize| fields.get(i).copied().unwrap_or("").to_owned();
+
+		if fields[0] == "PLAINTEXT" {
+			return Err(SignatureError::Plaintext);
+		}
+		if fields[0] == "NEWSIG" {
+			signatures.push(Signature {
+				key_id: String::new(),
+				fingerprint: None,
+				status: SignatureStatus::Error,
+				validity: KeyValidity::Unknown,
+			});
+			continue;
+		}
+		let signature = match signatures.last_mut() {
+			Some(x) => x,
+			None => continue,
+		};
+
+		match fields[0] {
+			"GOODSIG" | "BADSIG" | "EXPSIG" | "EXPKEYSIG" | "REVKEYSIG" => {
+				signature.key_id = field(1);
+				signature.status = match fields[0] {
+					"GOODSIG" => SignatureStatus::Good,
+					"BADSIG" => SignatureStatus::Bad,
+					"EXPSIG" => SignatureStatus::SignatureExpired,
+					"EXPKEYSIG" => SignatureStatus::KeyExpired,
+					_ => SignatureStatus::KeyRevoked,
+				};
+			},
+			"ERRSIG" => {
+				signature.key_id = field(1);
+				// The seventh field is the reason, where 9 means that the key is missing.
+				signature.status = match field(6).as_str() {
+					"9" => SignatureStatus::UnknownKey,
+					_ => SignatureStatus::Error,
+				};
+			},
+			"VALIDSIG" => signature.fingerprint = Some(field(1)),
+			"TRUST_UNDEFINED" => signature.validity = KeyValidity::Unknown,
+			"TRUST_NEVER" => signature.validity = KeyValidity::Never,
+			"TRUST_MARGINAL" => signature.validity = KeyValidity::Marginal,
+			"TRUST_FULLY" | "TRUST_ULTIMATE" => signature.validity = KeyValidity::Full,
+			_ => (),
+		}
+	}
+	Ok(signatures)
+}
+
+impl std::fmt::Display for SignatureStatus {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Good => write!(f, "good signature"),
+			Self::Bad => write!(f, "bad signature"),
+			Self::SignatureExpired => write!(f, "expired signature"),
+			Self::KeyExpired => write!(f, "signature by expired key"),
+			Self::KeyRevoked => write!(f, "signature by revoked key"),
+			Self::UnknownKey => write!(f, "signature by unknown key"),
+			Self::Error => write!(f, "unverifiable signature"),
+		}
+	}
+}
+
+impl std::fmt::Display for KeyValidity {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Unknown => write!(f, "unknown"),
+			Self::Never => write!(f, "never"),
+			Self::Marginal => write!(f, "marginal"),
+			Self::Full => write!(f, "full"),
+		}
+	}
+}
+
+impl std::error::Error for SignatureError {}
+
+impl std::fmt::Display for SignatureError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Missing => write!(f, "missing signature"),
+			Self::InvalidEncoding(e) => write!(f, "invalid base64 in %PGPSIG%: {}", e),
+			Self::RunGpg(program, e) => write!(f, "failed to run {}: {}", program.display(), e),
+			Self::Gpg(message) => write!(f, "gpg failed to check the signature: {}", message),
+			Self::Plaintext => write!(f, "signature contains signed data instead of a detached signature"),
+			Self::Invalid(signature) => write!(f, "{} (key {})", signature.status, signature.key_id),
+			Self::Untrusted(signature) => write!(f, "key {} is not trusted (validity: {})", signature.key_id, signature.validity),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use assert2::{assert, let_assert};
+	use std::os::unix::fs::PermissionsExt;
+
+	/// A GnuPG home directory in a temporary directory.
+	struct TestGnupg {
+		homedir: PathBuf,
+	}
+
+	impl TestGnupg {
+		fn new(dir: &Path, name: &str) -> Self {
+			let homedir = dir.join(name);
+			std::fs::create_dir_all(&homedir).unwrap();
+			std::fs::set_permissions(&homedir, std::fs::Permissions::from_mode(0o700)).unwrap();
+			Self { homedir }
+		}
+
+		fn gpg(&self, args: &[&str], input: &[u8]) -> Vec<u8> {
+			let mut child = Command::new("gpg")
+				.arg("--homedir")
+				.arg(&self.homedir)
+				.args(["--batch", "--no-tty", "--passphrase", ""])
+				.args(args)
+				.stdin(Stdio::piped())
+				.stdout(Stdio::piped())
+				.stderr(Stdio::null())
+				.spawn()
+				.unwrap();
+			child.stdin.take().unwrap().write_all(input).unwrap();
+			let output = child.wait_with_output().unwrap();
+			assert!(output.status.success(), "gpg {:?} failed", args);
+			output.stdout
+		}
+
+		/// Generate a signing key and return the fingerprint.
+		fn generate_key(&self, user_id: &str) -> String {
+			self.gpg(&["--quick-gen-key", user_id, "ed25519", "sign", "never"], b"");
+			let output = String::from_utf8(self.gpg(&["--with-colons", "--list-keys", user_id], b"")).unwrap();
+			let fpr = output.lines().find_map(|line| line.strip_prefix("fpr:")).unwrap();
+			fpr.trim_matches(':').to_owned()
+		}
+
+		fn sign(&self, path: &Path) -> Vec<u8> {
+			self.gpg(&["--detach-sign", "--output", "-", path.to_str().unwrap()], b"")
+		}
+
+		fn sign_inline(&self, data: &[u8]) -> Vec<u8> {
+			self.gpg(&["--sign", "--output", "-"], data)
+		}
+
+		fn export(&self, fingerprint: &str) -> Vec<u8> {
+			self.gpg(&["--export", fingerprint], b"")
+		}
+
+		fn import(&self, key: &[u8]) {
+			self.gpg(&["--import"], key);
+		}
+
+		fn set_ultimate_trust(&self, fingerprint: &str) {
+			self.gpg(&["--import-ownertrust"], format!("{}:6:\n", fingerprint).as_bytes());
+		}
+
+		fn keyring(&self) -> Keyring {
+			Keyring::new(&self.homedir)
+		}
+	}
+
+	impl Drop for TestGnupg {
+		fn drop(&mut self) {
+			let _ = Command::new("gpgconf")
+				.arg("--homedir")
+				.arg(&self.homedir)
+				.args(["--kill", "gpg-agent"])
+				.status();
+		}
+	}
+
+	fn sig_level(input: &str) -> SigLevel {
+		input.parse().unwrap()
+	}
+
+	#[test]
+	fn parse_gpg_status() {
+		let output = "\
+			[GNUPG:] NEWSIG\n\
+			[GNUPG:] KEY_CONSIDERED 9FA78469BA573D4627AA18B6DE53539DDD9D0FC1 0\n\
+			[GNUPG:] GOODSIG DE53539DDD9D0FC1 Test <test@example.com>\n\
+			[GNUPG:] VALIDSIG 9FA78469BA573D4627AA18B6DE53539DDD9D0FC1 2026-10-16 1792192739 0 4 0 22 8 00 9FA78469BA573D4627AA18B6DE53539DDD9D0FC1\n\
+			[GNUPG:] TRUST_ULTIMATE 0 pgp\n\
+			[GNUPG:] NEWSIG\n\
+			[GNUPG:] ERRSIG 0123456789ABCDEF 22 8 00 1792192739 9 -\n\
+			[GNUPG:] NO_PUBKEY 0123456789ABCDEF\n\
+			[GNUPG:] NEWSIG\n\
+			[GNUPG:] BADSIG DE53539DDD9D0FC1 Test <test@example.com>\n";
+		let_assert!(Ok(signatures) = parse_status(output));
+		assert!(signatures.len() == 3);
+		assert!(signatures[0].key_id == "DE53539DDD9D0FC1");
+		assert!(signatures[0].fingerprint.as_deref() == Some("9FA78469BA573D4627AA18B6DE53539DDD9D0FC1"));
+		assert!(signatures[0].status == SignatureStatus::Good);
+		assert!(signatures[0].validity == KeyValidity::Full);
+		assert!(signatures[1].key_id == "0123456789ABCDEF");
+		assert!(signatures[1].status == SignatureStatus::UnknownKey);
+		assert!(signatures[2].status == SignatureStatus::Bad);
+		assert!(signatures[2].validity == KeyValidity::Unknown);
+	}
+
+	#[test]
+	fn parse_gpg_status_with_plaintext() {
+		let output = "\
+			[GNUPG:] NEWSIG\n\
+			[GNUPG:] PLAINTEXT 62 1792192739 \n\
+			[GNUPG:] GOODSIG DE53539DDD9D0FC1 Test <test@example.com>\n";
+		assert!(let Err(SignatureError::Plaintext) = parse_status(output));
+	}
+
+	/// Make a keyring that runs a fake `gpg`, which prints the status lines and exits with the exit code.
+	fn fake_gpg(dir: &Path, name: &str, status: &[&str], exit_code: i32) -> Keyring {
+		let mut script = String::from("#!/bin/sh\ncat > /dev/null\n");
+		for line in status {
+			script += &format!("echo '[GNUPG:] {}'\n", line);
+		}
+		script += &format!("echo 'gpg: exit {}' >&2\nexit {}\n", exit_code, exit_code);
+		let program = dir.join(name);
+		std::fs::write(&program, script).unwrap();
+		std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+		Keyring::new(dir).program(program)
+	}
+
+	#[test]
+	fn reject_failed_gpg() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let package = dir.join("package.pkg.tar.zst");
+		std::fs::write(&package, "package data").unwrap();
+		let good = ["NEWSIG", "GOODSIG DE53539DDD9D0FC1 Test <test@example.com>", "TRUST_ULTIMATE 0 pgp"];
+
+		let keyring = fake_gpg(dir, "gpg-ok", &good, 0);
+		let_assert!(Ok(signatures) = keyring.verify_detached(&package, b"signature"));
+		assert!(signatures.len() == 1);
+		assert!(let Ok(()) = keyring.verify_package(&package, Some(b"signature"), &sig_level("Required")));
+
+		// Good signatures don't count when gpg itself fails.
+		let keyring = fake_gpg(dir, "gpg-fail", &good, 2);
+		let_assert!(Err(SignatureError::Gpg(message)) = keyring.verify_detached(&package, b"signature"));
+		assert!(message == "gpg: exit 2");
+		assert!(let Err(SignatureError::Gpg(_)) = keyring.verify_package(&package, Some(b"signature"), &sig_level("Required TrustAll")));
+
+		// When gpg fails because of a bad signature, that signature is the error.
+		let keyring = fake_gpg(dir, "gpg-bad", &["NEWSIG", good[1], "NEWSIG", "BADSIG 0123456789ABCDEF Other"], 1);
+		let_assert!(Err(SignatureError::Invalid(bad)) = keyring.verify_detached(&package, b"signature"));
+		assert!(bad.key_id == "0123456789ABCDEF");
+		assert!(bad.status == SignatureStatus::Bad);
+
+		// A signature over other data is rejected, even if gpg succeeds.
+		let keyring = fake_gpg(dir, "gpg-plaintext", &["NEWSIG", "PLAINTEXT 62 1792192739 ", good[1], good[2]], 0);
+		assert!(let Err(SignatureError::Plaintext) = keyring.verify_detached(&package, b"signature"));
+	}
+
+	#[test]
+	fn signature_policy() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let package = dir.join("package.pkg.tar.zst");
+		std::fs::write(&package, "package data").unwrap();
+		let keyring = Keyring::new(dir.join("no-such-keyring"));
+
+		assert!(let Ok(()) = keyring.verify_package(&package, Some(b"garbage"), &sig_level("Never")));
+		assert!(let Ok(()) = keyring.verify_package(&package, None, &sig_level("Optional")));
+		assert!(let Err(SignatureError::Missing) = keyring.verify_package(&package, None, &sig_level("Required")));
+		assert!(let Ok(()) = keyring.verify_package(&package, None, &sig_level("PackageOptional DatabaseRequired")));
+	}
+
+	#[test]
+	fn verify_with_keyring() {
+		let tempdir = tempfile::tempdir().unwrap();
+		let dir = tempdir.path();
+		let package = dir.join("package.pkg.tar.zst");
+		std::fs::write(&package, "package data").unwrap();
+
+		let signer = TestGnupg::new(dir, "signer");
+		let fingerprint = signer.generate_key("Packager <packager@example.com>");
+		let signature = signer.sign(&package);
+
+		// The key of the signer is ultimately trusted in its own keyring.
+		let_assert!(Ok(signatures) = signer.keyring().verify_detached(&package, &signature));
+		assert!(signatures.len() == 1);
+		assert!(signatures[0].fingerprint.as_deref() == Some(fingerprint.as_str()));
+		assert!(let Ok(()) = signer.keyring().verify_package(&package, Some(&signature), &sig_level("Required TrustedOnly")));
+
+		// An imported key without trust is only accepted with TrustAll.
+		let verifier = TestGnupg::new(dir, "verifier");
+		verifier.import(&signer.export(&fingerprint));
+		let keyring = verifier.keyring();
+		assert!(let Ok(()) = keyring.verify_package(&package, Some(&signature), &sig_level("Required TrustAll")));
+		let_assert!(Err(SignatureError::Untrusted(untrusted)) = keyring.verify_package(&package, Some(&signature), &sig_level("Required")));
+		assert!(untrusted.validity == KeyValidity::Unknown);
+		verifier.set_ultimate_trust(&fingerprint);
+		assert!(let Ok(()) = keyring.verify_package(&package, Some(&signature), &sig_level("Optional TrustedOnly")));
+
+		// Modified data and unknown keys are always rejected.
+		let tampered = dir.join("tampered.pkg.tar.zst");
+		std::fs::write(&tampered, "package data, but different").unwrap();
+		let_assert!(Err(SignatureError::Invalid(bad)) = keyring.verify_package(&tampered, Some(&signature), &sig_level("Optional TrustAll")));
+		assert!(bad.status == SignatureStatus::Bad);
+
+		let empty = TestGnupg::new(dir, "empty");
+		let_assert!(
+			Err(e) = empty
+				.keyring()
+				.verify_package(&package, Some(&signature), &sig_level("Optional TrustAll"))
+		);
+		let_assert!(SignatureError::Invalid(unknown) = &e);
+		assert!(unknown.status == SignatureStatus::UnknownKey);
+		assert!(e.to_string() == format!("signature by unknown key (key {})", &fingerprint[24..]));
+
+		let_assert!(Err(SignatureError::Gpg(_)) = keyring.verify_package(&package, Some(b"not a signature"), &sig_level("Required")));
+
+		// A signed message carries its own data, so it must not be accepted as signature for the package.
+		let signed = signer.sign_inline(b"other data");
+		let_assert!(
+			Err(SignatureError::Gpg(_) | SignatureError::Plaintext) =
+				keyring.verify_package(&package, Some(&signed), &sig_level("Required TrustAll"))
+		);
+
+		drop((signer, verifier, empty));
+	}
+
+	#[test]
+	fn decode_pgpsig() {
+		let mut package: DatabasePackage = crate::db::from_bytes(include_bytes!("../tests/database-package/desc")).unwrap();
+		let_assert!(Ok(Some(signature)) = package.pgp_signature());
+		assert!(signature[0] == 0x89);
+
+		package.pgpsig = Some("not base64!".into());
+		assert!(let Err(SignatureError::InvalidEncoding(_)) = package.pgp_signature());
+		package.pgpsig = None;
+		assert!(let Ok(None) = package.pgp_signature());
+	}
+}
@@ -1,62 +1,139 @@
 use std::cmp::Ordering;
 
-fn consume_while<'a, F>(input: &mut &'a str, mut condition: F) -> &'a str
+/// Split off the leading bytes of `input` that match a condition.
+fn consume_while<'a, F>(input: &mut &'a [u8], condition: F) -> &'a [u8]
 where
-	F: FnMut(char) -> bool,
+	F: Fn(&u8) -> bool,
 {
-	let i = input.find(|c| !condition(c)).unwrap_or_else(|| input.len());
+	let i = input.iter().position(|c| !condition(c)).unwrap_or(input.len());
 	let (result, remainder) = input.split_at(i);
 	*input = remainder;
 	result
 }
 
+/// Compare two version strings with the same algorithm as `rpmvercmp` from libalpm.
+///
+/// Only ASCII letters and digits are considered part of a version segment.
+/// All other characters (including `~` and non-ASCII characters) are separators.
+///
+/// Numeric segments are compared by value and may be arbitrarily long.
+/// Numeric segments are always newer than alphabetic segments.
+/// If two versions only differ in the number of separators between two segments,
+/// the version with the longer separator is newer.
 pub fn compare_version_string(a: &str, b: &str) -> Ordering {
-	let mut a = a;
-	let mut b = b;
-
-	// Loop over the alphanumeric parts.
-	while !a.is_empty() || !b.is_empty() {
-		// Get the first alphanumeric component.
-		let mut a_alnum = consume_while(&mut a, |x| x.is_alphanumeric());
-		let mut b_alnum = consume_while(&mut b, |x| x.is_alphanumeric());
-
-		// Loop over the numeric and alphabetical parts.
-		while !a_alnum.is_empty() || !b_alnum.is_empty() {
-			let a_num = consume_while(&mut a_alnum, |x| x.is_digit(10));
-			let b_num = consume_while(&mut b_alnum, |x| x.is_digit(10));
-			let a_alpha = consume_while(&mut a_alnum, |x| x.is_alphabetic());
-			let b_alpha = consume_while(&mut b_alnum, |x| x.is_alphabetic());
-
-			// Parse the numeric part.
-			let a_num = if a_num.is_empty() { -1 } else { a_num.parse::<i32>().unwrap() };
-			let b_num = if b_num.is_empty() { -1 } else { b_num.parse::<i32>().unwrap() };
-
-			// If the numeric part is different, we're done.
-			let num_ordering = a_num.cmp(&b_num);
-			if num_ordering != Ordering::Equal {
-				return num_ordering;
-			};
-
-			// If the alphabetical part is different, we're done.
-			// Note that an empty alphabetical part is "newer" than a non-empty alphabetical part.
-			let alpha_ordering = (a_alpha.is_empty(), a_alpha).cmp(&(b_alpha.is_empty(), b_alpha));
-			if alpha_ordering != Ordering::Equal {
-				return alpha_ordering;
-			};
+	if a == b {
+		return Ordering::Equal;
+	}
+
+	let mut a = a.as_bytes();
+	let mut b = b.as_bytes();
+
+	// Loop over the segments.
+	while !a.is_empty() && !b.is_empty() {
+		let a_sep = consume_while(&mut a, |c| !c.is_ascii_alphanumeric());
+		let b_sep = consume_while(&mut b, |c| !c.is_ascii_alphanumeric());
+
+		// If we ran to the end of either, we are finished with the loop.
+		if a.is_empty() || b.is_empty() {
+			break;
+		}
+
+		// If the separator lengths were different, we are also finished.
+		if a_sep.len() != b_sep.len() {
+			return a_sep.len().cmp(&b_sep.len());
 		}
 
-		// Drop the non-alphanumeric separator.
-		// If only one has a separator, it's a newer version.
-		let a_sep = consume_while(&mut a, |x| !x.is_alphanumeric());
-		let b_sep = consume_while(&mut b, |x| !x.is_alphanumeric());
-		let ordering = (!a_sep.is_empty()).cmp(&!b_sep.is_empty());
+		// Grab the first completely numeric or completely alphabetic segment.
+		// The type of the segment is determined by the first version.
+		let is_num = a[0].is_ascii_digit();
+		let (a_segment, b_segment) = if is_num {
+			(consume_while(&mut a, u8::is_ascii_digit), consume_while(&mut b, u8::is_ascii_digit))
+		} else {
+			(
+				consume_while(&mut a, u8::is_ascii_alphabetic),
+				consume_while(&mut b, u8::is_ascii_alphabetic),
+			)
+		};
+
+		// If the segments are of different types, numeric segments are always newer than alphabetic segments.
+		if b_segment.is_empty() {
+			return if is_num { Ordering::Greater } else { Ordering::Less };
+		}
+
+		let ordering = if is_num {
+			compare_numeric(a_segment, b_segment)
+		} else {
+			a_segment.cmp(b_segment)
+		};
 		if ordering != Ordering::Equal {
 			return ordering;
 		}
 	}
 
-	// If we get here the versions are equal.
-	Ordering::Equal
+	// All segments compared identically, but the separators may have been different.
+	if a.is_empty() && b.is_empty() {
+		return Ordering::Equal;
+	}
+
+	// The final showdown: a remaining alphabetic segment never beats an empty string.
+	// - If `a` is empty and `b` does not start with a letter, `b` is newer.
+	// - If `a` starts with a letter, `b` is newer.
+	// - Otherwise, `a` is newer.
+	let a_first = a.first();
+	let b_first = b.first();
+	if (a_first.is_none() && !b_first.map(u8::is_ascii_alphabetic).unwrap_or(false)) || a_first.map(u8::is_ascii_alphabetic).unwrap_or(false) {
+		Ordering::Less
+	} else {
+		Ordering::Greater
+	}
+}
+
+/// Compare two strings of ASCII digits by numeric value, without limits on their length.
+fn compare_numeric(a: &[u8], b: &[u8]) -> Ordering {
+	let a = trim_leading_zeros(a);
+	let b = trim_leading_zeros(b);
+	a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+fn trim_leading_zeros(input: &[u8]) -> &[u8] {
+	let i = input.iter().position(|&c| c != b'0').unwrap_or(input.len());
+	&input[i..]
+}
+
+/// Compare two full version strings with the same algorithm as `alpm_pkg_vercmp` from libalpm.
+///
+/// The versions are split into epoch, pkgver and pkgrel without any validation.
+/// A missing epoch is treated as `0`.
+/// The pkgrel is only compared if both versions have one.
+///
+/// This gives the same results as the `vercmp` tool from pacman.
+pub fn compare_full_version_string(a: &str, b: &str) -> Ordering {
+	if a == b {
+		return Ordering::Equal;
+	}
+
+	let (a_epoch, a_pkgver, a_pkgrel) = split_evr(a);
+	let (b_epoch, b_pkgver, b_pkgrel) = split_evr(b);
+
+	compare_numeric(a_epoch.as_bytes(), b_epoch.as_bytes())
+		.then_with(|| compare_version_string(a_pkgver, b_pkgver))
+		.then_with(|| match (a_pkgrel, b_pkgrel) {
+			(Some(a), Some(b)) => compare_version_string(a, b),
+			_ => Ordering::Equal,
+		})
+}
+
+/// Split a version string into epoch, pkgver and pkgrel like libalpm does.
+fn split_evr(input: &str) -> (&str, &str, Option<&str>) {
+	let digits = input.bytes().take_while(u8::is_ascii_digit).count();
+	let (epoch, rest) = match input[digits..].strip_prefix(':') {
+		Some(rest) => (&input[..digits], rest),
+		None => ("0", input),
+	};
+	match rest.rfind('-') {
+		Some(i) => (epoch, &rest[..i], Some(&rest[i + 1..])),
+		None => (epoch, rest, None),
+	}
 }
 
 #[cfg(test)]
@@ -104,9 +181,11 @@ mod test {
 		// Extra seperator makes the version greater.
 		assert_compare_version_string("1", "1.", Ordering::Less);
 
-		// Consecutive separators are folded into one separator.
+		// Trailing separators are all the same.
 		assert_compare_version_string("1.", "1..", Ordering::Equal);
-		assert_compare_version_string("1.2", "1..2", Ordering::Equal);
+
+		// A longer separator between segments makes the version greater.
+		assert_compare_version_string("1.2", "1..2", Ordering::Less);
 
 		// Empty components are newer than alphabetical components.
 		assert_compare_version_string("1..a", "1.", Ordering::Less);
@@ -114,4 +193,120 @@ mod test {
 		// Empty components are less than numeric components.
 		assert_compare_version_string("1..", "1..1", Ordering::Less);
 	}
+
+	#[test]
+	fn test_long_numeric_segments() {
+		assert_compare_version_string("20240101123045", "20240101123046", Ordering::Less);
+		assert_compare_version_string("1.20240101123045", "1.9", Ordering::Greater);
+		assert_compare_version_string("99999999999999999999999999", "100000000000000000000000000", Ordering::Less);
+	}
+
+	#[test]
+	fn test_leading_zeros() {
+		assert_compare_version_string("1.01", "1.1", Ordering::Equal);
+		assert_compare_version_string("1.001", "1.01", Ordering::Equal);
+		assert_compare_version_string("1.010", "1.9", Ordering::Greater);
+		assert_compare_version_string("1.0", "1.000", Ordering::Equal);
+	}
+
+	#[test]
+	fn test_special_separators() {
+		// Unlike RPM, libalpm has no special meaning for tildes: they are plain separators.
+		assert_compare_version_string("1.0~rc1", "1.0", Ordering::Greater);
+		assert_compare_version_string("1.0~rc1", "1.0.rc1", Ordering::Equal);
+		assert_compare_version_string("1.0+2", "1.0.2", Ordering::Equal);
+
+		// Non-ASCII characters are separators too.
+		assert_compare_version_string("1.0\u{e9}", "1.0", Ordering::Greater);
+		assert_compare_version_string("1\u{e9}2", "1.2", Ordering::Greater);
+		assert_compare_version_string("1\u{e9}2", "1..2", Ordering::Equal);
+		assert_compare_version_string("1\u{2163}", "1.", Ordering::Equal);
+	}
+
+	/// All test cases from `test/util/vercmptest.sh` in the pacman source tree,
+	/// in the same order and with the same section comments.
+	///
+	/// Like the upstream script, each case is also checked with the arguments swapped.
+	/// Cases that are not in the upstream script belong in the other tests of this module.
+	const PACMAN_VERCMP_TESTS: &[(&str, &str, i32)] = &[
+		// all similar length, no pkgrel
+		("1.5.0", "1.5.0", 0),
+		("1.5.1", "1.5.0", 1),
+		// mixed length
+		("1.5.1", "1.5", 1),
+		// with pkgrel, simple
+		("1.5.0-1", "1.5.0-1", 0),
+		("1.5.0-1", "1.5.0-2", -1),
+		("1.5.0-1", "1.5.1-1", -1),
+		("1.5.0-2", "1.5.1-1", -1),
+		// with pkgrel, mixed lengths
+		("1.5-1", "1.5.1-1", -1),
+		("1.5-2", "1.5.1-1", -1),
+		("1.5-2", "1.5.1-2", -1),
+		// mixed pkgrel inclusion
+		("1.5", "1.5-1", 0),
+		("1.5-1", "1.5", 0),
+		("1.1-1", "1.1", 0),
+		("1.0-1", "1.1", -1),
+		("1.1-1", "1.0", 1),
+		// alphanumeric versions
+		("1.5b-1", "1.5-1", -1),
+		("1.5b", "1.5", -1),
+		("1.5b-1", "1.5", -1),
+		("1.5b", "1.5.1", -1),
+		// from the manpage
+		("1.0a", "1.0alpha", -1),
+		("1.0alpha", "1.0b", -1),
+		("1.0b", "1.0beta", -1),
+		("1.0beta", "1.0rc", -1),
+		("1.0rc", "1.0", -1),
+		// going crazy? alpha-dotted versions
+		("1.5.a", "1.5", 1),
+		("1.5.b", "1.5.a", 1),
+		("1.5.1", "1.5.b", 1),
+		// alpha dots and dashes
+		("1.5.b-1", "1.5.b", 0),
+		("1.5-1", "1.5.b", -1),
+		// same/similar content, differing separators
+		("2.0", "2_0", 0),
+		("2.0_a", "2_0.a", 0),
+		("2.0a", "2.0.a", -1),
+		("2___a", "2_a", 1),
+		// epoch included version comparisons
+		("0:1.0", "0:1.0", 0),
+		("0:1.0", "0:1.1", -1),
+		("1:1.0", "0:1.0", 1),
+		("1:1.0", "0:1.1", 1),
+		("1:1.0", "2:1.1", -1),
+		// epoch + sometimes present pkgrel
+		("1:1.0", "0:1.0-1", 1),
+		("1:1.0-1", "0:1.1-1", 1),
+		// epoch included on one version
+		("0:1.0", "1.0", 0),
+		("0:1.0", "1.1", -1),
+		("0:1.1", "1.0", 1),
+		("1:1.0", "1.0", 1),
+		("1:1.0", "1.1", 1),
+		("1:1.1", "1.1", 1),
+	];
+
+	#[test]
+	fn test_pacman_vercmp_corpus() {
+		// The upstream script runs 92 checks: every case in both directions.
+		assert!(PACMAN_VERCMP_TESTS.len() * 2 == 92);
+		for &(a, b, expected) in PACMAN_VERCMP_TESTS {
+			let expected = expected.cmp(&0);
+			assert!(compare_full_version_string(a, b) == expected, "vercmp {} {}", a, b);
+			assert!(compare_full_version_string(b, a) == expected.reverse(), "vercmp {} {}", b, a);
+		}
+	}
+
+	#[test]
+	fn test_split_evr() {
+		assert!(split_evr("1.0") == ("0", "1.0", None));
+		assert!(split_evr("2:1.0-3") == ("2", "1.0", Some("3")));
+		assert!(split_evr(":1.0-3") == ("", "1.0", Some("3")));
+		assert!(split_evr("a:1.0-3") == ("0", "a:1.0", Some("3")));
+		assert!(split_evr("1.0-rc-3") == ("0", "1.0-rc", Some("3")));
+	}
 }
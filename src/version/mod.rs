@@ -24,5 +24,5 @@
 mod compare;
 mod types;
 
-pub use self::compare::compare_version_string;
-pub use self::types::{PackageVersion, PackageVersionFromStrError, Version, VersionFromStrError};
+pub use self::compare::{compare_full_version_string, compare_version_string};
+pub use self::types::{vercmp, PackageVersion, PackageVersionFromStrError, ParseMode, Version, VersionFromStrError};
@@ -19,6 +19,16 @@ pub struct PackageVersion {
 	pub pkgrel: String,
 }
 
+/// How strictly the pkgver is validated when parsing a version.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParseMode {
+	/// Accept any characters in the pkgver.
+	Lenient,
+
+	/// Reject an empty pkgver, or one that contains `/`, `:`, `-`, whitespace or non-ASCII characters.
+	Strict,
+}
+
 /// Error when parsing a [`Version`] from a string.
 #[derive(Debug, Eq, PartialEq)]
 pub enum VersionFromStrError {
@@ -40,24 +50,13 @@ impl Version {
 	/// Create a new version with epoch, pkgver and optional pkgrel.
 	pub fn new(epoch: i32, pkgver: impl Into<String>, pkgrel: Option<String>) -> Self {
 		let pkgver = pkgver.into();
-		let pkgrel = pkgrel;
-		Self { epoch, pkgver, pkgrel }
-	}
-}
-
-impl PackageVersion {
-	/// Create a new version with epoch, pkgver and pkgrel.
-	pub fn new(epoch: i32, pkgver: impl Into<String>, pkgrel: impl Into<String>) -> Self {
-		let pkgver = pkgver.into();
-		let pkgrel = pkgrel.into();
 		Self { epoch, pkgver, pkgrel }
 	}
-}
-
-impl std::str::FromStr for Version {
-	type Err = VersionFromStrError;
 
-	fn from_str(input: &str) -> Result<Self, Self::Err> {
+	/// Parse a version, validating the pkgver according to the parse mode.
+	///
+	/// The [`FromStr`](std::str::FromStr) implementation uses [`ParseMode::Lenient`].
+	pub fn parse_with(input: &str, mode: ParseMode) -> Result<Self, VersionFromStrError> {
 		// Parse the epoch if it exists.
 		let (epoch, version) = if let Some((epoch, rest)) = partition(input, ':') {
 			let epoch = epoch.parse().map_err(|_| VersionFromStrError::InvalidEpoch)?;
@@ -73,12 +72,7 @@ impl std::str::FromStr for Version {
 			(version, None)
 		};
 
-		// TODO: this is disabled because makepkg generates invalid versions for .so provides under some conditions.
-		// TODO: re-enable when makepkg and the real databases are fixed.
-		// Check the pkgver for invalid characters.
-		// if pkgver.chars().any(|c| !c.is_ascii() || c.is_ascii_whitespace() || c == '/' || c == ':' || c == '-') {
-		// 	return Err(VersionFromStrError::InvalidPkgver);
-		// }
+		check_pkgver(pkgver, mode)?;
 
 		// Check the pkgrel for invalid characters.
 		if let Some(pkgrel) = pkgrel {
@@ -89,19 +83,99 @@ impl std::str::FromStr for Version {
 
 		Ok(Self::new(epoch, pkgver, pkgrel.map(|x| x.into())))
 	}
-}
 
-impl std::str::FromStr for PackageVersion {
-	type Err = PackageVersionFromStrError;
+	/// Check the pkgver of an already parsed version according to the parse mode.
+	pub fn validate(&self, mode: ParseMode) -> Result<(), VersionFromStrError> {
+		check_pkgver(&self.pkgver, mode)
+	}
+}
 
-	fn from_str(input: &str) -> Result<Self, Self::Err> {
-		let Version { epoch, pkgver, pkgrel } = input.parse()?;
+impl PackageVersion {
+	/// Parse a package version, validating the pkgver according to the parse mode.
+	///
+	/// The [`FromStr`](std::str::FromStr) implementation uses [`ParseMode::Lenient`].
+	pub fn parse_with(input: &str, mode: ParseMode) -> Result<Self, PackageVersionFromStrError> {
+		let Version { epoch, pkgver, pkgrel } = Version::parse_with(input, mode)?;
 		if let Some(pkgrel) = pkgrel {
 			Ok(PackageVersion { epoch, pkgver, pkgrel })
 		} else {
 			Err(PackageVersionFromStrError::MissingPkgRel)
 		}
 	}
+
+	/// Check the pkgver of an already parsed version according to the parse mode.
+	pub fn validate(&self, mode: ParseMode) -> Result<(), PackageVersionFromStrError> {
+		Ok(check_pkgver(&self.pkgver, mode)?)
+	}
+}
+
+/// Check a pkgver for invalid characters according to the parse mode.
+fn check_pkgver(pkgver: &str, mode: ParseMode) -> Result<(), VersionFromStrError> {
+	match mode {
+		ParseMode::Lenient => Ok(()),
+		ParseMode::Strict => {
+			if pkgver.is_empty()
+				|| pkgver
+					.chars()
+					.any(|c| !c.is_ascii() || c.is_ascii_whitespace() || c == '/' || c == ':' || c == '-')
+			{
+				Err(VersionFromStrError::InvalidPkgver)
+			} else {
+				Ok(())
+			}
+		},
+	}
+}
+
+/// Compare two versions the way libalpm does when checking dependencies.
+///
+/// Unlike the [`Ord`] implementation of [`Version`],
+/// the pkgrel is only compared if both versions have one.
+/// So `1.0` is considered equal to `1.0-2`, just like `vercmp` from pacman reports.
+pub fn vercmp(a: &Version, b: &Version) -> Ordering {
+	match a.epoch.cmp(&b.epoch) {
+		Ordering::Equal => (),
+		x => return x,
+	}
+	match compare_version_string(&a.pkgver, &b.pkgver) {
+		Ordering::Equal => (),
+		x => return x,
+	}
+	match (&a.pkgrel, &b.pkgrel) {
+		(Some(a), Some(b)) => compare_version_string(a, b),
+		_ => Ordering::Equal,
+	}
+}
+
+impl PackageVersion {
+	/// Create a new version with epoch, pkgver and pkgrel.
+	pub fn new(epoch: i32, pkgver: impl Into<String>, pkgrel: impl Into<String>) -> Self {
+		let pkgver = pkgver.into();
+		let pkgrel = pkgrel.into();
+		Self { epoch, pkgver, pkgrel }
+	}
+}
+
+impl Default for ParseMode {
+	fn default() -> Self {
+		Self::Lenient
+	}
+}
+
+impl std::str::FromStr for Version {
+	type Err = VersionFromStrError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		Self::parse_with(input, ParseMode::Lenient)
+	}
+}
+
+impl std::str::FromStr for PackageVersion {
+	type Err = PackageVersionFromStrError;
+
+	fn from_str(input: &str) -> Result<Self, Self::Err> {
+		Self::parse_with(input, ParseMode::Lenient)
+	}
 }
 
 impl<'de> serde::Deserialize<'de> for Version {
@@ -142,6 +216,18 @@ impl<'de> serde::Deserialize<'de> for PackageVersion {
 	}
 }
 
+impl serde::Serialize for Version {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl serde::Serialize for PackageVersion {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
 impl std::error::Error for VersionFromStrError {}
 impl std::error::Error for PackageVersionFromStrError {}
 
@@ -166,6 +252,18 @@ impl std::fmt::Display for PackageVersionFromStrError {
 	}
 }
 
+impl From<PackageVersion> for Version {
+	fn from(other: PackageVersion) -> Self {
+		Self::new(other.epoch, other.pkgver, Some(other.pkgrel))
+	}
+}
+
+impl From<&PackageVersion> for Version {
+	fn from(other: &PackageVersion) -> Self {
+		Self::new(other.epoch, other.pkgver.clone(), Some(other.pkgrel.clone()))
+	}
+}
+
 impl From<VersionFromStrError> for PackageVersionFromStrError {
 	fn from(other: VersionFromStrError) -> Self {
 		match other {
@@ -176,7 +274,7 @@ impl From<VersionFromStrError> for PackageVersionFromStrError {
 	}
 }
 
-impl<'a> Ord for Version {
+impl Ord for Version {
 	fn cmp(&self, other: &Version) -> Ordering {
 		match self.epoch.cmp(&other.epoch) {
 			Ordering::Equal => (),
@@ -190,12 +288,12 @@ impl<'a> Ord for Version {
 			(None, None) => Ordering::Equal,
 			(None, Some(_)) => Ordering::Less,
 			(Some(_), None) => Ordering::Greater,
-			(Some(a), Some(b)) => compare_version_string(&a, &b),
+			(Some(a), Some(b)) => compare_version_string(a, b),
 		}
 	}
 }
 
-impl<'a> Ord for PackageVersion {
+impl Ord for PackageVersion {
 	fn cmp(&self, other: &PackageVersion) -> Ordering {
 		match self.epoch.cmp(&other.epoch) {
 			Ordering::Equal => (),
@@ -269,6 +367,34 @@ mod test {
 		assert!(PackageVersion::new(0, "1", "2") < PackageVersion::new(1, "0", "1"));
 	}
 
+	#[test]
+	fn test_vercmp() {
+		let parse = |x: &str| -> Version { x.parse().unwrap() };
+		assert!(vercmp(&parse("1.0"), &parse("1.0-2")) == Ordering::Equal);
+		assert!(vercmp(&parse("1.0-2"), &parse("1.0")) == Ordering::Equal);
+		assert!(vercmp(&parse("1.0-1"), &parse("1.0-2")) == Ordering::Less);
+		assert!(vercmp(&parse("1.1"), &parse("1.0-2")) == Ordering::Greater);
+		assert!(vercmp(&parse("1:0.1"), &parse("2.0")) == Ordering::Greater);
+
+		// The Ord implementation is stricter.
+		assert!(parse("1.0") < parse("1.0-2"));
+	}
+
+	#[test]
+	fn test_display() {
+		assert!(Version::new(0, "1.2.3", None).to_string() == "1.2.3");
+		assert!(Version::new(2, "1.2.3", None).to_string() == "2:1.2.3");
+		assert!(Version::new(0, "1.2.3", Some("4.1".into())).to_string() == "1.2.3-4.1");
+		assert!(Version::new(2, "1.2.3", Some("4".into())).to_string() == "2:1.2.3-4");
+		assert!(PackageVersion::new(0, "1.2.3", "4").to_string() == "1.2.3-4");
+		assert!(PackageVersion::new(1, "1.2.3", "4").to_string() == "1:1.2.3-4");
+
+		for input in ["1.0", "1:1.0", "1.0-2", "1:1.0-2", "0.7-1.1"] {
+			let version: Version = input.parse().unwrap();
+			assert!(version.to_string() == input);
+		}
+	}
+
 	#[test]
 	fn test_parse() {
 		let parse = |x: &str| -> Result<PackageVersion, _> { x.parse() };
@@ -278,9 +404,29 @@ mod test {
 		assert!(parse("5:1.2.3-4") == Ok(PackageVersion::new(5, "1.2.3", "4")));
 
 		assert!(parse("aap:1.2.3-4") == Err(PackageVersionFromStrError::InvalidEpoch));
-		// TODO: checking pkgver was disabled to work around makepkg bug
-		// assert!(parse("aap-noot-1") == Err(PackageVersionFromStrError::InvalidPkgver));
+		// Lenient parsing accepts invalid pkgvers, because makepkg sometimes generates them.
+		assert!(parse("aap-noot-1") == Ok(PackageVersion::new(0, "aap-noot", "1")));
 		assert!(parse("1.2.3-foo") == Err(PackageVersionFromStrError::InvalidPkgrel));
 		assert!(parse("1.2.3") == Err(PackageVersionFromStrError::MissingPkgRel));
 	}
+
+	#[test]
+	fn test_parse_strict() {
+		let parse = |x: &str| PackageVersion::parse_with(x, ParseMode::Strict);
+
+		assert!(parse("1.2.3-4") == Ok(PackageVersion::new(0, "1.2.3", "4")));
+		assert!(parse("5:1.2.3_rc+git.abc-4") == Ok(PackageVersion::new(5, "1.2.3_rc+git.abc", "4")));
+		assert!(parse("aap-noot-1") == Err(PackageVersionFromStrError::InvalidPkgver));
+		assert!(parse("1:2:3-1") == Err(PackageVersionFromStrError::InvalidPkgver));
+		assert!(parse("1/2-1") == Err(PackageVersionFromStrError::InvalidPkgver));
+		assert!(parse("1 2-1") == Err(PackageVersionFromStrError::InvalidPkgver));
+		assert!(parse("1.\u{e9}-1") == Err(PackageVersionFromStrError::InvalidPkgver));
+		assert!(parse("-1") == Err(PackageVersionFromStrError::InvalidPkgver));
+		assert!(parse("1.2.3-foo") == Err(PackageVersionFromStrError::InvalidPkgrel));
+
+		assert!(Version::parse_with("1.0", ParseMode::Strict) == Ok(Version::new(0, "1.0", None)));
+		assert!(Version::parse_with("1\t0", ParseMode::Strict) == Err(VersionFromStrError::InvalidPkgver));
+		assert!(Version::new(0, "1 0", None).validate(ParseMode::Lenient) == Ok(()));
+		assert!(Version::new(0, "1 0", None).validate(ParseMode::Strict) == Err(VersionFromStrError::InvalidPkgver));
+	}
 }